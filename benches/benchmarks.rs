@@ -1,5 +1,5 @@
 use bytes::*;
-use criterion::{criterion_group, criterion_main, Criterion};
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
 use hl7_mllp_codec::MllpCodec;
 use tokio_util::codec::{Decoder, Encoder};
 
@@ -43,11 +43,161 @@ fn bench_simple_encode(c: &mut Criterion) {
     });
 }
 
+fn bench_encode_unchecked(c: &mut Criterion) {
+    // this compares encode with encode_unchecked into a buffer already reserved for the frame
+    let mut codec = MllpCodec::new();
+    let payload = BytesMut::from(get_hl7_message());
+    let mut buf = BytesMut::with_capacity(codec.framed_len(payload.len()));
+
+    c.bench_function("Encode Real Message", |b| {
+        b.iter(|| {
+            buf.clear();
+            let _response = codec.encode(payload.clone(), &mut buf);
+        })
+    });
+
+    c.bench_function("Encode Unchecked Real Message", |b| {
+        b.iter(|| {
+            buf.clear();
+            let _response = codec.encode_unchecked(payload.clone(), &mut buf);
+        })
+    });
+}
+
+fn bench_reframe(c: &mut Criterion) {
+    // this compares re-wrapping a stream of decoded payloads, as a pass-through proxy would
+    let mut stream = BytesMut::new();
+    for _ in 0..100 {
+        stream.extend_from_slice(format!("\x0B{}\x1C\x0D", get_hl7_message()).as_bytes());
+    }
+    let mut codec = MllpCodec::new();
+    let payloads = codec.decode_all(&mut stream.clone()).unwrap();
+    let payloads: Vec<Bytes> = payloads.into_iter().map(|p| p.freeze()).collect();
+    let mut dst = BytesMut::with_capacity(stream.len());
+
+    c.bench_function("Copy + Encode Stream", |b| {
+        b.iter(|| {
+            dst.clear();
+            for payload in &payloads {
+                let _ = codec.encode(BytesMut::from(&payload[..]), &mut dst);
+            }
+        })
+    });
+
+    c.bench_function("Reframe Stream", |b| {
+        b.iter(|| {
+            dst.clear();
+            for payload in &payloads {
+                let _ = codec.reframe(payload, &mut dst);
+            }
+        })
+    });
+}
+
+fn bench_decode_into_buf(c: &mut Criterion) {
+    // this compares the default (allocating) decode with decoding into a reused buffer, over 100k frames.  Each frame is
+    // read separately, as off a socket, since the compliant decoder takes pipelined frames as one.
+    const FRAMES: usize = 100_000;
+    let msg = BytesMut::from(format!("\x0B{}\x1C\x0D", get_hl7_message()).as_str());
+    let mut codec = MllpCodec::new();
+
+    c.bench_function("Decode 100k Frames", |b| {
+        b.iter(|| {
+            for _ in 0..FRAMES {
+                let _ = codec.decode(&mut msg.clone());
+            }
+        })
+    });
+
+    c.bench_function("Decode 100k Frames Into Buf", |b| {
+        let mut out = Vec::new();
+        b.iter(|| {
+            for _ in 0..FRAMES {
+                let _ = codec.decode_into_buf(&mut msg.clone(), &mut out);
+            }
+        })
+    });
+}
+
+fn bench_decode_all(c: &mut Criterion) {
+    // this checks the capacity pre-pass in decode_all over a large batch, against decode_all_into, which runs the same
+    // decode loop without reserving, growing its Vec as it goes
+    let mut stream = BytesMut::new();
+    for _ in 0..1000 {
+        stream.extend_from_slice(format!("\x0B{}\x1C\x0D", get_hl7_message()).as_bytes());
+    }
+    let mut codec = MllpCodec::new();
+
+    c.bench_function("Decode All 1000 Frames", |b| {
+        b.iter(|| {
+            let _frames = codec.decode_all(&mut stream.clone());
+        })
+    });
+
+    c.bench_function("Decode All 1000 Frames (unreserved)", |b| {
+        b.iter(|| {
+            let mut frames = Vec::new();
+            let _ = codec.decode_all_into(&mut stream.clone(), &mut frames);
+        })
+    });
+}
+
+fn bench_late_header(c: &mut Criterion) {
+    // this decodes a frame whose header comes after 1MB of junk, comparing the codec's header search (which uses
+    // memchr with the `memchr` feature: run with and without it) against a plain scalar scan.  Both get a fresh copy of
+    // the buffer (copied and dropped outside the timed section), and the frame past the header is tiny, so what's timed
+    // is the search.
+    let mut msg = BytesMut::from(&vec![b'x'; 1024 * 1024][..]);
+    msg.extend_from_slice(b"\x0B\x06\x1C\x0D");
+    let mut codec = MllpCodec::new();
+
+    c.bench_function("Scalar Header Scan 1MB", |b| {
+        b.iter_batched_ref(
+            || msg.clone(),
+            |buf| buf.iter().position(|b| *b == 0x0B),
+            BatchSize::LargeInput,
+        )
+    });
+
+    c.bench_function("Decode Late Header 1MB", |b| {
+        b.iter_batched_ref(
+            || msg.clone(),
+            |buf| codec.decode(buf),
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+fn bench_large_frame_footer(c: &mut Criterion) {
+    // this decodes a 1MB frame searching forwards for its footer (as decode_all does), with a few stray 0x1C bytes in
+    // its content.  With the `memchr` feature the search jumps between 0x1C bytes rather than checking every position.
+    let mut content = vec![b'x'; 1024 * 1024];
+    for i in (0..content.len()).step_by(256 * 1024) {
+        content[i] = 0x1C;
+    }
+    let mut msg = BytesMut::from(&b"\x0B"[..]);
+    msg.extend_from_slice(&content);
+    msg.extend_from_slice(b"\x1C\x0D");
+    let mut codec = MllpCodec::new();
+
+    c.bench_function("Decode All 1MB Frame", |b| {
+        b.iter(|| {
+            let _frames = codec.decode_all(&mut msg.clone());
+        })
+    });
+}
+
 criterion_group!(
     benches,
     bench_simple_decode,
     bench_real_message_decode,
-    bench_simple_encode
+    bench_simple_encode,
+    bench_encode_unchecked,
+    bench_reframe,
+    bench_decode_into_buf,
+    bench_decode_all,
+    bench_late_header,
+    bench_large_frame_footer
 );
 criterion_main!(benches);
 
@@ -0,0 +1,339 @@
+//! Building acknowledgements (ACKs) for inbound messages, for listeners that don't want to pull in a full HL7 library
+//! just to say "got it".
+//!
+//! HL7 has two acknowledgment modes.  In _original_ mode (MSH-15 and MSH-16 both empty) the ACK reports whether the
+//! message was processed, using the application codes `AA`/`AE`/`AR`.  In _enhanced_ mode (either of them populated)
+//! the listener first sends a commit (accept) ACK saying the message was safely received, using `CA`/`CE`/`CR`, and
+//! any application ACK follows separately.  [build_ack] picks the right codes for the message it's acknowledging.
+
+use crate::hl7::{accept_ack_type, application_ack_type, msh_field};
+use bytes::{BufMut, BytesMut};
+
+/// The outcome being acknowledged, independent of the acknowledgment mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AckCode {
+    /// The message was accepted (`AA`, or `CA` in enhanced mode).
+    Accept,
+    /// The message was received but couldn't be handled (`AE`, or `CE` in enhanced mode).
+    Error,
+    /// The message was rejected outright (`AR`, or `CR` in enhanced mode).
+    Reject,
+}
+
+/// The acknowledgment mode a message requests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AckMode {
+    /// MSH-15 and MSH-16 are empty, a single application ACK is expected.
+    Original,
+    /// MSH-15 and/or MSH-16 is populated, a commit ACK is expected (possibly followed by an application ACK).
+    Enhanced,
+}
+
+/// Whether a decoded frame is a message in its own right or an acknowledgment of one, see [classify].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameKind {
+    /// A message, which needs acknowledging.
+    Message,
+    /// An acknowledgment (it has an MSA segment) of a message sent the other way.
+    Ack,
+}
+
+/// Classifies a decoded frame as a message or an ACK, by the presence of an MSA segment.  This is what lets a single
+/// connection carrying messages in both directions (as some bidirectional gateways do) route each frame to the right
+/// place.
+/// Example:
+/// ```
+/// use hl7_mllp_codec::ack::{classify, FrameKind};
+///
+/// assert_eq!(classify(b"MSH|^~\\&|LAB||||||ORU^R01|1|P|2.3\rPID|1\r"), FrameKind::Message);
+/// assert_eq!(classify(b"MSH|^~\\&|EMR||||||ACK^R01|1|P|2.3\rMSA|AA|1\r"), FrameKind::Ack);
+/// ```
+pub fn classify(frame: &[u8]) -> FrameKind {
+    let has_msa = frame
+        .split(|b| *b == b'\r' || *b == b'\n')
+        .any(|segment| segment.starts_with(b"MSA") && segment.len() > 3);
+    if has_msa {
+        FrameKind::Ack
+    } else {
+        FrameKind::Message
+    }
+}
+
+/// Returns the acknowledgment mode `frame` requests, based on MSH-15/MSH-16.
+pub fn ack_mode(frame: &[u8]) -> AckMode {
+    if accept_ack_type(frame).is_some() || application_ack_type(frame).is_some() {
+        AckMode::Enhanced
+    } else {
+        AckMode::Original
+    }
+}
+
+impl AckCode {
+    /// The MSA-1 acknowledgment code for this outcome in the given mode.
+    pub fn code(self, mode: AckMode) -> &'static str {
+        match (mode, self) {
+            (AckMode::Original, AckCode::Accept) => "AA",
+            (AckMode::Original, AckCode::Error) => "AE",
+            (AckMode::Original, AckCode::Reject) => "AR",
+            (AckMode::Enhanced, AckCode::Accept) => "CA",
+            (AckMode::Enhanced, AckCode::Error) => "CE",
+            (AckMode::Enhanced, AckCode::Reject) => "CR",
+        }
+    }
+}
+
+/// Builds an ACK (the unframed HL7 payload) for the message in `frame`, in the acknowledgment mode the message requests.
+///
+/// The ACK's MSH swaps the sending and receiving application/facility, echoes the message control ID, processing ID
+/// and version, and uses the same separators as the inbound message.  MSA-2 is the inbound MSH-10, exactly as sent.
+/// MSH-7 is left empty, as this crate doesn't know your clock.  Returns `None` if `frame` has no MSH segment.
+/// Example:
+/// ```
+/// use hl7_mllp_codec::ack::{build_ack, AckCode};
+///
+/// let frame = b"MSH|^~\\&|LAB|MYFAC|EMR|HOSP|201411130917||ORU^R01|3216598|P|2.3\r";
+/// let ack = build_ack(frame, AckCode::Accept).unwrap();
+/// assert_eq!(&ack[..], &b"MSH|^~\\&|EMR|HOSP|LAB|MYFAC|||ACK^R01|3216598|P|2.3\rMSA|AA|3216598\r"[..]);
+/// ```
+pub fn build_ack(frame: &[u8], code: AckCode) -> Option<BytesMut> {
+    let separator = msh_field(frame, 1)?;
+    let encoding = msh_field(frame, 2).unwrap_or("^~\\&");
+    let component_separator = encoding.chars().next().unwrap_or('^');
+    let field = |n| msh_field(frame, n).unwrap_or("");
+
+    let trigger = field(9).split(component_separator).nth(1).unwrap_or("");
+    let message_type = if trigger.is_empty() {
+        "ACK".to_owned()
+    } else {
+        format!("ACK{}{}", component_separator, trigger)
+    };
+    let control_id = field(10);
+
+    let msh = [
+        "MSH",
+        encoding,
+        field(5),
+        field(6),
+        field(3),
+        field(4),
+        "",
+        "",
+        &message_type,
+        control_id,
+        field(11),
+        field(12),
+    ]
+    .join(separator);
+    let msa = ["MSA", code.code(ack_mode(frame)), control_id].join(separator);
+
+    let mut ack = BytesMut::with_capacity(msh.len() + msa.len() + 2);
+    ack.put_slice(msh.as_bytes());
+    ack.put_u8(b'\r');
+    ack.put_slice(msa.as_bytes());
+    ack.put_u8(b'\r');
+    Some(ack)
+}
+
+/// The interesting parts of an ERR segment from a NAK, see [parse_err_segments].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ErrInfo<'a> {
+    /// The HL7 error code (the identifier of ERR-3, eg `207` for an application internal error).
+    pub code: Option<&'a str>,
+    /// The severity (ERR-4), `E`rror, `W`arning, `I`nformation or `F`atal.
+    pub severity: Option<&'a str>,
+    /// Where the error is, as the raw (component separated) location, eg `PID^1^3`.
+    pub location: Option<&'a str>,
+}
+
+/// Returns the ERR segments of an ACK/NAK, for senders wanting more detail on a rejection than the MSA code.
+///
+/// Both the v2.5+ layout (location in ERR-2, code in ERR-3, severity in ERR-4) and the older v2.3/v2.4 layout (code
+/// and location combined in ERR-1) are understood, preferring the newer fields where both are present.
+/// Example:
+/// ```
+/// use hl7_mllp_codec::ack::parse_err_segments;
+///
+/// let nak = b"MSH|^~\\&|EMR||||||ACK|1|P|2.5\rMSA|AE|1\rERR||PID^1^3|101^Required field missing^HL70357|E\r";
+/// let errors = parse_err_segments(nak);
+/// assert_eq!(errors[0].code, Some("101"));
+/// assert_eq!(errors[0].location, Some("PID^1^3"));
+/// ```
+pub fn parse_err_segments(frame: &[u8]) -> Vec<ErrInfo<'_>> {
+    let separator = msh_field(frame, 1)
+        .and_then(|s| s.chars().next())
+        .unwrap_or('|');
+    let encoding = msh_field(frame, 2).unwrap_or("^~\\&");
+    let component_separator = encoding.chars().next().unwrap_or('^');
+    let subcomponent_separator = encoding.chars().nth(3).unwrap_or('&');
+
+    fn non_empty(value: Option<&str>) -> Option<&str> {
+        value.filter(|v| !v.is_empty())
+    }
+
+    frame
+        .split(|b| *b == b'\r' || *b == b'\n')
+        .filter_map(|segment| std::str::from_utf8(segment).ok())
+        .filter(|segment| segment.starts_with("ERR") && segment.len() > 3)
+        .map(|segment| {
+            let fields: Vec<&str> = segment.split(separator).collect();
+            let field = |n: usize| non_empty(fields.get(n).copied());
+
+            // ERR-1 (pre v2.5) is segment^sequence^field^code&text
+            let legacy = field(1).map(|v| v.split(component_separator).collect::<Vec<_>>());
+            let legacy_code = legacy
+                .as_ref()
+                .and_then(|components| components.get(3))
+                .and_then(|code| code.split(subcomponent_separator).next());
+            let legacy_location =
+                field(1).map(|v| match v.match_indices(component_separator).nth(2) {
+                    Some((i, _)) => &v[..i],
+                    None => v,
+                });
+
+            ErrInfo {
+                code: non_empty(field(3).and_then(|v| v.split(component_separator).next()))
+                    .or(non_empty(legacy_code)),
+                severity: field(4),
+                location: field(2).or(non_empty(legacy_location)),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ORIGINAL: &[u8] =
+        b"MSH|^~\\&|LAB|MYFAC|EMR|HOSP|201411130917||ORU^R01|3216598|P|2.3\rPID|1\r";
+    const ENHANCED: &[u8] =
+        b"MSH|^~\\&|LAB|MYFAC|EMR|HOSP|201411130917||ORU^R01|3216598|P|2.5|||AL|NE\rPID|1\r";
+
+    #[test]
+    fn original_mode_uses_application_codes() {
+        assert_eq!(ack_mode(ORIGINAL), AckMode::Original);
+
+        let ack = build_ack(ORIGINAL, AckCode::Accept).unwrap();
+        assert_eq!(
+            &ack[..],
+            &b"MSH|^~\\&|EMR|HOSP|LAB|MYFAC|||ACK^R01|3216598|P|2.3\rMSA|AA|3216598\r"[..]
+        );
+        let ack = build_ack(ORIGINAL, AckCode::Reject).unwrap();
+        assert!(ack.ends_with(b"\rMSA|AR|3216598\r"));
+    }
+
+    #[test]
+    fn enhanced_mode_uses_commit_codes() {
+        assert_eq!(ack_mode(ENHANCED), AckMode::Enhanced);
+
+        let ack = build_ack(ENHANCED, AckCode::Accept).unwrap();
+        assert_eq!(
+            &ack[..],
+            &b"MSH|^~\\&|EMR|HOSP|LAB|MYFAC|||ACK^R01|3216598|P|2.5\rMSA|CA|3216598\r"[..]
+        );
+        let ack = build_ack(ENHANCED, AckCode::Error).unwrap();
+        assert!(ack.ends_with(b"\rMSA|CE|3216598\r"));
+
+        // only MSH-16 populated is still enhanced mode
+        let frame = b"MSH|^~\\&|LAB|MYFAC|||||ORU^R01|1|P|2.5||||AL\r";
+        assert_eq!(ack_mode(frame), AckMode::Enhanced);
+    }
+
+    #[test]
+    fn honours_declared_separators() {
+        let frame = b"MSH#*~\\&#LAB##EMR####ADT*A01#ID1#P#2.3\r";
+        let ack = build_ack(frame, AckCode::Accept).unwrap();
+        assert_eq!(
+            &ack[..],
+            &b"MSH#*~\\&#EMR##LAB####ACK*A01#ID1#P#2.3\rMSA#AA#ID1\r"[..]
+        );
+    }
+
+    #[test]
+    fn msa_echoes_control_id() {
+        fn msa_2(ack: &[u8], separator: u8) -> &[u8] {
+            let msa = ack
+                .split(|b| *b == b'\r')
+                .find(|s| s.starts_with(b"MSA"))
+                .unwrap();
+            msa.split(|b| *b == separator).nth(2).unwrap()
+        }
+
+        for (frame, separator, control_id) in [
+            (ORIGINAL, b'|', &b"3216598"[..]),
+            (
+                &b"MSH|^~\\&|LAB||||||ORU^R01|ORD 0001-A.7|P|2.3\r"[..],
+                b'|',
+                b"ORD 0001-A.7",
+            ),
+            // a control ID holding what's the field separator elsewhere
+            (
+                &b"MSH#*~\\&#LAB######ADT*A01#ID|1#P#2.3\r"[..],
+                b'#',
+                b"ID|1",
+            ),
+            (&b"MSH|^~\\&|LAB||||||ORU^R01||P|2.3\r"[..], b'|', b""),
+        ] {
+            let ack = build_ack(frame, AckCode::Accept).unwrap();
+            assert_eq!(msa_2(&ack, separator), control_id);
+        }
+    }
+
+    #[test]
+    fn classifies_by_msa() {
+        assert_eq!(classify(ORIGINAL), FrameKind::Message);
+        let ack = build_ack(ORIGINAL, AckCode::Accept).unwrap();
+        assert_eq!(classify(&ack), FrameKind::Ack);
+        // an MSA-ish prefix in some other segment's content doesn't count
+        assert_eq!(classify(b"MSH|^~\\&\rNTE|MSA|1\r"), FrameKind::Message);
+    }
+
+    #[test]
+    fn parses_single_err_segment() {
+        let nak = b"MSH|^~\\&|EMR||||||ACK^R01|1|P|2.5\rMSA|AE|1\rERR||PID^1^3|101^Required field missing^HL70357|E\r";
+        assert_eq!(
+            parse_err_segments(nak),
+            vec![ErrInfo {
+                code: Some("101"),
+                severity: Some("E"),
+                location: Some("PID^1^3"),
+            }]
+        );
+    }
+
+    #[test]
+    fn parses_multiple_err_segments() {
+        let nak = b"MSH|^~\\&|EMR||||||ACK^R01|1|P|2.5\rMSA|AR|1\r\
+            ERR||PID^1^5|102^Data type error^HL70357|E\r\
+            ERR||OBX^2|207|W\r\
+            ERR|PV1^1^3^103&Table value not found\r";
+        let errors = parse_err_segments(nak);
+        assert_eq!(errors.len(), 3);
+        assert_eq!(errors[0].code, Some("102"));
+        assert_eq!(
+            (errors[1].code, errors[1].severity, errors[1].location),
+            (Some("207"), Some("W"), Some("OBX^2"))
+        );
+        // the v2.3 style combined code and location
+        assert_eq!(
+            errors[2],
+            ErrInfo {
+                code: Some("103"),
+                severity: None,
+                location: Some("PV1^1^3"),
+            }
+        );
+    }
+
+    #[test]
+    fn no_err_segments() {
+        assert!(parse_err_segments(ORIGINAL).is_empty());
+        let ack = build_ack(ORIGINAL, AckCode::Accept).unwrap();
+        assert!(parse_err_segments(&ack).is_empty());
+    }
+
+    #[test]
+    fn no_msh_no_ack() {
+        assert_eq!(build_ack(b"PID|1\r", AckCode::Accept), None);
+    }
+}
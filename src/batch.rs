@@ -0,0 +1,125 @@
+//! Building and splitting HL7 batch files, which bundle several messages into one transmission (and so one MLLP
+//! frame) between `FHS`/`BHS` header and `BTS`/`FTS` trailer segments.
+//!
+//! Only single-batch files are built, which is all most systems exchange, but [split_batch] copes with any number of
+//! batches in the file.
+
+use crate::hl7::msh_field;
+use bytes::{BufMut, BytesMut};
+
+/// The batch-level segments, which belong to no message
+const BATCH_SEGMENTS: [&[u8]; 4] = [b"FHS", b"BHS", b"BTS", b"FTS"];
+
+/// Wraps `messages` in a batch file: a file header (`FHS`) and batch header (`BHS`), the messages, then the batch
+/// trailer (`BTS`) carrying the message count and the file trailer (`FTS`) carrying the batch count.  The headers use
+/// the delimiters declared by the first message, or the standard `|^~\&` if there are none.
+/// Example:
+/// ```
+/// use hl7_mllp_codec::batch::build_batch;
+///
+/// let batch = build_batch(&[b"MSH|^~\\&|LAB||||||ORU^R01|1|P|2.3\rOBX|1\r", b"MSH|^~\\&|LAB||||||ORU^R01|2|P|2.3\r"]);
+/// assert!(batch.starts_with(b"FHS|^~\\&\rBHS|^~\\&\rMSH"));
+/// assert!(batch.ends_with(b"BTS|2\rFTS|1\r"));
+/// ```
+pub fn build_batch(messages: &[&[u8]]) -> BytesMut {
+    let first = messages.first().copied().unwrap_or_default();
+    let separator = msh_field(first, 1).unwrap_or("|");
+    let encoding = msh_field(first, 2).unwrap_or("^~\\&");
+
+    let length = messages.iter().map(|m| m.len() + 1).sum::<usize>() + 64;
+    let mut batch = BytesMut::with_capacity(length);
+    for header in ["FHS", "BHS"] {
+        batch.put_slice([header, encoding].join(separator).as_bytes());
+        batch.put_u8(b'\r');
+    }
+    for message in messages {
+        batch.put_slice(message);
+        if !message.ends_with(b"\r") {
+            batch.put_u8(b'\r');
+        }
+    }
+    batch.put_slice(
+        ["BTS", &messages.len().to_string()]
+            .join(separator)
+            .as_bytes(),
+    );
+    batch.put_u8(b'\r');
+    batch.put_slice(["FTS", "1"].join(separator).as_bytes());
+    batch.put_u8(b'\r');
+    batch
+}
+
+/// Splits a batch file (or a lone message) into its messages, each running from its MSH segment up to the next
+/// message or batch segment, segment terminators included.  The batch headers and trailers are dropped.
+/// Example:
+/// ```
+/// use hl7_mllp_codec::batch::split_batch;
+///
+/// let batch = b"FHS|^~\\&\rBHS|^~\\&\rMSH|^~\\&|LAB|1\rOBX|1\rMSH|^~\\&|LAB|2\rBTS|2\rFTS|1\r";
+/// assert_eq!(split_batch(batch), vec![&b"MSH|^~\\&|LAB|1\rOBX|1\r"[..], &b"MSH|^~\\&|LAB|2\r"[..]]);
+/// ```
+pub fn split_batch(batch: &[u8]) -> Vec<&[u8]> {
+    let mut messages = Vec::new();
+    let mut message_start = None;
+    let mut segment_start = 0;
+    while segment_start < batch.len() {
+        let segment_end = batch[segment_start..]
+            .iter()
+            .position(|b| *b == b'\r' || *b == b'\n')
+            .map_or(batch.len(), |i| segment_start + i + 1);
+        let segment = &batch[segment_start..segment_end];
+
+        let is_msh = segment.starts_with(b"MSH");
+        if is_msh || BATCH_SEGMENTS.iter().any(|id| segment.starts_with(id)) {
+            if let Some(start) = message_start.take() {
+                messages.push(&batch[start..segment_start]);
+            }
+            if is_msh {
+                message_start = Some(segment_start);
+            }
+        }
+        segment_start = segment_end;
+    }
+    if let Some(start) = message_start {
+        messages.push(&batch[start..]);
+    }
+    messages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MESSAGES: [&[u8]; 3] = [
+        b"MSH|^~\\&|LAB||||||ORU^R01|1|P|2.3\rPID|1\rOBX|1|NM|WBC||10.1\r",
+        b"MSH|^~\\&|LAB||||||ORU^R01|2|P|2.3\rPID|2\r",
+        b"MSH|^~\\&|LAB||||||ORU^R01|3|P|2.3\rPID|3\rOBX|1|NM|RBC||3.2\r",
+    ];
+
+    #[test]
+    fn batch_trailers_count_messages() {
+        let batch = build_batch(&MESSAGES);
+        assert!(batch.starts_with(b"FHS|^~\\&\rBHS|^~\\&\rMSH|^~\\&|LAB||||||ORU^R01|1|"));
+        assert!(batch.ends_with(b"OBX|1|NM|RBC||3.2\rBTS|3\rFTS|1\r"));
+    }
+
+    #[test]
+    fn split_undoes_build() {
+        assert_eq!(split_batch(&build_batch(&MESSAGES)), MESSAGES);
+    }
+
+    #[test]
+    fn honours_declared_delimiters() {
+        let batch = build_batch(&[b"MSH#$%*@#LAB\r"]);
+        assert_eq!(
+            &batch[..],
+            b"FHS#$%*@\rBHS#$%*@\rMSH#$%*@#LAB\rBTS#1\rFTS#1\r"
+        );
+    }
+
+    #[test]
+    fn lone_message_splits_to_itself() {
+        assert_eq!(split_batch(MESSAGES[1]), vec![MESSAGES[1]]);
+        assert!(split_batch(b"FHS|^~\\&\rFTS|0\r").is_empty());
+    }
+}
@@ -0,0 +1,45 @@
+use crate::{FrameReader, MllpCodec, MllpError};
+use bytes::BytesMut;
+use std::io::Read;
+use std::path::Path;
+
+/// Splits an MLLP capture (eg a file of raw bytes recorded off the wire) into one file per frame, for operations teams
+/// picking apart a misbehaving feed.
+///
+/// Frames are written to `out_dir` as `00001.hl7`, `00002.hl7` etc in the order they were captured.  With
+/// `strip_framing` each file holds just the HL7 payload, otherwise the payload is re-wrapped in MLLP framing so the
+/// file can be replayed as-is.  Returns the number of frames written.
+///
+/// Example:
+/// ```no_run
+/// use std::fs::File;
+///
+/// let capture = File::open("feed.mllp")?;
+/// let frames = hl7_mllp_codec::split_capture(capture, "messages".as_ref(), true)?;
+/// println!("wrote {} messages", frames);
+/// # Ok::<(), hl7_mllp_codec::MllpError>(())
+/// ```
+pub fn split_capture(
+    input: impl Read,
+    out_dir: &Path,
+    strip_framing: bool,
+) -> Result<usize, MllpError> {
+    std::fs::create_dir_all(out_dir)?;
+
+    let mut framer = MllpCodec::new();
+    let mut written = 0;
+    for frame in FrameReader::new(input) {
+        let frame = frame?;
+        written += 1;
+
+        let path = out_dir.join(format!("{:05}.hl7", written));
+        if strip_framing {
+            std::fs::write(path, &frame)?;
+        } else {
+            let mut framed = BytesMut::with_capacity(framer.framed_len(frame.len()));
+            framer.reframe(&frame, &mut framed)?;
+            std::fs::write(path, &framed)?;
+        }
+    }
+    Ok(written)
+}
@@ -0,0 +1,102 @@
+use crate::{MllpCodec, MllpError};
+use bytes::Bytes;
+use futures::StreamExt;
+use log::debug;
+use tokio::io::AsyncRead;
+use tokio::sync::mpsc::Sender;
+use tokio::task::JoinHandle;
+use tokio_util::codec::Framed;
+
+/// Spawns a task that reads frames from `transport` and sends each to `frames`, decoupling the IO loop from whatever
+/// processes them (eg an actor holding the receiver).
+///
+/// A full channel is waited on rather than buffered around, so a slow consumer slows the reads (and, through TCP flow
+/// control, the sender) rather than growing memory.  The task ends, dropping `frames` so the receiver sees the end of
+/// the stream, when the connection closes or the receiver is dropped (both `Ok(())`), or on a decode error, which is
+/// what the returned handle resolves to.  Nothing is sent back to the publisher, so this suits receivers that ACK
+/// separately, or links that don't use them.
+///
+/// Requires the `channel` crate feature.
+/// Example:
+/// ```
+/// use bytes::Bytes;
+/// use futures::SinkExt;
+/// use hl7_mllp_codec::{spawn_reader, MllpCodec};
+/// use tokio_util::codec::Framed;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let (local, remote) = tokio::io::duplex(1024);
+///     let (frames, mut received) = tokio::sync::mpsc::channel(16);
+///     let reader = spawn_reader(Framed::new(remote, MllpCodec::new()), frames);
+///
+///     let mut publisher = Framed::new(local, MllpCodec::new());
+///     publisher.send(bytes::BytesMut::from("Hello")).await.unwrap();
+///     assert_eq!(received.recv().await, Some(Bytes::from("Hello")));
+///
+///     drop(publisher);
+///     reader.await.unwrap().unwrap();
+/// }
+/// ```
+pub fn spawn_reader<T>(
+    mut transport: Framed<T, MllpCodec>,
+    frames: Sender<Bytes>,
+) -> JoinHandle<Result<(), MllpError>>
+where
+    T: AsyncRead + Unpin + Send + 'static,
+{
+    tokio::spawn(async move {
+        while let Some(frame) = transport.next().await {
+            if frames.send(frame?.freeze()).await.is_err() {
+                debug!("MLLP: Frame receiver dropped, no longer reading");
+                break;
+            }
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::BytesMut;
+    use futures::SinkExt;
+    use tokio::io::AsyncWriteExt;
+
+    #[tokio::test]
+    async fn frames_delivered_through_channel() {
+        let (local, remote) = tokio::io::duplex(1024);
+        // a channel of one exercises the backpressure, the reader has to wait on each send
+        let (frames, mut received) = tokio::sync::mpsc::channel(1);
+        let reader = spawn_reader(Framed::new(remote, MllpCodec::new()), frames);
+
+        let mut publisher = Framed::new(local, MllpCodec::new());
+        for i in 0..5 {
+            let message = format!("Message {}", i);
+            publisher
+                .send(BytesMut::from(message.as_str()))
+                .await
+                .unwrap();
+            assert_eq!(received.recv().await, Some(Bytes::from(message)));
+        }
+
+        drop(publisher);
+        assert_eq!(received.recv().await, None); // the sender's dropped once the connection closes
+        reader.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn decode_error_ends_reader() {
+        let (mut local, remote) = tokio::io::duplex(1024);
+        let codec = MllpCodec::builder().max_frame_size(4).build().unwrap();
+        let (frames, mut received) = tokio::sync::mpsc::channel(1);
+        let reader = spawn_reader(Framed::new(remote, codec), frames);
+
+        local.write_all(b"\x0BToo long\x1C\x0D").await.unwrap();
+        assert_eq!(received.recv().await, None);
+        assert!(matches!(
+            reader.await.unwrap(),
+            Err(MllpError::FrameTooLarge { .. })
+        ));
+    }
+}
@@ -0,0 +1,75 @@
+//! **Non-standard** frame checksums, for bridging MLLP over transports that can corrupt data in flight.
+//!
+//! The checksum is a CRC32 (the IEEE polynomial used by zip, PNG and ethernet) of the payload, written as 8 uppercase
+//! hex digits between the payload and the footer.  Hex keeps the frame printable, so the checksum can never be
+//! mistaken for the header or footer bytes.
+
+/// The length of the checksum as written into the frame
+pub(crate) const CHECKSUM_LEN: usize = 8;
+
+/// Lookup table for the reflected IEEE polynomial, built at compile time
+const TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// The CRC32 of `data`
+pub(crate) fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, b| {
+        TABLE[((crc ^ *b as u32) & 0xFF) as usize] ^ (crc >> 8)
+    })
+}
+
+/// The checksum of `payload`, as it's written into the frame
+pub(crate) fn encode(payload: &[u8]) -> [u8; CHECKSUM_LEN] {
+    let mut digits = [0u8; CHECKSUM_LEN];
+    digits.copy_from_slice(format!("{:08X}", crc32(payload)).as_bytes());
+    digits
+}
+
+/// Splits the checksum off the end of a decoded frame, returning the checksum it carried, or `None` if the frame is
+/// too short or doesn't end in hex digits.
+pub(crate) fn split(frame: &[u8]) -> Option<(&[u8], u32)> {
+    let (payload, digits) = frame.split_at(frame.len().checked_sub(CHECKSUM_LEN)?);
+    let digits = std::str::from_utf8(digits).ok()?;
+    if !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None; // from_str_radix would take a leading '+'
+    }
+    Some((payload, u32::from_str_radix(digits, 16).ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_reference_crc32() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926); // the standard check value
+    }
+
+    #[test]
+    fn splits_checksum_from_frame() {
+        let mut frame = b"abcd".to_vec();
+        frame.extend_from_slice(&encode(b"abcd"));
+        assert_eq!(split(&frame), Some((&b"abcd"[..], crc32(b"abcd"))));
+
+        assert_eq!(split(b"abc"), None);
+        assert_eq!(split(b"abcd+1234567"), None);
+    }
+}
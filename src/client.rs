@@ -0,0 +1,448 @@
+use crate::ack::{classify, FrameKind};
+use crate::{MllpCodec, MllpError};
+use bytes::BytesMut;
+use futures::{SinkExt, StreamExt};
+use log::debug;
+use std::collections::VecDeque;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::time::{sleep_until, timeout, Instant};
+use tokio_util::codec::Framed;
+
+/// A publisher-side wrapper around a [Framed] MLLP transport that enforces the spec's wait-for-ack rule:
+/// "the Source system shall not send new HL7 content until an acknowledgement for the previous HL7 Content has been received."
+///
+/// Calling [send](MllpClient::send) while an ACK is still outstanding returns [MllpError::AckOutstanding] rather than
+/// putting a second message on the wire.
+///
+/// Requires the `client` crate feature.
+/// Example:
+/// ```no_run
+/// use bytes::BytesMut;
+/// use hl7_mllp_codec::MllpClient;
+/// use tokio::net::TcpStream;
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+///     let mut client = MllpClient::new(TcpStream::connect("127.0.0.1:8080").await?);
+///
+///     client.send(BytesMut::from("MSH|^~\\&|...")).await?;
+///     let ack = client.receive_ack().await?;
+///     println!("Received response: {:?}", ack);
+///     Ok(())
+/// }
+/// ```
+pub struct MllpClient<T> {
+    transport: Framed<T, MllpCodec>,
+    awaiting_ack: bool,
+    keepalive: Option<Keepalive>,
+    last_sent: Instant,
+    interleaved_acks: bool,
+    // frames that arrived while waiting for the other kind, when acks are interleaved
+    inbound_messages: VecDeque<BytesMut>,
+    inbound_acks: VecDeque<BytesMut>,
+}
+
+struct Keepalive {
+    interval: Duration,
+    payload: BytesMut,
+}
+
+impl<T> MllpClient<T>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Wraps the `io` stream in a default [MllpCodec].
+    pub fn new(io: T) -> Self {
+        MllpClient::from_framed(Framed::new(io, MllpCodec::new()))
+    }
+
+    /// Wraps an existing transport, for when the codec needs non-default configuration.
+    pub fn from_framed(transport: Framed<T, MllpCodec>) -> Self {
+        MllpClient {
+            transport,
+            awaiting_ack: false,
+            keepalive: None,
+            last_sent: Instant::now(),
+            interleaved_acks: false,
+            inbound_messages: VecDeque::new(),
+            inbound_acks: VecDeque::new(),
+        }
+    }
+
+    /// For bidirectional gateways that send their own messages down the same connection as the ACKs for ours.
+    /// When enabled, [receive_ack](MllpClient::receive_ack) only returns frames that [classify] as ACKs, holding any
+    /// messages that arrive in the meantime for [receive_message](MllpClient::receive_message).  As messages and ACKs
+    /// can then arrive back-to-back the codec looks for the first footer in its buffer rather than the last.
+    /// Defaults to `false`.
+    pub fn interleaved_acks(mut self, enabled: bool) -> Self {
+        self.interleaved_acks = enabled;
+        self.transport.codec_mut().scan_forward = enabled;
+        self
+    }
+
+    /// Enables keepalives for connections that could otherwise sit idle long enough to be dropped by a firewall.
+    /// Once nothing has been sent for `interval`, [idle](MllpClient::idle) sends `payload` (an empty frame is common)
+    /// as a message in its own right.  Like the client's other timeouts this runs on tokio's clock, so tests can drive
+    /// it from a paused runtime (see [tokio::time::advance]).
+    pub fn keepalive(mut self, interval: Duration, payload: BytesMut) -> Self {
+        self.keepalive = Some(Keepalive { interval, payload });
+        self
+    }
+
+    /// Waits until the connection has been idle for the keepalive interval, then sends the keepalive payload and
+    /// waits for its ACK, so the wait-for-ack rule is honoured for keepalives too.  Intended to be `select!`ed
+    /// against your source of real messages.
+    ///
+    /// Returns [MllpError::AckOutstanding] if a real message is still awaiting its ACK, and never completes if no
+    /// keepalive is configured.  If this is cancelled after the keepalive has been sent (eg by a message arriving), its
+    /// ACK is still [outstanding](MllpClient::awaiting_ack), to be taken by [receive_ack](MllpClient::receive_ack)
+    /// before the next send.
+    /// Example:
+    /// ```no_run
+    /// # use bytes::BytesMut;
+    /// # use hl7_mllp_codec::MllpClient;
+    /// # use std::time::Duration;
+    /// # async fn run(stream: tokio::net::TcpStream, mut messages: tokio::sync::mpsc::Receiver<BytesMut>) -> Result<(), hl7_mllp_codec::MllpError> {
+    /// let mut client = MllpClient::new(stream).keepalive(Duration::from_secs(60), BytesMut::new());
+    /// loop {
+    ///     tokio::select! {
+    ///         Some(message) = messages.recv() => {
+    ///             if client.awaiting_ack() {
+    ///                 client.receive_ack().await?; // the keepalive's, interrupted by this message
+    ///             }
+    ///             client.send(message).await?;
+    ///             client.receive_ack().await?;
+    ///         }
+    ///         ack = client.idle() => { ack?; }
+    ///     }
+    /// }
+    /// # }
+    /// ```
+    pub async fn idle(&mut self) -> Result<BytesMut, MllpError> {
+        if self.awaiting_ack {
+            return Err(MllpError::AckOutstanding);
+        }
+
+        let (due, payload) = match &self.keepalive {
+            Some(keepalive) => (
+                self.last_sent + keepalive.interval,
+                keepalive.payload.clone(),
+            ),
+            None => return futures::future::pending().await,
+        };
+
+        sleep_until(due).await;
+        debug!("MLLP: Connection idle, sending keepalive");
+        self.send(payload).await?;
+        self.receive_ack().await
+    }
+
+    /// Sends a message, returning [MllpError::AckOutstanding] if the previous message's ACK hasn't been received yet.
+    pub async fn send(&mut self, message: BytesMut) -> Result<(), MllpError> {
+        if self.awaiting_ack {
+            return Err(MllpError::AckOutstanding);
+        }
+
+        self.transport.send(message).await?;
+        self.awaiting_ack = true;
+        self.last_sent = Instant::now();
+        debug!("MLLP: Message sent, awaiting ack");
+        Ok(())
+    }
+
+    /// Waits for the next frame from the remote end, which per the spec is the ACK/NAK for the last message sent.
+    /// With [interleaved_acks](MllpClient::interleaved_acks) enabled, waits for the next frame that is an ACK.
+    pub async fn receive_ack(&mut self) -> Result<BytesMut, MllpError> {
+        let ack = match self.inbound_acks.pop_front() {
+            Some(ack) => ack,
+            None => loop {
+                let frame = self.next_frame().await?;
+                if !self.interleaved_acks || self.classify(&frame) == FrameKind::Ack {
+                    break frame;
+                }
+                debug!("MLLP: Message interleaved with ack, holding it");
+                self.inbound_messages.push_back(frame);
+            },
+        };
+        self.awaiting_ack = false;
+        Ok(ack)
+    }
+
+    /// Waits for the next message (rather than ACK) from the remote end, for connections with
+    /// [interleaved_acks](MllpClient::interleaved_acks) enabled.  Any ACKs that arrive in the meantime are held for
+    /// [receive_ack](MllpClient::receive_ack).
+    pub async fn receive_message(&mut self) -> Result<BytesMut, MllpError> {
+        if let Some(message) = self.inbound_messages.pop_front() {
+            return Ok(message);
+        }
+        loop {
+            let frame = self.next_frame().await?;
+            if self.classify(&frame) == FrameKind::Message {
+                return Ok(frame);
+            }
+            debug!("MLLP: Ack interleaved with messages, holding it");
+            self.inbound_acks.push_back(frame);
+        }
+    }
+
+    fn classify(&self, frame: &[u8]) -> FrameKind {
+        classify(&self.transport.codec().config().segment_ids(frame))
+    }
+
+    async fn next_frame(&mut self) -> Result<BytesMut, MllpError> {
+        match self.transport.next().await {
+            Some(Ok(frame)) => Ok(frame),
+            Some(Err(e)) => Err(e),
+            None => Err(MllpError::ConnectionClosed),
+        }
+    }
+
+    /// Whether a message has been sent and its ACK not yet received.
+    pub fn awaiting_ack(&self) -> bool {
+        self.awaiting_ack
+    }
+
+    /// Closes the connection gracefully: if a message is still awaiting its ACK, waits up to `ack_timeout` for it
+    /// (returning it) before flushing and shutting down the transport, so the remote end isn't left with an ACK it can't
+    /// deliver and we aren't left with data on the stream.  Fails with [MllpError::AckTimeout] if the ACK doesn't
+    /// arrive in time, in which case the transport is dropped without a graceful close.
+    pub async fn shutdown(mut self, ack_timeout: Duration) -> Result<Option<BytesMut>, MllpError> {
+        let ack = if self.awaiting_ack {
+            debug!("MLLP: Awaiting outstanding ack before shutting down");
+            match timeout(ack_timeout, self.receive_ack()).await {
+                Ok(ack) => Some(ack?),
+                Err(_) => return Err(MllpError::AckTimeout),
+            }
+        } else {
+            None
+        };
+
+        self.transport.close().await?;
+        debug!("MLLP: Connection shut down");
+        Ok(ack)
+    }
+
+    /// Returns the underlying transport.
+    pub fn into_inner(self) -> Framed<T, MllpCodec> {
+        self.transport
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::FutureExt;
+
+    #[tokio::test]
+    async fn second_send_before_ack_is_refused() {
+        let (local, remote) = tokio::io::duplex(1024);
+        let mut client = MllpClient::new(local);
+        let mut listener = Framed::new(remote, MllpCodec::new());
+
+        client.send(BytesMut::from("first")).await.unwrap();
+        match client.send(BytesMut::from("second")).await {
+            Err(MllpError::AckOutstanding) => {}
+            other => panic!("Second send before ack not refused: {:?}", other),
+        }
+
+        let received = listener.next().await.unwrap().unwrap();
+        assert_eq!(&received[..], b"first");
+        listener.send(BytesMut::from("\x06")).await.unwrap();
+
+        let ack = client.receive_ack().await.unwrap();
+        assert_eq!(&ack[..], b"\x06");
+        assert!(!client.awaiting_ack());
+
+        client.send(BytesMut::from("second")).await.unwrap();
+        let received = listener.next().await.unwrap().unwrap();
+        assert_eq!(&received[..], b"second");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn message_during_keepalive_ack_waits_for_it() {
+        let (local, remote) = tokio::io::duplex(1024);
+        let mut client = MllpClient::new(local).keepalive(Duration::from_secs(60), BytesMut::new());
+        let listener = tokio::spawn(async move {
+            let mut listener = Framed::new(remote, MllpCodec::new());
+            let mut received = Vec::new();
+            while let Some(Ok(frame)) = listener.next().await {
+                received.push(frame);
+                tokio::time::sleep(Duration::from_secs(1)).await; // a slow ACK
+                listener.send(BytesMut::from("\x06")).await.unwrap();
+            }
+            received
+        });
+        let (sender, mut messages) = tokio::sync::mpsc::channel(1);
+        tokio::spawn(async move {
+            // arrives while the keepalive (sent at 60s) is waiting on its ACK
+            tokio::time::sleep(Duration::from_millis(60_500)).await;
+            sender.send(BytesMut::from("message")).await.unwrap();
+        });
+
+        // the loop from the idle docs, until the message is acked
+        loop {
+            tokio::select! {
+                Some(message) = messages.recv() => {
+                    if client.awaiting_ack() {
+                        client.receive_ack().await.unwrap();
+                    }
+                    client.send(message).await.unwrap();
+                    client.receive_ack().await.unwrap();
+                    break;
+                }
+                ack = client.idle() => { ack.unwrap(); }
+            }
+        }
+
+        drop(client);
+        assert_eq!(listener.await.unwrap(), vec!["", "message"]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn keepalive_sent_after_interval() {
+        let (local, remote) = tokio::io::duplex(1024);
+        let mut client = MllpClient::new(local).keepalive(Duration::from_secs(30), BytesMut::new());
+        let mut listener = Framed::new(remote, MllpCodec::new());
+
+        let listener = tokio::spawn(async move {
+            let keepalive = listener.next().await.unwrap().unwrap();
+            listener.send(BytesMut::from("\x06")).await.unwrap();
+            keepalive
+        });
+
+        let start = Instant::now();
+        let ack = client.idle().await.unwrap();
+
+        assert_eq!(&ack[..], b"\x06");
+        assert!(start.elapsed() >= Duration::from_secs(30));
+        assert!(listener.await.unwrap().is_empty());
+        assert!(!client.awaiting_ack());
+    }
+
+    /// Lets spawned tasks run to their next wait, without the paused clock auto-advancing as it would if we slept
+    async fn settle() {
+        for _ in 0..10 {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn keepalive_sent_exactly_at_interval() {
+        let (local, remote) = tokio::io::duplex(1024);
+        let mut client = MllpClient::new(local).keepalive(Duration::from_secs(30), BytesMut::new());
+        let mut listener = Framed::new(remote, MllpCodec::new());
+        let idle = tokio::spawn(async move { client.idle().await });
+
+        settle().await;
+        tokio::time::advance(Duration::from_millis(29_999)).await;
+        settle().await;
+        assert!(listener.next().now_or_never().is_none());
+
+        tokio::time::advance(Duration::from_millis(1)).await;
+        assert!(listener.next().await.unwrap().unwrap().is_empty());
+        listener.send(BytesMut::from("\x06")).await.unwrap();
+        assert_eq!(&idle.await.unwrap().unwrap()[..], b"\x06");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn shutdown_ack_timeout_fires_exactly() {
+        let (local, _remote) = tokio::io::duplex(1024);
+        let mut client = MllpClient::new(local);
+        client.send(BytesMut::from("last")).await.unwrap();
+        let mut shutdown = tokio::spawn(client.shutdown(Duration::from_secs(5)));
+
+        settle().await;
+        tokio::time::advance(Duration::from_millis(4999)).await;
+        settle().await;
+        assert!((&mut shutdown).now_or_never().is_none());
+
+        tokio::time::advance(Duration::from_millis(1)).await;
+        match shutdown.await.unwrap() {
+            Err(MllpError::AckTimeout) => {}
+            other => panic!("Expected an ack timeout, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn no_keepalive_while_awaiting_ack() {
+        let (local, _remote) = tokio::io::duplex(1024);
+        let mut client = MllpClient::new(local).keepalive(Duration::from_secs(30), BytesMut::new());
+
+        client.send(BytesMut::from("first")).await.unwrap();
+
+        match client.idle().await {
+            Err(MllpError::AckOutstanding) => {}
+            other => panic!("Keepalive attempted while awaiting ack: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn interleaved_message_and_ack_are_routed() {
+        let (local, remote) = tokio::io::duplex(1024);
+        let mut client = MllpClient::new(local).interleaved_acks(true);
+        let mut gateway = Framed::new(remote, MllpCodec::new());
+
+        client
+            .send(BytesMut::from("MSH|^~\\&|LAB||||||ORU^R01|1|P|2.3\r"))
+            .await
+            .unwrap();
+        gateway.next().await.unwrap().unwrap();
+
+        // the gateway sends a message of its own before getting round to our ack
+        let message = "MSH|^~\\&|EMR||||||ADT^A01|X1|P|2.3\rPID|1\r";
+        let ack = "MSH|^~\\&|EMR||||||ACK^R01|1|P|2.3\rMSA|AA|1\r";
+        gateway.send(BytesMut::from(message)).await.unwrap();
+        gateway.send(BytesMut::from(ack)).await.unwrap();
+
+        assert_eq!(client.receive_ack().await.unwrap(), ack);
+        assert!(!client.awaiting_ack());
+        assert_eq!(client.receive_message().await.unwrap(), message);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn shutdown_waits_for_outstanding_ack() {
+        let (local, remote) = tokio::io::duplex(1024);
+        let mut client = MllpClient::new(local);
+        let mut listener = Framed::new(remote, MllpCodec::new());
+
+        client.send(BytesMut::from("last")).await.unwrap();
+        let listener = tokio::spawn(async move {
+            listener.next().await.unwrap().unwrap();
+            tokio::time::sleep(Duration::from_secs(2)).await;
+            listener.send(BytesMut::from("\x06")).await.unwrap();
+            listener.next().await.is_none() // the client closed the connection cleanly
+        });
+
+        let ack = client.shutdown(Duration::from_secs(5)).await.unwrap();
+        assert_eq!(ack.as_deref(), Some(&b"\x06"[..]));
+        assert!(listener.await.unwrap());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn shutdown_gives_up_on_late_ack() {
+        let (local, _remote) = tokio::io::duplex(1024);
+        let mut client = MllpClient::new(local);
+
+        client.send(BytesMut::from("last")).await.unwrap();
+        match client.shutdown(Duration::from_secs(5)).await {
+            Err(MllpError::AckTimeout) => {}
+            other => panic!("Expected an ack timeout, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn closed_connection_while_awaiting_ack() {
+        let (local, remote) = tokio::io::duplex(1024);
+        let mut client = MllpClient::new(local);
+
+        client.send(BytesMut::from("first")).await.unwrap();
+        drop(remote);
+
+        match client.receive_ack().await {
+            Err(MllpError::ConnectionClosed) | Err(MllpError::Io(_)) => {}
+            other => panic!("Expected closed connection, got {:?}", other),
+        }
+        assert!(client.awaiting_ack());
+    }
+}
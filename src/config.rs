@@ -0,0 +1,686 @@
+use crate::hl7::{self, normalize_segment_ids};
+use crate::recovery::ErrorHook;
+use crate::{FrameValidator, MllpCodec, MllpError, RecoveryAction, MLLP_FOOTER, MLLP_HEADER};
+use bytes::BytesMut;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A transform applied to payloads as they're framed or deframed, see [MllpCodecBuilder::encode_transform]
+pub(crate) type PayloadTransform = dyn Fn(&[u8]) -> Vec<u8> + Send + Sync;
+
+/// What the decoder should do when it encounters a frame larger than the configured max frame size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OversizeAction {
+    /// Fail the decode with [MllpError::FrameTooLarge], which generally tears down the connection.
+    #[default]
+    Error,
+    /// Discard the frame (up to and including its footer) and carry on decoding subsequent frames.
+    Skip,
+}
+
+/// What the decoder should do with NUL (`0x00`) bytes inside a frame's content, which corrupt feeds sometimes carry
+/// and many HL7 parsers choke on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NulHandling {
+    /// Leave them be, the content is returned as received.
+    #[default]
+    PassThrough,
+    /// Remove them from the decoded content.
+    Strip,
+    /// Fail the decode with [MllpError::InvalidFrame].
+    Error,
+}
+
+/// Builds a [MllpCodec] with non-default behaviours, for those peers that don't quite follow the spec.
+///
+/// Every option defaults to the spec-compliant behaviour, so `MllpCodec::builder().build()` is equivalent to `MllpCodec::new()`.
+/// Example:
+/// ```
+/// use hl7_mllp_codec::MllpCodec;
+/// let mllp = MllpCodec::builder()
+///     .allow_missing_header(true)
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Default)]
+pub struct MllpCodecBuilder {
+    config: MllpConfig,
+}
+
+/// The configuration of a [MllpCodec], built with a [MllpCodecBuilder].
+///
+/// A configuration can be shared (via an `Arc`) between any number of codecs, see [MllpCodec::from_config].
+pub struct MllpConfig {
+    pub(crate) header: u8,
+    pub(crate) footer: Vec<u8>,
+    pub(crate) allow_missing_header: bool,
+    pub(crate) footer_ends_previous_frame: bool,
+    pub(crate) skip_empty_frames: bool,
+    pub(crate) allow_reversed_footer: bool,
+    pub(crate) validator: Option<Box<dyn FrameValidator>>,
+    pub(crate) max_frame_size: Option<usize>,
+    pub(crate) check_encoded_size: bool,
+    pub(crate) min_frame_size: usize,
+    pub(crate) max_prefix_skip: Option<usize>,
+    pub(crate) type_frame_sizes: HashMap<String, usize>,
+    pub(crate) oversize_action: OversizeAction,
+    pub(crate) max_buffered_frames: Option<usize>,
+    pub(crate) one_frame_per_buffer: bool,
+    pub(crate) on_error: Option<Box<ErrorHook>>,
+    pub(crate) pad_to: Option<usize>,
+    pub(crate) expected_delimiters: Option<String>,
+    pub(crate) collapse_repeated_footers: bool,
+    pub(crate) collapse_repeated_headers: bool,
+    pub(crate) reject_bare_footer: bool,
+    pub(crate) strip_trailing_terminator: bool,
+    pub(crate) reject_framed_payloads: bool,
+    pub(crate) validate_hl7: bool,
+    pub(crate) max_segment_len: Option<usize>,
+    pub(crate) strict_encoding_characters: bool,
+    pub(crate) high_water_mark: Option<usize>,
+    pub(crate) lenient_segment_ids: bool,
+    pub(crate) auto_control_id: bool,
+    pub(crate) frame_checksum: bool,
+    pub(crate) slow_decode_threshold: Option<Duration>,
+    pub(crate) nul_handling: NulHandling,
+    pub(crate) transcode_utf16: bool,
+    pub(crate) strip_bom: bool,
+    pub(crate) prepend_bom: bool,
+    pub(crate) consume_trailing_lf: bool,
+    pub(crate) allowed_message_types: Option<Vec<String>>,
+    pub(crate) encode_transform: Option<Box<PayloadTransform>>,
+    pub(crate) decode_transform: Option<Box<PayloadTransform>>,
+}
+
+impl Default for MllpConfig {
+    fn default() -> Self {
+        MllpConfig {
+            header: MLLP_HEADER,
+            footer: MLLP_FOOTER.to_vec(),
+            allow_missing_header: false,
+            footer_ends_previous_frame: false,
+            skip_empty_frames: false,
+            allow_reversed_footer: false,
+            validator: None,
+            max_frame_size: None,
+            check_encoded_size: false,
+            min_frame_size: 0,
+            max_prefix_skip: None,
+            type_frame_sizes: HashMap::new(),
+            oversize_action: OversizeAction::default(),
+            max_buffered_frames: None,
+            one_frame_per_buffer: false,
+            on_error: None,
+            pad_to: None,
+            expected_delimiters: None,
+            collapse_repeated_footers: true,
+            collapse_repeated_headers: false,
+            reject_bare_footer: false,
+            strip_trailing_terminator: false,
+            reject_framed_payloads: false,
+            validate_hl7: false,
+            max_segment_len: None,
+            strict_encoding_characters: false,
+            high_water_mark: None,
+            lenient_segment_ids: false,
+            auto_control_id: false,
+            frame_checksum: false,
+            slow_decode_threshold: None,
+            nul_handling: NulHandling::default(),
+            transcode_utf16: false,
+            strip_bom: false,
+            prepend_bom: false,
+            consume_trailing_lf: false,
+            allowed_message_types: None,
+            encode_transform: None,
+            decode_transform: None,
+        }
+    }
+}
+
+impl MllpConfig {
+    /// The max frame size for a frame whose content (so far) is `content`, see [MllpCodecBuilder::max_frame_size_for]
+    pub(crate) fn max_frame_size_of(&self, content: &[u8]) -> Option<usize> {
+        if self.type_frame_sizes.is_empty() {
+            return self.max_frame_size;
+        }
+
+        // the message type is only settled once the field after it has started
+        let content = self.segment_ids(content);
+        if hl7::msh_field(&content, 10).is_some() {
+            return hl7::message_type(&content)
+                .and_then(|message_type| self.type_frame_sizes.get(message_type))
+                .copied()
+                .or(self.max_frame_size);
+        }
+        let largest = self.type_frame_sizes.values().max().copied();
+        self.max_frame_size
+            .zip(largest)
+            .map(|(max, largest)| max.max(largest))
+    }
+
+    /// The frame as the segment-finding checks should see it, see [MllpCodecBuilder::lenient_segment_ids]
+    pub(crate) fn segment_ids<'a>(&self, frame: &'a [u8]) -> Cow<'a, [u8]> {
+        if self.lenient_segment_ids {
+            normalize_segment_ids(frame)
+        } else {
+            Cow::Borrowed(frame)
+        }
+    }
+}
+
+impl MllpCodecBuilder {
+    /// **Non-standard:** Frames with a block header other than `0x0B`, for MLLP-like transports with their own framing.
+    /// The header byte must not appear in the footer, see [build](MllpCodecBuilder::build).  Defaults to [MLLP_HEADER].
+    pub fn header(mut self, header: u8) -> Self {
+        self.config.header = header;
+        self
+    }
+
+    /// **Non-standard:** Frames with a block footer other than `0x1C 0x0D`, for MLLP-like transports with their own
+    /// framing.  Defaults to [MLLP_FOOTER].
+    pub fn footer(mut self, footer: &[u8]) -> Self {
+        self.config.footer = footer.to_vec();
+        self
+    }
+
+    /// Some extremely lax senders omit the `0x0B` block header entirely and only terminate their frames with the footer.
+    /// When enabled, if no header is found but a footer is, everything from the start of the buffer (ie the end of the
+    /// previous frame) up to the footer is treated as the payload.  Defaults to `false`.
+    pub fn allow_missing_header(mut self, allow: bool) -> Self {
+        self.config.allow_missing_header = allow;
+        self
+    }
+
+    /// **Non-standard:** Some senders send a frame's footer in a packet of its own, sometimes after the frame seems to
+    /// have ended, so a footer turns up ahead of the next frame.  These stray footers are always discarded, bar when
+    /// [allow_missing_header](MllpCodecBuilder::allow_missing_header) is enabled, where everything up to a footer is a
+    /// frame, so each would decode as an empty frame.  When enabled, footers at the start of the buffer are instead
+    /// taken as a late terminator for the previous frame and discarded, while a header followed straight by a footer is
+    /// still an empty frame.  To drop those too, see [skip_empty_frames](MllpCodecBuilder::skip_empty_frames).
+    /// Defaults to `false`.
+    pub fn footer_ends_previous_frame(mut self, enabled: bool) -> Self {
+        self.config.footer_ends_previous_frame = enabled;
+        self
+    }
+
+    /// Discards frames with no content at all (eg `0x0B 0x1C 0x0D`, or with
+    /// [allow_missing_header](MllpCodecBuilder::allow_missing_header) a stray footer), rather than returning them.
+    /// Their framing is counted in [MllpStats::bytes_skipped](crate::MllpStats::bytes_skipped).  This drops every empty
+    /// frame, where [footer_ends_previous_frame](MllpCodecBuilder::footer_ends_previous_frame) only drops stray footers,
+    /// so a peer's empty keep-alive frames are lost along with them.  Defaults to `false`.
+    pub fn skip_empty_frames(mut self, skip: bool) -> Self {
+        self.config.skip_empty_frames = skip;
+        self
+    }
+
+    /// **Non-standard:** A known class of buggy gateways swaps the footer byte order, terminating frames with `0x0D 0x1C`.
+    /// When enabled, either byte order is accepted as a footer, whichever appears first.  A payload ending in CR followed
+    /// by a correct footer is still treated as a correct footer.  Defaults to `false`.
+    pub fn allow_reversed_footer(mut self, allow: bool) -> Self {
+        self.config.allow_reversed_footer = allow;
+        self
+    }
+
+    /// Only accepts frames whose message type (MSH-9) is in `allowed`, failing the decode of any others with
+    /// [MllpError::DisallowedMessageType].  Entries are either a message code, allowing any trigger event (`ADT`), or a
+    /// code and trigger event (`ORU^R01`).  Frames without a message type at all are rejected too.  Defaults to
+    /// accepting every message type.
+    /// Example:
+    /// ```
+    /// use bytes::BytesMut;
+    /// use hl7_mllp_codec::{MllpCodec, MllpError};
+    /// use tokio_util::codec::Decoder;
+    ///
+    /// let mut codec = MllpCodec::builder()
+    ///     .allowed_message_types(&["ADT", "ORU^R01"])
+    ///     .build()
+    ///     .unwrap();
+    /// let mut src = BytesMut::from("\x0BMSH|^~\\&|LAB||||||ORM^O01|1|P|2.3\x1C\x0D");
+    /// assert!(matches!(
+    ///     codec.decode(&mut src),
+    ///     Err(MllpError::DisallowedMessageType { .. })
+    /// ));
+    /// ```
+    pub fn allowed_message_types(mut self, allowed: &[&str]) -> Self {
+        self.config.allowed_message_types = Some(allowed.iter().map(|t| (*t).to_owned()).collect());
+        self
+    }
+
+    /// **Non-standard:** Some senders terminate their frames with `0x1C 0x0D 0x0A`, adding a LF after the footer.  The
+    /// decoder already skips the LF as junk ahead of the next frame, but counts it in
+    /// [MllpStats::bytes_skipped](crate::MllpStats::bytes_skipped).  When enabled, a single LF directly after a footer is
+    /// quietly consumed as part of it.  Defaults to `false`.
+    pub fn consume_trailing_lf(mut self, consume: bool) -> Self {
+        self.config.consume_trailing_lf = consume;
+        self
+    }
+
+    /// Some senders terminate their frames with the footer twice (`0x1C 0x0D 0x1C 0x0D`).  The spec-compliant decoder
+    /// takes the _last_ footer in the buffer as the end of the frame, so would hand back the first footer as content.
+    /// When enabled, a run of back-to-back footers ends the frame at the first of them (content can't hold a `0x1C`
+    /// anyway), and the rest are discarded.  Stray footers ahead of the next frame are always discarded.  Defaults to
+    /// `true`, disable it to get the extra footers back as content.
+    pub fn collapse_repeated_footers(mut self, collapse: bool) -> Self {
+        self.config.collapse_repeated_footers = collapse;
+        self
+    }
+
+    /// **Non-standard:** Some senders start their frames with the header twice (`0x0B 0x0B`), so the second ends up
+    /// as the first byte of the content, in front of the MSH.  When enabled, a run of headers starts the frame at the
+    /// first byte that isn't one.  Defaults to `false`.
+    pub fn collapse_repeated_headers(mut self, collapse: bool) -> Self {
+        self.config.collapse_repeated_headers = collapse;
+        self
+    }
+
+    /// What to do with a read holding nothing but a footer, with no frame to end.  By default it's consumed (and
+    /// counted in [MllpStats::bytes_skipped](crate::MllpStats::bytes_skipped)) like any other stray footer, when
+    /// enabled the decode fails with [MllpError::InvalidFrame] instead, for peers that should never send one.  Either way
+    /// the footer's removed from the buffer, so it can't stall the decoder.  Has no effect when
+    /// [allow_missing_header](MllpCodecBuilder::allow_missing_header) is enabled, as a bare footer is then an empty
+    /// frame.  Defaults to `false`.
+    pub fn reject_bare_footer(mut self, reject: bool) -> Self {
+        self.config.reject_bare_footer = reject;
+        self
+    }
+
+    /// Runs `validator` against every decoded frame, failing the decode with [MllpError::InvalidFrame] if it objects.
+    /// See [FrameValidator].
+    pub fn validator(mut self, validator: impl FrameValidator + 'static) -> Self {
+        self.config.validator = Some(Box::new(validator));
+        self
+    }
+
+    /// Runs some basic HL7 structure checks against every decoded frame, failing the decode with
+    /// [MllpError::InvalidFrame] if the frame doesn't start with an MSH segment, or has a segment longer than the
+    /// [max_segment_len](MllpCodecBuilder::max_segment_len).  Not suitable for links carrying bare single-byte
+    /// ACK/NAKs.  Defaults to `false`.
+    pub fn validate_hl7(mut self, validate: bool) -> Self {
+        self.config.validate_hl7 = validate;
+        self
+    }
+
+    /// Limits the length of any one segment (excluding its terminator) as part of the
+    /// [validate_hl7](MllpCodecBuilder::validate_hl7) checks, which this enables.  An enormously long segment is often
+    /// a sign of binary corruption, eg in an OBX.  Defaults to no limit.
+    pub fn max_segment_len(mut self, max: usize) -> Self {
+        self.config.validate_hl7 = true;
+        self.config.max_segment_len = Some(max);
+        self
+    }
+
+    /// Requires MSH-2 (the encoding characters) to be exactly four distinct characters, none of them the field
+    /// separator, as part of the [validate_hl7](MllpCodecBuilder::validate_hl7) checks, which this enables.  A short
+    /// or repetitive MSH-2 means a truncated or corrupt header, which HL7 parsers are apt to misread rather than
+    /// reject.  Note this rejects the fifth (truncation) character HL7 v2.7 allows.  Defaults to `false`.
+    pub fn strict_encoding_characters(mut self, strict: bool) -> Self {
+        self.config.validate_hl7 |= strict;
+        self.config.strict_encoding_characters = strict;
+        self
+    }
+
+    /// **Non-standard:** Buggy senders sometimes emit lowercase segment IDs, or indent segments with spaces.  When
+    /// enabled, the codec's own checks ([validate_hl7](MllpCodecBuilder::validate_hl7), the validator's version etc)
+    /// and the client/server helpers' ACK detection match segment IDs case-insensitively, ignoring leading whitespace
+    /// (see [hl7::normalize_segment_ids](crate::hl7::normalize_segment_ids)).  Decoded frames are still handed back
+    /// byte for byte as sent.  Defaults to `false`.
+    pub fn lenient_segment_ids(mut self, lenient: bool) -> Self {
+        self.config.lenient_segment_ids = lenient;
+        self
+    }
+
+    /// Rejects frames whose MSH delimiters (the field separator MSH-1 followed by the encoding characters MSH-2, eg
+    /// `|^~\\&`) aren't exactly `delimiters`, failing the decode with [MllpError::InvalidFrame].  On a point-to-point
+    /// link the partner's delimiters are fixed, so a mismatch means a misconfigured sender and is better caught here
+    /// than as garbled fields further down the line.  Defaults to accepting whatever the message declares.
+    /// Example:
+    /// ```
+    /// use hl7_mllp_codec::MllpCodec;
+    /// let mllp = MllpCodec::builder()
+    ///     .expect_delimiters("|^~\\&")
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn expect_delimiters(mut self, delimiters: &str) -> Self {
+        self.config.expected_delimiters = Some(delimiters.to_owned());
+        self
+    }
+
+    /// Limits the size of frame content (excluding the framing bytes) the decoder will accept, protecting against
+    /// unbounded buffering.  Oversized frames are handled per the [oversize_action](MllpCodecBuilder::oversize_action).
+    /// Defaults to no limit.
+    pub fn max_frame_size(mut self, max: usize) -> Self {
+        self.config.max_frame_size = Some(max);
+        self
+    }
+
+    /// Applies the [max_frame_size](MllpCodecBuilder::max_frame_size) (and any
+    /// [per message type sizes](MllpCodecBuilder::max_frame_size_for)) to encoding too, so the app can't send a frame a
+    /// peer with the same limit would reject.  A payload whose frame (the payload plus the header, footer and any
+    /// checksum, but not padding) would be over the max fails the encode with [MllpError::FrameTooLarge], leaving `dst`
+    /// as it was.  This is a little stricter than the decoder, which only counts the content.  Defaults to `false`,
+    /// encoding payloads of any size.
+    pub fn check_encoded_size(mut self, check: bool) -> Self {
+        self.config.check_encoded_size = check;
+        self
+    }
+
+    /// Fails the decode of any frame whose content is shorter than `min` bytes with [MllpError::FrameTooSmall], for
+    /// links where every frame should at least carry a MSH segment, so a tiny frame points to truncation.  Defaults to
+    /// `0`, ie no minimum.
+    pub fn min_frame_size(mut self, min: usize) -> Self {
+        self.config.min_frame_size = min;
+        self
+    }
+
+    /// Overrides the [max_frame_size](MllpCodecBuilder::max_frame_size) for frames of `message_type` (the message code,
+    /// MSH-9.1, eg `ORU`), for links carrying the odd large message (like results with embedded images) among
+    /// otherwise small ones.  Can be called for any number of types, the rest are held to the max frame size (if any).
+    /// As the type isn't known until MSH-9 has arrived, until then a partial frame is only held to the largest of the
+    /// limits.
+    /// Example:
+    /// ```
+    /// use hl7_mllp_codec::MllpCodec;
+    /// let mllp = MllpCodec::builder()
+    ///     .max_frame_size(64 * 1024)
+    ///     .max_frame_size_for("ORU", 16 * 1024 * 1024)
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn max_frame_size_for(mut self, message_type: &str, max: usize) -> Self {
+        self.config
+            .type_frame_sizes
+            .insert(message_type.to_owned(), max);
+        self
+    }
+
+    /// Limits how many bytes of junk the decoder will buffer while waiting for a header, for peers that stream
+    /// garbage without ever starting a frame.  Past the limit the junk is discarded and the decode fails with
+    /// [MllpError::PrefixTooLong], a clearer diagnostic than eventually tripping the
+    /// [max_frame_size](MllpCodecBuilder::max_frame_size).  Junk that's followed by a header is discarded as usual,
+    /// however long it is.  Defaults to no limit.
+    pub fn max_prefix_skip(mut self, max: usize) -> Self {
+        self.config.max_prefix_skip = Some(max);
+        self
+    }
+
+    /// What to do with frames over the [max_frame_size](MllpCodecBuilder::max_frame_size), defaults to [OversizeAction::Error].
+    pub fn oversize_action(mut self, action: OversizeAction) -> Self {
+        self.config.oversize_action = action;
+        self
+    }
+
+    /// What to do with NUL bytes in decoded content, defaults to [NulHandling::PassThrough].
+    pub fn nul_handling(mut self, handling: NulHandling) -> Self {
+        self.config.nul_handling = handling;
+        self
+    }
+
+    /// Limits how many complete-but-undecoded frames may be buffered at once, failing the decode with
+    /// [MllpError::TooManyFrames] beyond that.  This protects memory against a sender pipelining frames (in breach of
+    /// the spec) faster than they're consumed, particularly via [decode_all](MllpCodec::decode_all).  Defaults to no limit.
+    pub fn max_buffered_frames(mut self, max: usize) -> Self {
+        self.config.max_buffered_frames = Some(max);
+        self
+    }
+
+    /// Enforces the spec's wait-for-ack rule on the sender: as a compliant sender waits for each frame's ACK, there's
+    /// never anything after a complete frame but (at most) junk.  When enabled, finding another frame's header behind
+    /// a complete frame fails the decode with [MllpError::PipelinedFrame], discarding the complete frame, rather than
+    /// decoding the frames regardless.  Defaults to `false`.
+    pub fn one_frame_per_buffer(mut self, strict: bool) -> Self {
+        self.config.one_frame_per_buffer = strict;
+        self
+    }
+
+    /// The amount of undecoded data (in bytes) beyond which [is_above_high_water](MllpCodec::is_above_high_water)
+    /// signals that the consumer isn't keeping up, so the caller can apply backpressure by pausing reads.  Unlike the
+    /// [max_frame_size](MllpCodecBuilder::max_frame_size) this never fails a decode.  Defaults to no mark.
+    pub fn high_water_mark(mut self, bytes: usize) -> Self {
+        self.config.high_water_mark = Some(bytes);
+        self
+    }
+
+    /// Logs (at `trace` level) any call to `decode` that takes longer than `threshold`, along with the size of the
+    /// frame (or buffered partial frame) involved, to help track down slow decodes of large frames.  Defaults to no
+    /// logging, which also skips reading the clock.
+    pub fn slow_decode_threshold(mut self, threshold: Duration) -> Self {
+        self.config.slow_decode_threshold = Some(threshold);
+        self
+    }
+
+    /// Registers a hook deciding how to recover when decoding fails (an oversized frame, a frame rejected by the
+    /// validator etc), rather than leaving the policy to the crate.  The hook is given the error and the decoder's
+    /// working buffer (which it may repair before asking for a [Retry](RecoveryAction::Retry)).
+    /// [recovery::strict](crate::recovery::strict) and [recovery::lenient](crate::recovery::lenient) are provided for the common cases.  Defaults to no hook, which
+    /// behaves as [recovery::strict](crate::recovery::strict).
+    /// Example:
+    /// ```
+    /// use hl7_mllp_codec::{MllpCodec, MllpError, RecoveryAction};
+    ///
+    /// let mllp = MllpCodec::builder()
+    ///     .max_frame_size(1024 * 1024)
+    ///     .on_error(|error, _buf| match error {
+    ///         MllpError::FrameTooLarge { .. } => RecoveryAction::Skip,
+    ///         _ => RecoveryAction::Abort,
+    ///     })
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn on_error<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&MllpError, &mut BytesMut) -> RecoveryAction + Send + Sync + 'static,
+    {
+        self.config.on_error = Some(Box::new(hook));
+        self
+    }
+
+    /// **Non-standard:** Some Windows based systems send their messages as UTF-16 (which MSH-18 would declare as
+    /// `UNICODE UTF-16`).  When enabled, decoded content starting with a UTF-16 byte order mark, or with `MSH` in
+    /// UTF-16, is transcoded to UTF-8 (dropping the BOM), so the rest of the crate (and the caller) sees the message as
+    /// usual.  Content that isn't valid UTF-16 fails the decode with [MllpError::InvalidFrame].  Frames are still found
+    /// by their single byte header and footer, and other content is left as it is.  This happens ahead of the
+    /// [nul_handling](MllpCodecBuilder::nul_handling), as UTF-16 is full of NUL bytes.  Defaults to `false`.
+    pub fn transcode_utf16(mut self, transcode: bool) -> Self {
+        self.config.transcode_utf16 = transcode;
+        self
+    }
+
+    /// **Non-standard:** Drops a UTF-8 byte order mark (`EF BB BF`) from the start of decoded content, as sent by some
+    /// Windows based systems, so the content starts with its MSH as the rest of the crate (and most HL7 parsers)
+    /// expect.  Defaults to `false`.
+    pub fn strip_bom(mut self, strip: bool) -> Self {
+        self.config.strip_bom = strip;
+        self
+    }
+
+    /// **Non-standard:** The counterpart to [strip_bom](MllpCodecBuilder::strip_bom), for downstream systems that
+    /// insist on one.  Writes a UTF-8 byte order mark between the header and the payload of every encoded frame (after
+    /// any [encode_transform](MllpCodecBuilder::encode_transform)), unless the payload already starts with one.
+    /// Defaults to `false`.
+    pub fn prepend_bom(mut self, prepend: bool) -> Self {
+        self.config.prepend_bom = prepend;
+        self
+    }
+
+    /// Passes every outbound payload through `transform` just before it's framed (after any
+    /// [strip_trailing_terminator](MllpCodecBuilder::strip_trailing_terminator) or
+    /// [auto_control_id](MllpCodecBuilder::auto_control_id) handling, but before any checksum is added), for
+    /// peers that expect their payloads obfuscated, re-encoded and so on.  The transformed payload must not contain the
+    /// footer, or the peer will see the frame end early.  Pair it with [decode_transform](MllpCodecBuilder::decode_transform)
+    /// to reverse it on the way in.  Defaults to no transform.
+    /// Example:
+    /// ```
+    /// use bytes::BytesMut;
+    /// use hl7_mllp_codec::MllpCodec;
+    /// use tokio_util::codec::Encoder;
+    ///
+    /// let mut codec = MllpCodec::builder()
+    ///     .encode_transform(|payload| payload.to_ascii_uppercase())
+    ///     .build()
+    ///     .unwrap();
+    /// let mut dst = BytesMut::new();
+    /// codec.encode(BytesMut::from("abcd"), &mut dst).unwrap();
+    /// assert_eq!(&dst[..], b"\x0BABCD\x1C\x0D");
+    /// ```
+    pub fn encode_transform<F>(mut self, transform: F) -> Self
+    where
+        F: Fn(&[u8]) -> Vec<u8> + Send + Sync + 'static,
+    {
+        self.config.encode_transform = Some(Box::new(transform));
+        self
+    }
+
+    /// Passes every inbound payload through `transform` just after it's deframed (after any checksum is verified,
+    /// but before [nul_handling](MllpCodecBuilder::nul_handling), validation and so on see it), reversing an
+    /// [encode_transform](MllpCodecBuilder::encode_transform).  Defaults to no transform.
+    pub fn decode_transform<F>(mut self, transform: F) -> Self
+    where
+        F: Fn(&[u8]) -> Vec<u8> + Send + Sync + 'static,
+    {
+        self.config.decode_transform = Some(Box::new(transform));
+        self
+    }
+
+    /// Fails the encode of any payload that already starts with the header and ends with the footer with
+    /// [MllpError::AlreadyFramed], catching the proxy that forgets to take the framing off a frame before passing it
+    /// on (which would otherwise put a frame inside a frame on the wire).  Use
+    /// [encode_raw](MllpCodec::encode_raw) for passing frames through as they are.  Defaults to `false`, so any payload
+    /// is framed as given.
+    pub fn reject_framed_payloads(mut self, reject: bool) -> Self {
+        self.config.reject_framed_payloads = reject;
+        self
+    }
+
+    /// Many HL7 generators end the last segment with a `\r`, `\n` or `\r\n` that doesn't belong inside the frame.
+    /// When enabled, a single such terminator is removed from each payload before it's encoded.  Defaults to `false`,
+    /// so payloads are framed byte for byte as given.
+    pub fn strip_trailing_terminator(mut self, strip: bool) -> Self {
+        self.config.strip_trailing_terminator = strip;
+        self
+    }
+
+    /// When enabled, payloads being encoded whose MSH-10 (message control ID) is empty or missing get a generated ID
+    /// filled in before framing, so the receiver's ACK can always be matched back to the message.  The generated IDs
+    /// are the current time in milliseconds plus a per-process sequence number.  Payloads that already carry an ID,
+    /// or have no MSH segment, are framed as given.  Defaults to `false`.
+    /// Example:
+    /// ```
+    /// use bytes::BytesMut;
+    /// use hl7_mllp_codec::{hl7, MllpCodec};
+    /// use tokio_util::codec::{Decoder, Encoder};
+    ///
+    /// let mut codec = MllpCodec::builder().auto_control_id(true).build().unwrap();
+    /// let mut framed = BytesMut::new();
+    /// codec
+    ///     .encode(BytesMut::from("MSH|^~\\&|ZIS||||||ADT^A01||P|2.3"), &mut framed)
+    ///     .unwrap();
+    /// let payload = codec.decode(&mut framed).unwrap().unwrap();
+    /// assert!(hl7::control_id(&payload).is_some());
+    /// ```
+    pub fn auto_control_id(mut self, enabled: bool) -> Self {
+        self.config.auto_control_id = enabled;
+        self
+    }
+
+    /// **Non-standard:** Pads each encoded frame with NUL bytes after the footer, up to the next multiple of
+    /// `block_size` bytes, for bridges onto transports that prefer fixed-size blocks.  The padding sits _outside_ the
+    /// frame, so decoders (this one included) skip it as junk between frames, but it's not part of the MLLP spec and
+    /// shouldn't be sent to peers that aren't expecting it (nor to a decoder with
+    /// [allow_missing_header](MllpCodecBuilder::allow_missing_header) enabled, which would take it as content).
+    /// `block_size` must be non-zero.  Defaults to no padding.
+    pub fn pad_to(mut self, block_size: usize) -> Self {
+        self.config.pad_to = Some(block_size);
+        self
+    }
+
+    /// **Non-standard:** Protects each frame with a CRC32 of its payload, for links that can corrupt data in flight.
+    /// Encoding appends the checksum (as 8 hex digits) to the payload inside the frame, and decoding strips and
+    /// verifies it, failing with [MllpError::ChecksumMismatch] if it doesn't match.  Both ends of the link must have
+    /// this enabled, so it's strictly for deployments where you control both; no standard MLLP peer will understand
+    /// it.  Defaults to `false`.
+    /// Example:
+    /// ```
+    /// use bytes::BytesMut;
+    /// use hl7_mllp_codec::MllpCodec;
+    /// use tokio_util::codec::{Decoder, Encoder};
+    ///
+    /// let mut codec = MllpCodec::builder().frame_checksum(true).build().unwrap();
+    /// let mut framed = BytesMut::new();
+    /// codec.encode(BytesMut::from("abcd"), &mut framed).unwrap();
+    /// assert_eq!(&framed[..], b"\x0BabcdED82CD11\x1C\x0D");
+    /// assert_eq!(&codec.decode(&mut framed).unwrap().unwrap()[..], b"abcd");
+    /// ```
+    pub fn frame_checksum(mut self, enabled: bool) -> Self {
+        self.config.frame_checksum = enabled;
+        self
+    }
+
+    /// Creates the configured [MllpCodec], failing with [MllpError::InvalidConfig] if the options can't work together,
+    /// eg a custom header byte that also appears in the footer, which would leave frame boundaries ambiguous.
+    /// Example:
+    /// ```
+    /// use hl7_mllp_codec::{MllpCodec, MllpError};
+    ///
+    /// let result = MllpCodec::builder().header(0x1C).build();
+    /// assert!(matches!(result, Err(MllpError::InvalidConfig(_))));
+    /// ```
+    pub fn build(self) -> Result<MllpCodec, MllpError> {
+        Ok(MllpCodec::from_config(Arc::new(self.build_config()?)))
+    }
+
+    /// Creates the configuration alone, for sharing between many codecs via [MllpCodec::from_config].
+    pub fn build_config(self) -> Result<MllpConfig, MllpError> {
+        let config = self.config;
+        if config.footer.is_empty() {
+            return Err(MllpError::InvalidConfig("the footer is empty".into()));
+        }
+        if config.pad_to == Some(0) {
+            return Err(MllpError::InvalidConfig(
+                "the pad block size is zero".into(),
+            ));
+        }
+        if config.footer.contains(&config.header) {
+            return Err(MllpError::InvalidConfig(format!(
+                "the header byte {:#04X} also appears in the footer {:02X?}",
+                config.header, config.footer
+            )));
+        }
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colliding_header_and_footer_fail_to_build() {
+        let result = MllpCodec::builder().header(0x1C).build();
+        assert!(matches!(result, Err(MllpError::InvalidConfig(_))));
+
+        // the header appearing later in the footer is just as ambiguous
+        let result = MllpCodec::builder().footer(&[0x1C, 0x0B]).build();
+        assert!(matches!(result, Err(MllpError::InvalidConfig(_))));
+
+        let result = MllpCodec::builder().footer(&[]).build_config();
+        assert!(matches!(result, Err(MllpError::InvalidConfig(_))));
+
+        let result = MllpCodec::builder().pad_to(0).build();
+        assert!(matches!(result, Err(MllpError::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn distinct_custom_framing_builds() {
+        let config = MllpCodec::builder()
+            .header(0x02)
+            .footer(&[0x03])
+            .build_config()
+            .unwrap();
+        assert_eq!(config.header, 0x02);
+        assert_eq!(config.footer, vec![0x03]);
+    }
+}
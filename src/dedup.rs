@@ -0,0 +1,143 @@
+use crate::hl7;
+use log::trace;
+use std::collections::{BTreeMap, HashMap};
+
+/// A bounded, least-recently-seen cache of message control IDs (MSH-10), for listeners facing at-least-once delivery
+/// that want to drop messages they've already processed.
+///
+/// Once `capacity` IDs have been recorded the least recently seen ID is evicted to make room.
+/// Example:
+/// ```
+/// use hl7_mllp_codec::DedupCache;
+/// let mut cache = DedupCache::new(1024);
+/// let frame = b"MSH|^~\\&|ZIS|1^AHospital|||200405141144||ADT^A01|20041104082400|P|2.3\r";
+///
+/// assert!(!cache.is_duplicate(frame));
+/// assert!(cache.is_duplicate(frame)); // redelivered
+/// ```
+#[derive(Debug, Clone)]
+pub struct DedupCache {
+    capacity: usize,
+    last_seen: HashMap<String, u64>, // control id -> tick it was last seen
+    by_age: BTreeMap<u64, String>,   // tick -> control id, oldest first
+    tick: u64,
+}
+
+impl DedupCache {
+    /// Creates a cache remembering at most `capacity` control IDs.  A capacity of 0 never flags anything as a duplicate.
+    pub fn new(capacity: usize) -> Self {
+        DedupCache {
+            capacity,
+            last_seen: HashMap::new(), // grows as IDs are seen, the capacity's only a bound
+            by_age: BTreeMap::new(),
+            tick: 0,
+        }
+    }
+
+    /// Records the control ID (MSH-10) of a decoded frame, returning `true` if it's been seen before.
+    ///
+    /// Frames without a control ID can't be deduplicated, so are never flagged (and aren't recorded).
+    pub fn is_duplicate(&mut self, frame: &[u8]) -> bool {
+        match hl7::control_id(frame) {
+            Some(id) => self.check_control_id(id),
+            None => false,
+        }
+    }
+
+    /// Records a control ID, returning `true` if it's been seen before.
+    pub fn check_control_id(&mut self, control_id: &str) -> bool {
+        if self.capacity == 0 {
+            return false;
+        }
+
+        self.tick += 1;
+
+        if let Some(previous) = self.last_seen.insert(control_id.to_owned(), self.tick) {
+            // seen it, bump it to most recently seen
+            let id = self.by_age.remove(&previous).unwrap_or_default();
+            self.by_age.insert(self.tick, id);
+            trace!("MLLP: Duplicate control id '{}'", control_id);
+            return true;
+        }
+
+        self.by_age.insert(self.tick, control_id.to_owned());
+
+        if self.last_seen.len() > self.capacity {
+            let oldest = self.by_age.keys().next().copied();
+            if let Some(oldest) = oldest.and_then(|tick| self.by_age.remove(&tick)) {
+                self.last_seen.remove(&oldest);
+            }
+        }
+
+        false
+    }
+
+    /// The maximum number of control IDs remembered.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The number of control IDs currently remembered.
+    pub fn len(&self) -> usize {
+        self.last_seen.len()
+    }
+
+    /// Whether no control IDs are currently remembered.
+    pub fn is_empty(&self) -> bool {
+        self.last_seen.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(control_id: &str) -> Vec<u8> {
+        format!(
+            "MSH|^~\\&|ZIS|1^AHospital|||200405141144||ADT^A01|{}|P|2.3\r",
+            control_id
+        )
+        .into_bytes()
+    }
+
+    #[test]
+    fn second_delivery_is_duplicate() {
+        let mut cache = DedupCache::new(8);
+
+        assert!(!cache.is_duplicate(&message("20041104082400")));
+        assert!(cache.is_duplicate(&message("20041104082400")));
+        assert!(!cache.is_duplicate(&message("20041104082401")));
+    }
+
+    #[test]
+    fn least_recently_seen_is_evicted() {
+        let mut cache = DedupCache::new(2);
+
+        cache.check_control_id("A");
+        cache.check_control_id("B");
+        assert!(cache.check_control_id("A")); // A is now more recent than B
+        cache.check_control_id("C"); // evicts B
+
+        assert_eq!(cache.len(), 2);
+        assert!(cache.check_control_id("A"));
+        assert!(!cache.check_control_id("B"));
+    }
+
+    #[test]
+    fn unbounded_capacity_allocates_nothing_up_front() {
+        let mut cache = DedupCache::new(usize::MAX);
+        let frame = b"MSH|^~\\&|ZIS|1^AHospital|||200405141144||ADT^A01|ID1|P|2.3\r";
+        assert!(!cache.is_duplicate(frame));
+        assert!(cache.is_duplicate(frame));
+    }
+
+    #[test]
+    fn frames_without_control_id_are_not_duplicates() {
+        let mut cache = DedupCache::new(8);
+        let frame = b"MSH|^~\\&|ZIS|1^AHospital|||200405141144||ADT^A01||P|2.3\r";
+
+        assert!(!cache.is_duplicate(frame));
+        assert!(!cache.is_duplicate(frame));
+        assert!(cache.is_empty());
+    }
+}
@@ -0,0 +1,227 @@
+use std::fmt;
+
+/// Errors raised by this crate, on top of any IO errors from the underlying transport.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum MllpError {
+    /// An error from the underlying transport.
+    Io(std::io::Error),
+    /// A second message was sent before the ACK/NAK for the previous message was received, which the MLLP spec forbids.
+    AckOutstanding,
+    /// The transport closed while we were still expecting a frame.
+    ConnectionClosed,
+    /// A frame was started but not completed within the configured deadline.
+    FrameTimeout,
+    /// A frame was started, but no further bytes arrived within the configured inactivity period.
+    FrameStalled,
+    /// The ACK for the last message sent didn't arrive within the allowed time.
+    AckTimeout,
+    /// A decoded frame was rejected by the configured [FrameValidator](crate::FrameValidator).
+    InvalidFrame(String),
+    /// A frame's content exceeded the configured max frame size.  For frames whose footer hasn't arrived yet `size` is
+    /// the amount received so far.
+    FrameTooLarge {
+        /// The size of the frame content, in bytes.
+        size: usize,
+        /// The configured maximum, in bytes.
+        max: usize,
+    },
+    /// A frame's content was shorter than the configured [min_frame_size](crate::MllpCodecBuilder::min_frame_size),
+    /// suggesting it was truncated.
+    FrameTooSmall {
+        /// The size of the frame content, in bytes.
+        size: usize,
+        /// The configured minimum, in bytes.
+        min: usize,
+    },
+    /// More bytes arrived without a header than the configured
+    /// [max_prefix_skip](crate::MllpCodecBuilder::max_prefix_skip), so the peer is sending junk rather than frames.
+    PrefixTooLong {
+        /// The number of junk bytes buffered, all of which were discarded.
+        size: usize,
+        /// The configured maximum, in bytes.
+        max: usize,
+    },
+    /// More complete frames were buffered than the configured maximum, indicating a sender pipelining frames faster
+    /// than they're being consumed.
+    TooManyFrames {
+        /// The number of complete frames buffered.
+        count: usize,
+        /// The configured maximum.
+        max: usize,
+    },
+    /// The start of a second frame arrived behind a complete one, meaning the sender didn't wait for the first frame's
+    /// ACK, see [one_frame_per_buffer](crate::MllpCodecBuilder::one_frame_per_buffer).
+    PipelinedFrame,
+    /// A frame decoded as text wasn't valid UTF-8.
+    InvalidUtf8(std::str::Utf8Error),
+    /// A payload given to encode already had the MLLP framing, see
+    /// [reject_framed_payloads](crate::MllpCodecBuilder::reject_framed_payloads).
+    AlreadyFramed,
+    /// A [MllpCodecBuilder](crate::MllpCodecBuilder) was given options that can't work together.
+    InvalidConfig(String),
+    /// A frame's message type (MSH-9) isn't in the configured
+    /// [allowlist](crate::MllpCodecBuilder::allowed_message_types).  `message_type` is `None` if the frame didn't have one.
+    DisallowedMessageType {
+        /// The frame's message type, eg `ORU^R01`.
+        message_type: Option<String>,
+    },
+    /// A frame's [checksum](crate::MllpCodecBuilder::frame_checksum) didn't match its content, so it was corrupted in
+    /// transit.  A frame with no checksum at all is reported with `expected: None`.
+    ChecksumMismatch {
+        /// The checksum the frame carried, if it had one.
+        expected: Option<u32>,
+        /// The checksum of the content actually received.
+        actual: u32,
+    },
+}
+
+impl MllpError {
+    /// Whether this is a failure of the underlying transport (including it closing mid-frame), which may be transient
+    /// and so worth retrying on a new connection.
+    pub fn is_io(&self) -> bool {
+        matches!(self, MllpError::Io(_) | MllpError::ConnectionClosed)
+    }
+
+    /// Whether this is the peer breaking the protocol (a malformed, oversized or rejected frame), after which the
+    /// connection is generally best dropped.  Misuse of the crate itself (eg [InvalidConfig](MllpError::InvalidConfig))
+    /// is neither IO nor protocol.
+    pub fn is_protocol(&self) -> bool {
+        matches!(
+            self,
+            MllpError::FrameTimeout
+                | MllpError::FrameStalled
+                | MllpError::InvalidFrame(_)
+                | MllpError::FrameTooLarge { .. }
+                | MllpError::FrameTooSmall { .. }
+                | MllpError::PrefixTooLong { .. }
+                | MllpError::TooManyFrames { .. }
+                | MllpError::PipelinedFrame
+                | MllpError::InvalidUtf8(_)
+                | MllpError::DisallowedMessageType { .. }
+                | MllpError::ChecksumMismatch { .. }
+        )
+    }
+}
+
+impl fmt::Display for MllpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MllpError::Io(e) => write!(f, "MLLP transport error: {}", e),
+            MllpError::AckOutstanding => write!(
+                f,
+                "MLLP: cannot send a message while the previous message's ack is outstanding"
+            ),
+            MllpError::ConnectionClosed => write!(f, "MLLP: connection closed unexpectedly"),
+            MllpError::FrameTimeout => write!(f, "MLLP: frame not completed within the deadline"),
+            MllpError::AckTimeout => write!(f, "MLLP: ack not received within the timeout"),
+            MllpError::FrameStalled => write!(f, "MLLP: no progress on a partial frame"),
+            MllpError::InvalidFrame(reason) => write!(f, "MLLP: invalid frame: {}", reason),
+            MllpError::FrameTooLarge { size, max } => write!(
+                f,
+                "MLLP: frame of {} bytes exceeds the max frame size of {} bytes",
+                size, max
+            ),
+            MllpError::FrameTooSmall { size, min } => write!(
+                f,
+                "MLLP: frame of {} bytes is under the min frame size of {} bytes",
+                size, min
+            ),
+            MllpError::PrefixTooLong { size, max } => write!(
+                f,
+                "MLLP: {} bytes received without a block header, exceeding the max of {}",
+                size, max
+            ),
+            MllpError::TooManyFrames { count, max } => write!(
+                f,
+                "MLLP: {} complete frames buffered, exceeding the max of {}",
+                count, max
+            ),
+            MllpError::PipelinedFrame => {
+                write!(
+                    f,
+                    "MLLP: second frame received before the first was acknowledged"
+                )
+            }
+            MllpError::InvalidUtf8(e) => write!(f, "MLLP: frame is not valid UTF-8: {}", e),
+            MllpError::AlreadyFramed => write!(f, "MLLP: payload is already MLLP framed"),
+            MllpError::InvalidConfig(reason) => {
+                write!(f, "MLLP: invalid configuration: {}", reason)
+            }
+            MllpError::DisallowedMessageType {
+                message_type: Some(message_type),
+            } => write!(f, "MLLP: message type {} is not allowed", message_type),
+            MllpError::DisallowedMessageType { message_type: None } => {
+                write!(f, "MLLP: frame has no message type, so is not allowed")
+            }
+            MllpError::ChecksumMismatch {
+                expected: Some(expected),
+                actual,
+            } => write!(
+                f,
+                "MLLP: frame checksum {:08X} doesn't match its content ({:08X})",
+                expected, actual
+            ),
+            MllpError::ChecksumMismatch {
+                expected: None,
+                actual,
+            } => write!(
+                f,
+                "MLLP: frame has no checksum (content checksum {:08X})",
+                actual
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MllpError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MllpError::Io(e) => Some(e),
+            MllpError::InvalidUtf8(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for MllpError {
+    fn from(e: std::io::Error) -> Self {
+        MllpError::Io(e)
+    }
+}
+
+/// Transport errors are unwrapped, anything else becomes an [InvalidData](std::io::ErrorKind::InvalidData) error.
+impl From<MllpError> for std::io::Error {
+    fn from(e: MllpError) -> Self {
+        match e {
+            MllpError::Io(e) => e,
+            e => std::io::Error::new(std::io::ErrorKind::InvalidData, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MllpCodec;
+    use bytes::BytesMut;
+    use tokio_util::codec::Decoder;
+
+    #[test]
+    fn classifies_protocol_and_io_errors() {
+        let mut codec = MllpCodec::builder().max_frame_size(4).build().unwrap();
+        let oversized = codec
+            .decode(&mut BytesMut::from("\x0Babcdefgh\x1C\x0D"))
+            .unwrap_err();
+        assert!(matches!(oversized, MllpError::FrameTooLarge { .. }));
+        assert!(oversized.is_protocol());
+        assert!(!oversized.is_io());
+
+        let io: MllpError = std::io::Error::from(std::io::ErrorKind::ConnectionReset).into();
+        assert!(io.is_io());
+        assert!(!io.is_protocol());
+
+        let config = MllpError::InvalidConfig("header in footer".to_owned());
+        assert!(!config.is_io() && !config.is_protocol());
+    }
+}
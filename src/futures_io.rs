@@ -0,0 +1,187 @@
+use crate::{MllpCodec, MllpError};
+use bytes::{Buf, BytesMut};
+use futures::io::{AsyncRead, AsyncWrite};
+use futures::{Sink, Stream};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio_util::codec::{Decoder, Encoder};
+
+const READ_CHUNK: usize = 8 * 1024;
+// how much encoded data can be waiting to be written before the sink stops taking frames
+const BACKPRESSURE_BOUNDARY: usize = 8 * 1024;
+
+/// Frames an IO stream implementing the `futures` IO traits (as async-std's `TcpStream` does), for those not running
+/// tokio.  This is the equivalent of wrapping a tokio stream in a `Framed` with a [MllpCodec], and does the framing
+/// with the same codec: a `Stream` of decoded frames, and a `Sink` of payloads to frame and send.
+///
+/// Requires the `futures-io` crate feature.
+/// Example:
+/// ```
+/// use bytes::BytesMut;
+/// use futures::{io::Cursor, SinkExt, StreamExt};
+/// use hl7_mllp_codec::FuturesFramed;
+///
+/// futures::executor::block_on(async {
+///     let mut transport = FuturesFramed::new(Cursor::new(b"\x0BHello\x1C\x0D".to_vec()));
+///     assert_eq!(transport.next().await.unwrap().unwrap(), "Hello");
+///     transport.send(BytesMut::from("\x06")).await.unwrap();
+/// });
+/// ```
+pub struct FuturesFramed<T> {
+    io: T,
+    codec: MllpCodec,
+    read_buf: BytesMut,
+    write_buf: BytesMut,
+    eof: bool,
+}
+
+impl<T> FuturesFramed<T> {
+    /// Frames `io` with a default [MllpCodec].
+    pub fn new(io: T) -> Self {
+        FuturesFramed::with_codec(io, MllpCodec::new())
+    }
+
+    /// Frames `io` with a configured codec.
+    pub fn with_codec(io: T, codec: MllpCodec) -> Self {
+        FuturesFramed {
+            io,
+            codec,
+            read_buf: BytesMut::new(),
+            write_buf: BytesMut::new(),
+            eof: false,
+        }
+    }
+
+    /// Returns the codec, eg to check its [stats](MllpCodec::stats).
+    pub fn codec(&self) -> &MllpCodec {
+        &self.codec
+    }
+
+    /// Consumes the transport, returning the underlying IO stream.  Anything read but not yet decoded, or encoded but
+    /// not yet flushed, is lost.
+    pub fn into_inner(self) -> T {
+        self.io
+    }
+}
+
+impl<T: AsyncRead + Unpin> Stream for FuturesFramed<T> {
+    type Item = Result<BytesMut, MllpError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.eof {
+            return Poll::Ready(None);
+        }
+
+        let mut chunk = [0u8; READ_CHUNK];
+        loop {
+            match this.codec.decode(&mut this.read_buf) {
+                Ok(Some(frame)) => return Poll::Ready(Some(Ok(frame))),
+                Ok(None) => {}
+                Err(e) => return Poll::Ready(Some(Err(e))),
+            }
+
+            match Pin::new(&mut this.io).poll_read(cx, &mut chunk) {
+                Poll::Ready(Ok(0)) => {
+                    // a truncated frame is only reported the once
+                    let truncated = this.codec.buffered_len() > 0 || !this.read_buf.is_empty();
+                    this.eof = true;
+                    return Poll::Ready(truncated.then(|| Err(MllpError::ConnectionClosed)));
+                }
+                Poll::Ready(Ok(read)) => this.read_buf.extend_from_slice(&chunk[..read]),
+                Poll::Ready(Err(e)) => return Poll::Ready(Some(Err(e.into()))),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl<T: AsyncWrite + Unpin> FuturesFramed<T> {
+    /// Writes out everything encoded so far
+    fn poll_write_buf(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), MllpError>> {
+        while !self.write_buf.is_empty() {
+            match Pin::new(&mut self.io).poll_write(cx, &self.write_buf) {
+                Poll::Ready(Ok(0)) => return Poll::Ready(Err(MllpError::ConnectionClosed)),
+                Poll::Ready(Ok(written)) => self.write_buf.advance(written),
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e.into())),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<T: AsyncWrite + Unpin> Sink<BytesMut> for FuturesFramed<T> {
+    type Error = MllpError;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+        if this.write_buf.len() >= BACKPRESSURE_BOUNDARY {
+            return this.poll_write_buf(cx);
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: BytesMut) -> Result<(), Self::Error> {
+        let this = self.get_mut();
+        this.codec.encode(item, &mut this.write_buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+        match this.poll_write_buf(cx) {
+            Poll::Ready(Ok(())) => Pin::new(&mut this.io).poll_flush(cx).map_err(Into::into),
+            other => other,
+        }
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+        match this.poll_write_buf(cx) {
+            Poll::Ready(Ok(())) => Pin::new(&mut this.io).poll_close(cx).map_err(Into::into),
+            other => other,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use futures::io::Cursor;
+    use futures::{SinkExt, StreamExt};
+
+    #[test]
+    fn decodes_frames_from_futures_io() {
+        block_on(async {
+            // each read is whole frames, as from a publisher waiting on its acks
+            let mut transport = FuturesFramed::new(Cursor::new(b"junk\x0BHello\x1C\x0D".to_vec()));
+            assert_eq!(transport.next().await.unwrap().unwrap(), "Hello");
+            assert!(transport.next().await.is_none());
+            assert_eq!(transport.codec().stats().frames_decoded, 1);
+        });
+    }
+
+    #[test]
+    fn truncated_frame_is_an_error() {
+        block_on(async {
+            let mut transport = FuturesFramed::new(Cursor::new(b"\x0BHel".to_vec()));
+            assert!(matches!(
+                transport.next().await,
+                Some(Err(MllpError::ConnectionClosed))
+            ));
+            assert!(transport.next().await.is_none());
+        });
+    }
+
+    #[test]
+    fn encodes_frames_to_futures_io() {
+        block_on(async {
+            let mut transport = FuturesFramed::new(Cursor::new(Vec::new()));
+            transport.send(BytesMut::from("Hello")).await.unwrap();
+            transport.send(BytesMut::from("World")).await.unwrap();
+            let written = transport.into_inner().into_inner();
+            assert_eq!(&written[..], b"\x0BHello\x1C\x0D\x0BWorld\x1C\x0D");
+        });
+    }
+}
@@ -0,0 +1,1049 @@
+//! Lightweight helpers for peeking at the HL7 content of a decoded frame.
+//!
+//! These are *not* a HL7 parser, they just walk the MSH segment far enough to pull out the handful of fields that
+//! are useful at the transport level (control IDs, versions etc).  If you need more than that, hand the frame off to
+//! a proper HL7 library.
+
+use crate::MllpError;
+use std::borrow::Cow;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The ID of the message header segment, which must be the first segment of every HL7 message.
+const MSH: &[u8] = b"MSH";
+
+/// The ID of the continuation pointer segment, which ends each part of a message split across several.
+const DSC: &[u8] = b"DSC";
+
+/// Tidies up segment IDs from buggy senders, so the (strict) helpers in this module can find them: leading spaces and
+/// tabs before each segment are dropped, and lowercase segment IDs are uppercased (so ` msh|...` becomes `MSH|...`).
+/// Nothing beyond the segment IDs is touched, and the frame is only copied if something needs fixing.
+/// Example:
+/// ```
+/// use hl7_mllp_codec::hl7::{control_id, normalize_segment_ids};
+///
+/// let frame = b" msh|^~\\&|ZIS||||||ADT^A01|ID123|P|2.3\r";
+/// assert_eq!(control_id(frame), None);
+/// assert_eq!(control_id(&normalize_segment_ids(frame)), Some("ID123"));
+/// ```
+pub fn normalize_segment_ids(frame: &[u8]) -> Cow<'_, [u8]> {
+    let needs_fixing = |segment: &[u8]| {
+        matches!(segment.first(), Some(b' ') | Some(b'\t'))
+            || segment.iter().take(MSH.len()).any(u8::is_ascii_lowercase)
+    };
+    if !frame.split(is_segment_separator).any(needs_fixing) {
+        return Cow::Borrowed(frame);
+    }
+
+    let mut normalized = Vec::with_capacity(frame.len());
+    let mut rest = frame;
+    loop {
+        let end = rest
+            .iter()
+            .position(is_segment_separator)
+            .unwrap_or(rest.len());
+        let (segment, tail) = rest.split_at(end);
+
+        let start = segment
+            .iter()
+            .position(|b| *b != b' ' && *b != b'\t')
+            .unwrap_or(segment.len());
+        let segment = &segment[start..];
+        let id_len = segment.len().min(MSH.len());
+        normalized.extend(segment[..id_len].iter().map(u8::to_ascii_uppercase));
+        normalized.extend_from_slice(&segment[id_len..]);
+
+        match tail.split_first() {
+            Some((separator, tail)) => {
+                normalized.push(*separator);
+                rest = tail;
+            }
+            None => return Cow::Owned(normalized),
+        }
+    }
+}
+
+/// CR is the HL7 segment separator, but LF turns up in files often enough to split on too
+fn is_segment_separator(b: &u8) -> bool {
+    *b == b'\r' || *b == b'\n'
+}
+
+/// Returns the number of segments in a frame, as a cheap structural summary for logging.  Empty segments (after the
+/// final separator, or between the CR and LF of CRLF terminators) aren't counted, so an empty frame has none.
+/// Example:
+/// ```
+/// use hl7_mllp_codec::hl7::segment_count;
+/// assert_eq!(segment_count(b"MSH|^~\\&|ZIS\rEVN|A01\r"), 2);
+/// ```
+pub fn segment_count(frame: &[u8]) -> usize {
+    frame
+        .split(is_segment_separator)
+        .filter(|segment| !segment.is_empty())
+        .count()
+}
+
+/// Returns the MSH segment from a frame, up to (but not including) the segment separator.
+fn msh_segment(frame: &[u8]) -> Option<&[u8]> {
+    segment_at(frame, MSH).map(|(_, segment)| segment)
+}
+
+/// Returns the first segment with ID `id` (and at least a field separator after it), along with its offset within the
+/// frame
+fn segment_at<'a>(frame: &'a [u8], id: &[u8]) -> Option<(usize, &'a [u8])> {
+    let mut start = 0;
+    for segment in frame.split(is_segment_separator) {
+        if segment.starts_with(id) && segment.len() > id.len() {
+            return Some((start, segment));
+        }
+        start += segment.len() + 1;
+    }
+    None
+}
+
+/// Returns the value of field `field` of the MSH segment, using the field separator the message itself declares.
+///
+/// Numbering follows the HL7 convention, so `msh_field(frame, 1)` is the field separator itself, `msh_field(frame, 2)` the
+/// encoding characters, and `msh_field(frame, 10)` the message control ID.  Returns `None` if there's no MSH segment, the
+/// segment is too short, or the field isn't valid UTF-8.
+/// Example:
+/// ```
+/// use hl7_mllp_codec::hl7::msh_field;
+/// let frame = b"MSH|^~\\&|ZIS|1^AHospital|||200405141144||ADT^A01|20041104082400|P|2.3\r";
+/// assert_eq!(msh_field(frame, 9), Some("ADT^A01"));
+/// ```
+pub fn msh_field(frame: &[u8], field: usize) -> Option<&str> {
+    try_msh_field(frame, field).ok()
+}
+
+/// Why a MSH field couldn't be read, see [try_msh_field].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MshParseError {
+    /// The frame has no MSH segment.
+    NoMshSegment,
+    /// The MSH segment declares a field separator (MSH-1) that can't be one, eg a letter or digit.
+    BadSeparator(u8),
+    /// The MSH segment ends before the field asked for.
+    TooFewFields {
+        /// The field asked for, eg `10` for the control ID.
+        needed: usize,
+        /// How many fields the segment has, counting MSH-1.
+        found: usize,
+    },
+    /// The field asked for isn't one, as fields are numbered from `1` (MSH-1, the field separator).
+    InvalidField {
+        /// The field asked for.
+        field: usize,
+    },
+    /// The field isn't valid UTF-8.
+    NotUtf8 {
+        /// The field asked for.
+        field: usize,
+    },
+}
+
+impl fmt::Display for MshParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MshParseError::NoMshSegment => write!(f, "no MSH segment"),
+            MshParseError::BadSeparator(separator) => {
+                write!(f, "{:?} can't be the field separator", *separator as char)
+            }
+            MshParseError::TooFewFields { needed, found } => write!(
+                f,
+                "MSH-{} needed, but the segment only has {} fields",
+                needed, found
+            ),
+            MshParseError::InvalidField { field } => {
+                write!(f, "MSH-{} isn't a field, they're numbered from 1", field)
+            }
+            MshParseError::NotUtf8 { field } => write!(f, "MSH-{} is not valid UTF-8", field),
+        }
+    }
+}
+
+impl std::error::Error for MshParseError {}
+
+/// [msh_field], but saying why the field couldn't be read, for diagnosing malformed messages.  Field numbering starts at
+/// `1` (MSH-1, the field separator), asking for field `0` is an [MshParseError::InvalidField].
+/// Example:
+/// ```
+/// use hl7_mllp_codec::hl7::{try_msh_field, MshParseError};
+///
+/// let frame = b"MSH|^~\\&|ZIS|1^AHospital|\r";
+/// assert_eq!(try_msh_field(frame, 3), Ok("ZIS"));
+/// assert_eq!(
+///     try_msh_field(frame, 10),
+///     Err(MshParseError::TooFewFields { needed: 10, found: 5 })
+/// );
+/// ```
+pub fn try_msh_field(frame: &[u8], field: usize) -> Result<&str, MshParseError> {
+    if field == 0 {
+        return Err(MshParseError::InvalidField { field });
+    }
+    let segment = msh_segment(frame).ok_or(MshParseError::NoMshSegment)?;
+    let separator = segment[MSH.len()];
+    if separator.is_ascii_alphanumeric() || separator.is_ascii_whitespace() {
+        return Err(MshParseError::BadSeparator(separator));
+    }
+
+    let value = match field {
+        1 => &segment[MSH.len()..=MSH.len()],
+        // MSH-1 _is_ the separator, so the first value after it is MSH-2
+        _ => {
+            let mut fields = segment[MSH.len() + 1..].split(|b| *b == separator);
+            match fields.nth(field - 2) {
+                Some(value) => value,
+                None => {
+                    let found = segment[MSH.len() + 1..].split(|b| *b == separator).count() + 1; // MSH-1
+                    return Err(MshParseError::TooFewFields {
+                        needed: field,
+                        found,
+                    });
+                }
+            }
+        }
+    };
+
+    std::str::from_utf8(value).map_err(|_| MshParseError::NotUtf8 { field })
+}
+
+/// Returns component `component` of field `field` of the first `segment` segment, splitting on the component separator
+/// the message declares in MSH-2 rather than assuming `^`.
+///
+/// Fields and components are numbered from `1` as in HL7, so `field_component(frame, "PID", 5, 2)` is PID-5.2 (the
+/// given name).  MSH fields are numbered as for [msh_field].  A repeating field yields the components of its first
+/// repetition, and a field without any component separators is its own first component.  Returns `None` if there's no
+/// MSH segment, no such segment, or the field or component is missing; an empty component comes back as `Some("")`.
+/// Example:
+/// ```
+/// use hl7_mllp_codec::hl7::field_component;
+/// let frame = b"MSH|^~\\&|ZIS||||||ADT^A01|1|P|2.3\rPID|1||123||Vries^Danny^D.\r";
+/// assert_eq!(field_component(frame, "PID", 5, 2), Some("Danny"));
+/// assert_eq!(field_component(frame, "MSH", 9, 2), Some("A01"));
+/// ```
+pub fn field_component<'a>(
+    frame: &'a [u8],
+    segment: &str,
+    field: usize,
+    component: usize,
+) -> Option<&'a str> {
+    let (component_separator, repetition_separator, _) = encoding_characters(frame)?;
+    let value = segment_field(frame, segment, field)?;
+    let first_repetition = value.split(repetition_separator).next()?;
+    match component {
+        0 => None,
+        _ => first_repetition
+            .split(component_separator)
+            .nth(component - 1),
+    }
+}
+
+/// Returns subcomponent `subcomponent` of [component](field_component) `component` of field `field` of the first
+/// `segment` segment, splitting on the subcomponent separator the message declares in MSH-2 rather than assuming `&`.
+/// Example:
+/// ```
+/// use hl7_mllp_codec::hl7::field_subcomponent;
+/// let frame = b"MSH|^~\\&|ZIS||||||ADT^A01|1|P|2.3\rPID|1||123^^^AHospital&1.2.3&ISO\r";
+/// assert_eq!(field_subcomponent(frame, "PID", 3, 4, 2), Some("1.2.3"));
+/// ```
+pub fn field_subcomponent<'a>(
+    frame: &'a [u8],
+    segment: &str,
+    field: usize,
+    component: usize,
+    subcomponent: usize,
+) -> Option<&'a str> {
+    let (_, _, subcomponent_separator) = encoding_characters(frame)?;
+    let value = field_component(frame, segment, field, component)?;
+    match subcomponent {
+        0 => None,
+        _ => value.split(subcomponent_separator).nth(subcomponent - 1),
+    }
+}
+
+/// Returns the component, repetition and subcomponent separators declared in MSH-2, falling back to the standard
+/// `^`, `~` and `&` for any the message leaves out.
+fn encoding_characters(frame: &[u8]) -> Option<(char, char, char)> {
+    let mut declared = msh_field(frame, 2)?.chars();
+    let component = declared.next().unwrap_or('^');
+    let repetition = declared.next().unwrap_or('~');
+    let subcomponent = declared.nth(1).unwrap_or('&'); // skipping the escape character
+    Some((component, repetition, subcomponent))
+}
+
+/// Returns the raw value of field `field` of the first `segment` segment, numbered from `1` as in HL7.
+fn segment_field<'a>(frame: &'a [u8], segment: &str, field: usize) -> Option<&'a str> {
+    if segment.as_bytes() == MSH {
+        return msh_field(frame, field);
+    }
+
+    let separator = *msh_segment(frame)?.get(MSH.len())?;
+    let id = segment.as_bytes();
+    let found = frame.split(is_segment_separator).find(|candidate| {
+        candidate.starts_with(id) && candidate.get(id.len()) == Some(&separator)
+    })?;
+    match field {
+        0 => None,
+        _ => std::str::from_utf8(found.split(|b| *b == separator).nth(field)?).ok(),
+    }
+}
+
+/// Returns the message control ID (MSH-10), or `None` if it's missing or empty.
+pub fn control_id(frame: &[u8]) -> Option<&str> {
+    msh_field(frame, 10).filter(|id| !id.is_empty())
+}
+
+/// Fills in an empty (or missing) message control ID (MSH-10) with `id`, padding out any missing fields before it with
+/// the declared field separator.  A frame that already has a control ID, or has no MSH segment at all, is returned
+/// untouched, and the frame is only copied if something needs filling in.
+/// Example:
+/// ```
+/// use hl7_mllp_codec::hl7::{control_id, fill_control_id};
+///
+/// let frame = b"MSH|^~\\&|ZIS||||||ADT^A01||P|2.3\r";
+/// assert_eq!(control_id(&fill_control_id(frame, "ID123")), Some("ID123"));
+///
+/// let short = b"MSH|^~\\&|ZIS\r";
+/// assert_eq!(control_id(&fill_control_id(short, "ID123")), Some("ID123"));
+/// ```
+pub fn fill_control_id<'a>(frame: &'a [u8], id: &str) -> Cow<'a, [u8]> {
+    const CONTROL_ID: usize = 10;
+    if control_id(frame).is_some() {
+        return Cow::Borrowed(frame);
+    }
+    let (start, segment) = match segment_at(frame, MSH) {
+        Some(found) => found,
+        None => return Cow::Borrowed(frame),
+    };
+    let separator = segment[MSH.len()];
+
+    // walk to the start of MSH-10, or the end of the segment if it runs out of fields first
+    let mut insert_at = MSH.len() + 1;
+    let mut field = 2;
+    while field < CONTROL_ID {
+        match segment[insert_at..].iter().position(|b| *b == separator) {
+            Some(offset) => insert_at += offset + 1,
+            None => break,
+        }
+        field += 1;
+    }
+    if field == CONTROL_ID && matches!(segment.get(insert_at), Some(b) if *b != separator) {
+        return Cow::Borrowed(frame); // MSH-10 has content, just not valid UTF-8
+    }
+    let insert_at = if field == CONTROL_ID {
+        insert_at
+    } else {
+        segment.len()
+    };
+
+    let mut filled = Vec::with_capacity(frame.len() + CONTROL_ID + id.len());
+    filled.extend_from_slice(&frame[..start + insert_at]);
+    filled.resize(filled.len() + CONTROL_ID - field, separator); // the fields it's missing ahead of MSH-10
+    filled.extend_from_slice(id.as_bytes());
+    filled.extend_from_slice(&frame[start + insert_at..]);
+    Cow::Owned(filled)
+}
+
+/// Returns the sending facility (MSH-4), ignoring any further components (so just the namespace ID of `1^AHospital`).
+pub fn sending_facility(frame: &[u8]) -> Option<&str> {
+    first_component(frame, 4)
+}
+
+/// Returns the receiving facility (MSH-6), ignoring any further components.
+pub fn receiving_facility(frame: &[u8]) -> Option<&str> {
+    first_component(frame, 6)
+}
+
+/// Returns the message code of the message type (MSH-9.1), eg `ADT` for an `ADT^A01`.
+pub fn message_type(frame: &[u8]) -> Option<&str> {
+    first_component(frame, 9)
+}
+
+/// Returns the trigger event of the message type (MSH-9.2), eg `A01` for an `ADT^A01`.
+pub fn trigger_event(frame: &[u8]) -> Option<&str> {
+    let component_separator = msh_field(frame, 2)?.chars().next().unwrap_or('^');
+    msh_field(frame, 9)?
+        .split(component_separator)
+        .nth(1)
+        .filter(|value| !value.is_empty())
+}
+
+/// Returns the processing ID (MSH-11.1): `P` for production, `T` for training or `D` for debugging.  Handy for keeping
+/// production messages off a test endpoint, and vice versa.
+pub fn processing_id(frame: &[u8]) -> Option<&str> {
+    first_component(frame, 11)
+}
+
+/// Returns the HL7 version the message declares in MSH-12 (eg `2.3`, `2.5.1`), ignoring any further components.
+pub fn hl7_version(frame: &[u8]) -> Option<&str> {
+    first_component(frame, 12)
+}
+
+/// Returns the accept acknowledgment type (MSH-15), eg `AL`, `NE`, `ER` or `SU`, or `None` if it's missing or empty.
+/// Either this or MSH-16 being present puts the message in enhanced acknowledgment mode.
+pub fn accept_ack_type(frame: &[u8]) -> Option<&str> {
+    first_component(frame, 15)
+}
+
+/// Returns the application acknowledgment type (MSH-16), eg `AL`, `NE`, `ER` or `SU`, or `None` if it's missing or empty.
+pub fn application_ack_type(frame: &[u8]) -> Option<&str> {
+    first_component(frame, 16)
+}
+
+/// Returns the first component of the message date/time (MSH-7), ie the raw timestamp, eg `20041104082400.0000+0100`,
+/// or `None` if it's missing or empty.  Any further components (eg the degree of precision older versions allow) are
+/// dropped.
+pub fn message_datetime(frame: &[u8]) -> Option<&str> {
+    first_component(frame, 7)
+}
+
+/// A HL7 `DTM` timestamp, broken into its parts.  HL7 allows timestamps to be truncated to any precision from the
+/// year down, so everything below the year is optional.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hl7Timestamp {
+    /// The four digit year.
+    pub year: u16,
+    /// The month, 1-12.
+    pub month: Option<u8>,
+    /// The day of the month, 1-31.
+    pub day: Option<u8>,
+    /// The hour, 0-23.
+    pub hour: Option<u8>,
+    /// The minute, 0-59.
+    pub minute: Option<u8>,
+    /// The second, 0-59.
+    pub second: Option<u8>,
+    /// Fractional seconds, in nanoseconds.
+    pub nanosecond: Option<u32>,
+    /// The offset from UTC in minutes, if the timestamp declares one (eg `+0100` is 60).
+    pub utc_offset_minutes: Option<i16>,
+}
+
+impl Hl7Timestamp {
+    /// Parses a HL7 timestamp of the form `YYYY[MM[DD[HH[MM[SS[.S[S[S[S]]]]]]]]][+/-ZZZZ]`, returning `None` if it's malformed.
+    /// A date that doesn't exist (eg `20230229`, or the 31st of April) is malformed.
+    /// Example:
+    /// ```
+    /// use hl7_mllp_codec::hl7::Hl7Timestamp;
+    ///
+    /// let ts = Hl7Timestamp::parse("20041104082400.0000+0100").unwrap();
+    /// assert_eq!((ts.year, ts.month, ts.day), (2004, Some(11), Some(4)));
+    /// assert_eq!(ts.utc_offset_minutes, Some(60));
+    /// ```
+    pub fn parse(value: &str) -> Option<Hl7Timestamp> {
+        let (value, utc_offset_minutes) = match value.find(['+', '-']) {
+            Some(i) => (&value[..i], Some(parse_offset(&value[i..])?)),
+            None => (value, None),
+        };
+
+        let (value, nanosecond) = match value.split_once('.') {
+            Some((whole, fraction)) => (whole, Some(parse_fraction(fraction)?)),
+            None => (value, None),
+        };
+
+        // the fixed width parts must be complete pairs of digits after the year
+        if value.len() < 4 || value.len() > 14 || value.len() % 2 != 0 || !is_digits(value) {
+            return None;
+        }
+        if nanosecond.is_some() && value.len() != 14 {
+            return None; // fractions only make sense with seconds
+        }
+
+        let part = |start: usize| {
+            value
+                .get(start..start + 2)
+                .map(|p| p.parse::<u8>().unwrap())
+        };
+        let timestamp = Hl7Timestamp {
+            year: value[..4].parse().ok()?,
+            month: part(4),
+            day: part(6),
+            hour: part(8),
+            minute: part(10),
+            second: part(12),
+            nanosecond,
+            utc_offset_minutes,
+        };
+
+        // a part that's been truncated away is fine, one that's there has to make sense
+        let in_range = |v: Option<u8>, min: u8, max: u8| match v {
+            Some(v) => (min..=max).contains(&v),
+            None => true,
+        };
+        let last_day = timestamp
+            .month
+            .map_or(31, |month| days_in_month(timestamp.year, month));
+        if in_range(timestamp.month, 1, 12)
+            && in_range(timestamp.day, 1, last_day)
+            && in_range(timestamp.hour, 0, 23)
+            && in_range(timestamp.minute, 0, 59)
+            && in_range(timestamp.second, 0, 59)
+        {
+            Some(timestamp)
+        } else {
+            None
+        }
+    }
+}
+
+/// How many days `month` (1 to 12) of `year` has, allowing for leap years
+#[allow(clippy::manual_is_multiple_of)] // is_multiple_of needs Rust 1.87
+fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        4 | 6 | 9 | 11 => 30,
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        _ => 31,
+    }
+}
+
+/// Returns the message date/time (MSH-7) parsed into its parts, see [Hl7Timestamp::parse].
+pub fn message_timestamp(frame: &[u8]) -> Option<Hl7Timestamp> {
+    Hl7Timestamp::parse(message_datetime(frame)?)
+}
+
+/// The transport-relevant MSH fields of a message, pulled out in a single walk of the segment, for callers that want
+/// several of them and would rather not re-walk the MSH in each [helper](msh_field).  Each field holds what the
+/// corresponding helper would return.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MshHeader {
+    /// The sending facility (MSH-4), see [sending_facility].
+    pub sending_facility: Option<String>,
+    /// The receiving facility (MSH-6), see [receiving_facility].
+    pub receiving_facility: Option<String>,
+    /// The raw message date/time (MSH-7), see [message_datetime].
+    pub message_datetime: Option<String>,
+    /// The message type (MSH-9) in full, eg `ADT^A01`.
+    pub message_type: Option<String>,
+    /// The message control ID (MSH-10), see [control_id].
+    pub control_id: Option<String>,
+    /// The HL7 version (MSH-12), see [hl7_version].
+    pub version: Option<String>,
+}
+
+impl MshHeader {
+    /// Parses the MSH segment of `frame`, returning `None` if it doesn't have one.
+    /// Example:
+    /// ```
+    /// use hl7_mllp_codec::hl7::MshHeader;
+    ///
+    /// let header = MshHeader::parse(b"MSH|^~\\&|ZIS|1^AHospital|||200405141144||ADT^A01|ID123|P|2.3\r").unwrap();
+    /// assert_eq!(header.control_id.as_deref(), Some("ID123"));
+    /// assert_eq!(header.message_type.as_deref(), Some("ADT^A01"));
+    /// ```
+    pub fn parse(frame: &[u8]) -> Option<MshHeader> {
+        let segment = msh_segment(frame)?;
+        let separator = segment[MSH.len()];
+        // fields[0] is MSH-2, as for msh_field
+        let fields: Vec<&[u8]> = segment[MSH.len() + 1..]
+            .split(|b| *b == separator)
+            .collect();
+        let component_separator = fields
+            .first()
+            .and_then(|encoding| encoding.first())
+            .copied()
+            .unwrap_or(b'^');
+
+        let field = |n: usize| {
+            fields
+                .get(n - 2)
+                .and_then(|value| std::str::from_utf8(value).ok())
+                .filter(|value| !value.is_empty())
+        };
+        let first_component = |n: usize| {
+            field(n)
+                .and_then(|value| value.split(component_separator as char).next())
+                .filter(|value| !value.is_empty())
+                .map(str::to_owned)
+        };
+
+        Some(MshHeader {
+            sending_facility: first_component(4),
+            receiving_facility: first_component(6),
+            message_datetime: first_component(7),
+            message_type: field(9).map(str::to_owned),
+            control_id: field(10).map(str::to_owned),
+            version: first_component(12),
+        })
+    }
+}
+
+/// Returns the continuation pointer (DSC-1) of a message that's been split across several, or `None` if the message
+/// isn't continued.  The next part of the message carries the same pointer in MSH-14, see [Reassembler].
+/// Example:
+/// ```
+/// use hl7_mllp_codec::hl7::continuation_pointer;
+///
+/// let frame = b"MSH|^~\\&|LAB||||||ORU^R01|1|P|2.3\rOBX|1|TX|||Part one\rDSC|PTR0001|F\r";
+/// assert_eq!(continuation_pointer(frame), Some("PTR0001"));
+/// ```
+pub fn continuation_pointer(frame: &[u8]) -> Option<&str> {
+    let separator = *msh_segment(frame)?.get(MSH.len())?;
+    let (_, dsc) = segment_at(frame, DSC)?;
+    let pointer = dsc[DSC.len() + 1..].split(|b| *b == separator).next()?;
+    std::str::from_utf8(pointer).ok().filter(|p| !p.is_empty())
+}
+
+/// Stitches messages split across several frames by [DSC continuation pointers](continuation_pointer) back into one.
+///
+/// Each frame is [pushed](Reassembler::push) as it's decoded.  Frames that aren't part of a continued message come
+/// straight back out, while the parts of a continued message are held until the last part arrives, then returned as a
+/// single message: the first part's MSH and segments, followed by the segments (less their MSH) of each later part,
+/// with the DSC segments dropped.
+/// Example:
+/// ```
+/// use hl7_mllp_codec::hl7::Reassembler;
+///
+/// let mut reassembler = Reassembler::new();
+/// let first = b"MSH|^~\\&|LAB||||||ORU^R01|1|P|2.3\rOBX|1|TX|||Part one\rDSC|PTR0001|F\r";
+/// let second = b"MSH|^~\\&|LAB||||||ORU^R01|2|P|2.3||PTR0001\rOBX|2|TX|||Part two\r";
+///
+/// assert_eq!(reassembler.push(first).unwrap(), None);
+/// let message = reassembler.push(second).unwrap().unwrap();
+/// assert_eq!(
+///     message,
+///     &b"MSH|^~\\&|LAB||||||ORU^R01|1|P|2.3\rOBX|1|TX|||Part one\rOBX|2|TX|||Part two\r"[..]
+/// );
+/// ```
+#[derive(Debug, Default)]
+pub struct Reassembler {
+    /// The message so far, and the pointer the next part will carry
+    pending: Option<(Vec<u8>, String)>,
+}
+
+impl Reassembler {
+    /// Creates a reassembler with no message in progress.
+    pub fn new() -> Self {
+        Reassembler::default()
+    }
+
+    /// Whether part of a continued message is being held, waiting for the rest.
+    pub fn is_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Adds the next decoded frame, returning the complete message once there is one.  A frame that doesn't continue
+    /// the pending message (its MSH-14 doesn't match the pointer) fails with [MllpError::InvalidFrame], dropping the
+    /// pending parts, as the rest of that message is evidently not coming.
+    pub fn push(&mut self, frame: &[u8]) -> Result<Option<Vec<u8>>, MllpError> {
+        let mut message = match self.pending.take() {
+            None => frame.to_vec(),
+            Some((mut message, pointer)) => {
+                if msh_field(frame, 14) != Some(pointer.as_str()) {
+                    return Err(MllpError::InvalidFrame(format!(
+                        "expected the continuation of message {}, got {}",
+                        pointer,
+                        msh_field(frame, 14)
+                            .filter(|p| !p.is_empty())
+                            .unwrap_or("a new message")
+                    )));
+                }
+                if !matches!(message.last(), Some(b) if is_segment_separator(b)) {
+                    message.push(b'\r');
+                }
+                message.extend_from_slice(after_msh(frame));
+                message
+            }
+        };
+
+        match continuation_pointer(&message).map(str::to_owned) {
+            Some(pointer) => {
+                remove_segment(&mut message, DSC);
+                self.pending = Some((message, pointer));
+                Ok(None)
+            }
+            None => Ok(Some(message)),
+        }
+    }
+}
+
+/// The segments of a frame after its MSH segment
+fn after_msh(frame: &[u8]) -> &[u8] {
+    match segment_at(frame, MSH) {
+        Some((start, msh)) => frame.get(start + msh.len() + 1..).unwrap_or_default(),
+        None => frame,
+    }
+}
+
+/// Removes the first segment with ID `id`, along with its terminator
+fn remove_segment(frame: &mut Vec<u8>, id: &[u8]) {
+    if let Some((start, segment)) = segment_at(frame, id) {
+        let end = (start + segment.len() + 1).min(frame.len());
+        frame.drain(start..end);
+    }
+}
+
+fn is_digits(value: &str) -> bool {
+    value.bytes().all(|b| b.is_ascii_digit())
+}
+
+fn parse_offset(offset: &str) -> Option<i16> {
+    let (sign, digits) = offset.split_at(1);
+    if digits.len() != 4 || !is_digits(digits) {
+        return None;
+    }
+    let minutes = digits[..2].parse::<i16>().ok()? * 60 + digits[2..].parse::<i16>().ok()?;
+    Some(if sign == "-" { -minutes } else { minutes })
+}
+
+fn parse_fraction(fraction: &str) -> Option<u32> {
+    if fraction.is_empty() || fraction.len() > 4 || !is_digits(fraction) {
+        return None;
+    }
+    let scale = 10u32.pow(9 - fraction.len() as u32);
+    Some(fraction.parse::<u32>().ok()? * scale)
+}
+
+/// Generates a control ID that's unique within this process: the current time in milliseconds followed by a
+/// per-process sequence number, 19 digits in all so it fits the 20 character limit older HL7 versions put on MSH-10.
+pub(crate) fn generate_control_id() -> String {
+    static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis())
+        .unwrap_or_default();
+    let sequence = SEQUENCE.fetch_add(1, Ordering::Relaxed) % 1_000_000;
+    format!("{:013}{:06}", millis, sequence)
+}
+
+/// Returns the first component of a MSH field, using the component separator the message declares
+fn first_component(frame: &[u8], field: usize) -> Option<&str> {
+    let component_separator = msh_field(frame, 2)?.chars().next().unwrap_or('^');
+
+    msh_field(frame, field)?
+        .split(component_separator)
+        .next()
+        .filter(|value| !value.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_components_and_subcomponents() {
+        let frame = b"MSH|^~\\&|ZIS||||||ADT^A01|1|P|2.3\rPID|1||123^^^AHospital&1.2.3&ISO||Vries^Danny^D.~Smit^Jan\r";
+        assert_eq!(field_component(frame, "PID", 5, 1), Some("Vries"));
+        assert_eq!(field_component(frame, "PID", 5, 2), Some("Danny"));
+        assert_eq!(field_component(frame, "PID", 5, 3), Some("D."));
+        assert_eq!(field_component(frame, "PID", 5, 4), None);
+        assert_eq!(field_component(frame, "PID", 3, 2), Some(""));
+        assert_eq!(field_component(frame, "PID", 1, 1), Some("1"));
+        assert_eq!(field_component(frame, "PID", 6, 1), None);
+        assert_eq!(field_component(frame, "PV1", 1, 1), None);
+        assert_eq!(field_component(frame, "MSH", 9, 1), Some("ADT"));
+        assert_eq!(field_subcomponent(frame, "PID", 3, 4, 1), Some("AHospital"));
+        assert_eq!(field_subcomponent(frame, "PID", 3, 4, 3), Some("ISO"));
+        assert_eq!(field_subcomponent(frame, "PID", 3, 4, 4), None);
+        assert_eq!(field_subcomponent(frame, "PID", 5, 2, 1), Some("Danny"));
+    }
+
+    #[test]
+    fn reads_components_with_declared_separators() {
+        let frame = b"MSH#$*\\@#ZIS######ADT$A01#1#P#2.3\rPID#1##123$$$AHospital@1.2.3##Vries$Danny$D.*Smit\r";
+        assert_eq!(field_component(frame, "PID", 5, 2), Some("Danny"));
+        assert_eq!(field_component(frame, "PID", 5, 3), Some("D."));
+        assert_eq!(field_component(frame, "MSH", 9, 2), Some("A01"));
+        assert_eq!(field_subcomponent(frame, "PID", 3, 4, 2), Some("1.2.3"));
+        // the standard separators are just content here
+        let frame = b"MSH#$*\\@#ZIS\rPID#1##Vries^Danny\r";
+        assert_eq!(field_component(frame, "PID", 3, 1), Some("Vries^Danny"));
+    }
+
+    const SAMPLE: &[u8] = b"MSH|^~\\&|ZIS|1^AHospital|||200405141144||ADT^A01|20041104082400|P|2.3|||AL|NE|||8859/15|\rEVN|A01|20041104082400.0000+0100|20041104082400\r";
+
+    #[test]
+    fn reads_field_separator_and_encoding_chars() {
+        assert_eq!(msh_field(SAMPLE, 1), Some("|"));
+        assert_eq!(msh_field(SAMPLE, 2), Some("^~\\&"));
+    }
+
+    #[test]
+    fn reads_control_id() {
+        assert_eq!(control_id(SAMPLE), Some("20041104082400"));
+    }
+
+    #[test]
+    fn honours_declared_separator() {
+        let frame = b"MSH#^~\\&#A#B#####C#ID123\r";
+        assert_eq!(control_id(frame), Some("ID123"));
+    }
+
+    #[test]
+    fn reads_version() {
+        assert_eq!(hl7_version(SAMPLE), Some("2.3"));
+
+        let frame = b"MSH|^~\\&|EPIC|EPICADT|SMS|SMSADT|199912271408|CHARRIS|ADT^A04|1817457|D|2.5.1^AUS|\rPID||0493575\r";
+        assert_eq!(hl7_version(frame), Some("2.5.1"));
+    }
+
+    #[test]
+    fn msh_parse_errors_say_why() {
+        assert_eq!(try_msh_field(SAMPLE, 10), Ok("20041104082400"));
+        assert_eq!(
+            try_msh_field(b"PID|1|ABC\r", 10),
+            Err(MshParseError::NoMshSegment)
+        );
+        assert_eq!(
+            try_msh_field(b"MSHX^~\\&XZISX\r", 3),
+            Err(MshParseError::BadSeparator(b'X'))
+        );
+        assert_eq!(
+            try_msh_field(b"MSH|^~\\&|ZIS||\rEVN|A01\r", 10),
+            Err(MshParseError::TooFewFields {
+                needed: 10,
+                found: 5
+            })
+        );
+        assert_eq!(
+            try_msh_field(b"MSH|^~\\&|Z\xFFS|\r", 3),
+            Err(MshParseError::NotUtf8 { field: 3 })
+        );
+        assert_eq!(msh_field(b"MSH|^~\\&|Z\xFFS|\r", 3), None);
+        assert_eq!(
+            try_msh_field(SAMPLE, 0),
+            Err(MshParseError::InvalidField { field: 0 })
+        );
+    }
+
+    #[test]
+    fn counts_segments() {
+        let frame = b"MSH|^~\\&|ZIS|1^AHospital|||200405141144||ADT^A01|1|P|2.3\rEVN|A01\rPID|1||P01\rPV1|1|I\r";
+        assert_eq!(segment_count(frame), 4);
+        assert_eq!(segment_count(&frame[..frame.len() - 1]), 4); // without the final separator
+        assert_eq!(segment_count(b"MSH|^~\\&|ZIS\r\nEVN|A01\r\n"), 2);
+        assert_eq!(segment_count(b""), 0);
+    }
+
+    #[test]
+    fn reads_processing_id() {
+        assert_eq!(processing_id(SAMPLE), Some("P"));
+
+        let frame = b"MSH|^~\\&|EPIC|EPICADT|SMS|SMSADT|199912271408|CHARRIS|ADT^A04|1817457|D^T|2.5.1|\rPID||0493575\r";
+        assert_eq!(processing_id(frame), Some("D"));
+        assert_eq!(processing_id(b"MSH|^~\\&|||||||ADT^A01|1||2.3\r"), None);
+    }
+
+    #[test]
+    fn reads_facilities() {
+        assert_eq!(sending_facility(SAMPLE), Some("1"));
+        assert_eq!(receiving_facility(SAMPLE), None);
+        assert_eq!(
+            receiving_facility(b"MSH|^~\\&|ZIS|A|EMR|B^Other|\r"),
+            Some("B")
+        );
+    }
+
+    #[test]
+    fn reads_message_type() {
+        assert_eq!(message_type(SAMPLE), Some("ADT"));
+        assert_eq!(trigger_event(SAMPLE), Some("A01"));
+        assert_eq!(trigger_event(b"MSH|^~\\&|ZIS||||||ACK|1|P|2.3\r"), None);
+    }
+
+    #[test]
+    fn reads_ack_types() {
+        assert_eq!(accept_ack_type(SAMPLE), Some("AL"));
+        assert_eq!(application_ack_type(SAMPLE), Some("NE"));
+        assert_eq!(
+            accept_ack_type(b"MSH|^~\\&|ZIS||||||ADT^A01|1|P|2.3\r"),
+            None
+        );
+    }
+
+    #[test]
+    fn reads_message_datetime() {
+        assert_eq!(message_datetime(SAMPLE), Some("200405141144"));
+        assert_eq!(
+            message_datetime(b"MSH|^~\\&|ZIS||||||ADT^A01|1|P|2.3\r"),
+            None
+        );
+        assert_eq!(
+            message_timestamp(b"MSH|^~\\&|ZIS||||||ADT^A01|1|P|2.3\r"),
+            None
+        );
+    }
+
+    #[test]
+    fn parses_timestamp_precisions() {
+        let ts = message_timestamp(SAMPLE).unwrap();
+        assert_eq!(
+            ts,
+            Hl7Timestamp {
+                year: 2004,
+                month: Some(5),
+                day: Some(14),
+                hour: Some(11),
+                minute: Some(44),
+                second: None,
+                nanosecond: None,
+                utc_offset_minutes: None,
+            }
+        );
+
+        // leap days only in leap years
+        assert_eq!(Hl7Timestamp::parse("20240229").unwrap().day, Some(29));
+        assert_eq!(Hl7Timestamp::parse("20000229").unwrap().day, Some(29));
+        assert_eq!(Hl7Timestamp::parse("20240430").unwrap().day, Some(30));
+
+        let ts = Hl7Timestamp::parse("20041104").unwrap();
+        assert_eq!(
+            (ts.year, ts.month, ts.day, ts.hour),
+            (2004, Some(11), Some(4), None)
+        );
+
+        let ts = Hl7Timestamp::parse("20041104082400").unwrap();
+        assert_eq!(
+            (ts.hour, ts.minute, ts.second),
+            (Some(8), Some(24), Some(0))
+        );
+
+        let ts = Hl7Timestamp::parse("20041104082400.0000+0100").unwrap();
+        assert_eq!(ts.nanosecond, Some(0));
+        assert_eq!(ts.utc_offset_minutes, Some(60));
+
+        let ts = Hl7Timestamp::parse("200411040824-0330").unwrap();
+        assert_eq!(ts.utc_offset_minutes, Some(-210));
+
+        let ts = Hl7Timestamp::parse("20041104082400.25").unwrap();
+        assert_eq!(ts.nanosecond, Some(250_000_000));
+    }
+
+    #[test]
+    fn rejects_malformed_timestamps() {
+        for bad in [
+            "",
+            "200",
+            "2004111",
+            "20041304",
+            "20240230",
+            "20230229",
+            "21000229",
+            "20240431",
+            "2004110425",
+            "200411040824.5",
+            "20041104+01",
+            "2004ab",
+        ] {
+            assert_eq!(Hl7Timestamp::parse(bad), None, "{} parsed", bad);
+        }
+    }
+
+    #[test]
+    fn normalizes_sloppy_segment_ids() {
+        let frame = b"msh|^~\\&|ZIS||||||ADT^A01|ID1|P|2.3\r  pid|1|abc\n\tMsa|AA|ID1\r";
+        assert_eq!(msh_field(frame, 10), None);
+
+        let normalized = normalize_segment_ids(frame);
+        assert_eq!(
+            &normalized[..],
+            &b"MSH|^~\\&|ZIS||||||ADT^A01|ID1|P|2.3\rPID|1|abc\nMSA|AA|ID1\r"[..]
+        );
+        assert_eq!(control_id(&normalized), Some("ID1"));
+        assert_eq!(hl7_version(&normalized), Some("2.3"));
+
+        // the content past the segment ID is left alone
+        assert!(normalized.windows(3).any(|w| w == b"abc"));
+    }
+
+    #[test]
+    fn strict_frames_not_copied() {
+        assert!(matches!(normalize_segment_ids(SAMPLE), Cow::Borrowed(_)));
+        assert!(matches!(
+            normalize_segment_ids(b" MSH|^~\\&\r"),
+            Cow::Owned(_)
+        ));
+    }
+
+    #[test]
+    fn fills_empty_control_id() {
+        let frame = b"MSH|^~\\&|ZIS||||||ADT^A01||P|2.3\rEVN|A01\r";
+        let filled = fill_control_id(frame, "ID123");
+        assert_eq!(
+            &filled[..],
+            &b"MSH|^~\\&|ZIS||||||ADT^A01|ID123|P|2.3\rEVN|A01\r"[..]
+        );
+    }
+
+    #[test]
+    fn pads_short_msh_for_control_id() {
+        let filled = fill_control_id(b"MSH|^~\\&|ZIS\rEVN|A01\r", "ID123");
+        assert_eq!(&filled[..], &b"MSH|^~\\&|ZIS|||||||ID123\rEVN|A01\r"[..]);
+    }
+
+    #[test]
+    fn existing_control_id_kept() {
+        assert!(matches!(fill_control_id(SAMPLE, "ID123"), Cow::Borrowed(_)));
+        assert!(matches!(
+            fill_control_id(b"EVN|A01\r", "ID123"),
+            Cow::Borrowed(_)
+        ));
+    }
+
+    #[test]
+    fn generated_control_ids_are_unique() {
+        let first = generate_control_id();
+        let second = generate_control_id();
+        assert_eq!(first.len(), 19);
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn reassembles_continued_message() {
+        let first = b"MSH|^~\\&|LAB||||||ORU^R01|1|P|2.3\rPID|1\rOBX|1|TX|||One\rDSC|PTR1|F\r";
+        let second = b"MSH|^~\\&|LAB||||||ORU^R01|2|P|2.3||PTR1\rOBX|2|TX|||Two\r";
+        assert_eq!(continuation_pointer(first), Some("PTR1"));
+        assert_eq!(continuation_pointer(second), None);
+
+        let mut reassembler = Reassembler::new();
+        assert_eq!(reassembler.push(first).unwrap(), None);
+        assert!(reassembler.is_pending());
+        let message = reassembler.push(second).unwrap().unwrap();
+        assert_eq!(
+            message,
+            &b"MSH|^~\\&|LAB||||||ORU^R01|1|P|2.3\rPID|1\rOBX|1|TX|||One\rOBX|2|TX|||Two\r"[..]
+        );
+        assert!(!reassembler.is_pending());
+
+        // uncontinued messages go straight through
+        assert_eq!(reassembler.push(SAMPLE).unwrap().unwrap(), SAMPLE);
+    }
+
+    #[test]
+    fn unrelated_frame_breaks_continuation() {
+        let mut reassembler = Reassembler::new();
+        reassembler
+            .push(b"MSH|^~\\&|LAB||||||ORU^R01|1|P|2.3\rOBX|1|TX|||One\rDSC|PTR1|F")
+            .unwrap();
+        assert!(matches!(
+            reassembler.push(SAMPLE),
+            Err(MllpError::InvalidFrame(_))
+        ));
+        assert!(!reassembler.is_pending());
+    }
+
+    #[test]
+    fn header_matches_field_helpers() {
+        let header = MshHeader::parse(SAMPLE).unwrap();
+        assert_eq!(header.sending_facility.as_deref(), sending_facility(SAMPLE));
+        assert_eq!(
+            header.receiving_facility.as_deref(),
+            receiving_facility(SAMPLE)
+        );
+        assert_eq!(header.message_datetime.as_deref(), message_datetime(SAMPLE));
+        assert_eq!(header.message_type.as_deref(), msh_field(SAMPLE, 9));
+        assert_eq!(header.control_id.as_deref(), control_id(SAMPLE));
+        assert_eq!(header.version.as_deref(), hl7_version(SAMPLE));
+
+        assert_eq!(MshHeader::parse(b"EVN|A01\r"), None);
+    }
+
+    #[test]
+    fn missing_fields_are_none() {
+        assert_eq!(msh_field(b"MSH|^~\\&|A\r", 10), None);
+        assert_eq!(msh_field(b"PID|1||\r", 2), None);
+        assert_eq!(control_id(b"MSH|^~\\&|||||||ADT^A01||P|2.3\r"), None);
+        assert_eq!(hl7_version(b"MSH|^~\\&|||||||ADT^A01|1|P||\r"), None);
+    }
+}
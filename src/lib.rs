@@ -51,25 +51,250 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
  available which enables some non-compliant behaviours:
  - Removes the assumption that there's only message at a time on the wire in the event that a publisher fails to wait for an ACK/NAK, and publishes multiple messages asyncronously
 
+ There are also some optional helpers built on top of the codec:
+ - `client` enables [MllpClient], a publisher-side wrapper that enforces the wait-for-ack rule
+ - `server` enables [serve_mllp], a listener loop that hands each message to your handler and sends back its ACK
+ - `listen` enables [listen], which accepts TCP connections and spawns a [serve_mllp] loop for each
+ - `channel` enables [spawn_reader], which reads frames in a background task and sends them down a channel
+ - `capture` enables [split_capture], which splits a capture file into a file per message
+ - `replay` enables [replay_file], which re-sends a capture file to a listener
+ - `futures-io` enables [FuturesFramed], which frames IO streams implementing the `futures` IO traits (eg async-std's)
+ - `memchr` searches for headers and footers using the [memchr](https://docs.rs/memchr) crate's SIMD routines, which
+   is much faster when there's a lot of junk (or a large frame) to get through
+
  */
 
+pub mod ack;
+pub mod batch;
+#[cfg(feature = "capture")]
+mod capture;
+#[cfg(feature = "channel")]
+mod channel;
+mod checksum;
+#[cfg(feature = "client")]
+mod client;
+mod config;
+mod dedup;
+mod error;
+#[cfg(feature = "futures-io")]
+mod futures_io;
+pub mod hl7;
+mod reader;
+pub mod recovery;
+#[cfg(feature = "replay")]
+mod replay;
+mod router;
+#[cfg(feature = "server")]
+mod server;
+mod stats;
+mod timestamp;
+mod utf16;
+mod validate;
+mod writer;
+
+#[cfg(feature = "capture")]
+pub use capture::split_capture;
+#[cfg(feature = "channel")]
+pub use channel::spawn_reader;
+#[cfg(feature = "client")]
+pub use client::MllpClient;
+pub use config::{MllpCodecBuilder, MllpConfig, NulHandling, OversizeAction};
+pub use dedup::DedupCache;
+pub use error::MllpError;
+#[cfg(feature = "futures-io")]
+pub use futures_io::FuturesFramed;
+pub use reader::FrameReader;
+pub use recovery::RecoveryAction;
+#[cfg(feature = "replay")]
+pub use replay::replay_file;
+pub use router::{RouteBy, Router};
+#[cfg(feature = "listen")]
+pub use server::{listen, listen_on, listen_on_with};
+#[cfg(feature = "server")]
+pub use server::{serve_mllp, serve_mllp_with, ExcessConnections, ServeOptions};
+pub use stats::MllpStats;
+pub use timestamp::{TimestampedCodec, TimestampedFrame};
+pub use validate::FrameValidator;
+pub use writer::write_mllp_frame;
+
 use bytes::buf::{Buf, BufMut};
-use bytes::BytesMut;
-use log::{debug, trace};
+use bytes::{Bytes, BytesMut};
+use log::{debug, trace, warn};
+use std::borrow::Cow;
+use std::io::Write;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio_util::codec::*;
 
+/// The filler written after the footer when frames are [padded](MllpCodecBuilder::pad_to) to a block size.
+const PAD_BYTE: u8 = 0x00;
+
+/// The UTF-8 byte order mark, see [MllpCodecBuilder::strip_bom].
+const UTF8_BOM: &[u8] = &[0xEF, 0xBB, 0xBF];
+
+/// The Start-of-Text and End-of-Text chars, see [MllpCodec::stx_etx].
+const STX: u8 = 0x02;
+const ETX: u8 = 0x03;
+
+/// The Vertical-Tab char, the marker for the start of a MLLP frame.
+pub const MLLP_HEADER: u8 = 0x0B;
+
+/// The File-Separator char + CR, the marker for the end of a MLLP frame.
+///
+/// Handy for hand building (or scanning) frames in test utilities and proxies.
+/// Example:
+/// ```
+/// use hl7_mllp_codec::{is_footer_at, MLLP_FOOTER, MLLP_HEADER};
+///
+/// let mut frame = vec![MLLP_HEADER];
+/// frame.extend_from_slice(b"Hello World");
+/// frame.extend_from_slice(&MLLP_FOOTER);
+///
+/// assert_eq!(frame, b"\x0BHello World\x1C\x0D");
+/// assert!(is_footer_at(&frame, frame.len() - 2));
+/// ```
+pub const MLLP_FOOTER: [u8; 2] = [0x1C, 0x0D];
+
+/// Returns whether the [MLLP_FOOTER] starts at index `i` of `buf`.  Out of range indexes are never a footer.
+pub fn is_footer_at(buf: &[u8], i: usize) -> bool {
+    buf.get(i..i.saturating_add(MLLP_FOOTER.len())) == Some(&MLLP_FOOTER[..])
+}
+
+/// Where the decoder stands with what's been received, see [MllpCodec::classify].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameState {
+    /// A complete frame is ready to `decode`.
+    Complete,
+    /// A frame has been started, but its footer hasn't arrived yet.
+    PartialHeaderSeen,
+    /// Nothing but (possibly) junk has arrived, there's no frame started.
+    NoHeaderYet,
+    /// What's been received already breaks the configured [max_frame_size](MllpCodecBuilder::max_frame_size) or
+    /// [max_prefix_skip](MllpCodecBuilder::max_prefix_skip), so the next `decode` will fail (or skip the frame).
+    Invalid,
+}
+
+/// Finds the first `byte` in `buf`, using SIMD where the `memchr` feature is enabled
+fn find_byte(byte: u8, buf: &[u8]) -> Option<usize> {
+    #[cfg(feature = "memchr")]
+    {
+        memchr::memchr(byte, buf)
+    }
+    #[cfg(not(feature = "memchr"))]
+    {
+        buf.iter().position(|b| *b == byte)
+    }
+}
+
+/// Finds the first occurrence of `needle` (which mustn't be empty) in `buf`.  With the `memchr` feature only candidates
+/// for its first byte, found with memchr, are checked.
+fn find_slice(needle: &[u8], buf: &[u8]) -> Option<usize> {
+    #[cfg(feature = "memchr")]
+    {
+        let mut from = 0;
+        while let Some(i) = memchr::memchr(needle[0], &buf[from..]) {
+            let i = from + i;
+            if buf[i..].starts_with(needle) {
+                return Some(i);
+            }
+            from = i + 1;
+        }
+        None
+    }
+    #[cfg(not(feature = "memchr"))]
+    {
+        buf.windows(needle.len()).position(|w| w == needle)
+    }
+}
+
+/// Finds the last occurrence of `needle` (which mustn't be empty) in `buf`, see [find_slice]
+#[cfg(not(feature = "noncompliance"))]
+fn rfind_slice(needle: &[u8], buf: &[u8]) -> Option<usize> {
+    #[cfg(feature = "memchr")]
+    {
+        let mut end = buf.len();
+        while let Some(i) = memchr::memrchr(needle[0], &buf[..end]) {
+            if buf[i..].starts_with(needle) {
+                return Some(i);
+            }
+            end = i;
+        }
+        None
+    }
+    #[cfg(not(feature = "memchr"))]
+    {
+        buf.windows(needle.len()).rposition(|w| w == needle)
+    }
+}
+
 /// See the [crate] documentation for better details.
-#[derive(Default)]
-pub struct MllpCodec {
+///
+/// # Cancellation safety
+/// `decode` is synchronous, so a poll can't be dropped part way through one.  Between calls the codec holds only the
+/// start of an incomplete frame (and how far it has searched it for a footer), which stays valid however many calls
+/// come in with no new data.  Dropping a `Framed` read future (eg a `select!` or timeout firing) mid-frame loses
+/// nothing, the frame is returned by a later read once the rest arrives.
+///
+/// # Output type
+/// Frames are decoded as `BytesMut` by default.  A codec decoding frames for fan-out to several consumers can decode
+/// them as (cheaply cloned) `Bytes` instead, see [with_output](MllpCodec::with_output).
+pub struct MllpCodec<O = BytesMut> {
     // If we receive the start of a message in a call to decode but not the end, we need to buffer the content
     // and prepend it to the data in the next call (Issue #4)
     buffer: BytesMut,
+    config: Arc<MllpConfig>,
+    // set while we're discarding the remainder of an oversized frame
+    skipping: bool,
+    // how much of the oversized frame being skipped has been discarded, for the stats
+    skipped_frame_len: Option<usize>,
+    // set while decoding pipelined (or interleaved) frames, where we need the first footer rather than the last
+    scan_forward: bool,
+    // how much of `buffer` previous calls have searched for a footer without finding one, so we don't search it again
+    footer_searched: usize,
+    // where the header of the frame `buffer` is waiting on the rest of was found, so we don't search for it again
+    header_at: Option<usize>,
+    // the footers counted in the buffer being decoded, as (address of its first byte, bytes counted, footers found), so
+    // the max_buffered_frames check only counts what's arrived since
+    footer_count: Option<(usize, usize, usize)>,
+    // set when the last decode had to discard junk or a stray footer to find its frame
+    resynced: bool,
+    // where the header (if it had one) and footer of the last decoded frame were, for debugging framing issues
+    last_offsets: Option<(Option<usize>, usize)>,
+    // where every byte taken in by decode is copied to, and how much at the front of src has already been copied
+    tee: Option<Box<dyn Write + Send + Sync>>,
+    tee_pending: usize,
+    stats: MllpStats,
+    output: PhantomData<fn() -> O>,
 }
 
-impl MllpCodec {
-    const BLOCK_HEADER: u8 = 0x0B; //Vertical-Tab char, the marker for the start of a message
-    const BLOCK_FOOTER: [u8; 2] = [0x1C, 0x0D]; //File-Separator char + CR, the marker for the end of a message
+/// A type a decoded frame can be returned as, see [MllpCodec::with_output].
+pub trait FromBytesMut {
+    /// Converts a decoded frame.
+    fn from_bytes_mut(frame: BytesMut) -> Self;
+}
+
+/// Exclusively owned, mutable frames (the default).
+impl FromBytesMut for BytesMut {
+    fn from_bytes_mut(frame: BytesMut) -> Self {
+        frame
+    }
+}
+
+/// Shared, immutable frames, cloned without copying the frame.
+impl FromBytesMut for Bytes {
+    fn from_bytes_mut(frame: BytesMut) -> Self {
+        frame.freeze()
+    }
+}
+
+impl Default for MllpCodec {
+    fn default() -> Self {
+        MllpCodec::new()
+    }
+}
 
+impl MllpCodec {
     /// Creates a new Codec instance, generally for use within a [Tokio Framed](https://docs.rs/tokio-util/0.6.7/tokio_util/codec/struct.Framed.html),
     /// but can be instantiated standalone for testing purposes etc.
     /// Example:
@@ -78,259 +303,3120 @@ impl MllpCodec {
     /// let mllp = MllpCodec::new();
     /// ```
     pub fn new() -> Self {
-        MllpCodec {
-            buffer: BytesMut::new()
-        }
+        MllpCodec::from_config(Arc::default()) // the default config is always valid
     }
 
-    #[cfg(feature = "noncompliance")]
-    fn get_footer_position(src: &BytesMut) -> Option<usize> {
-        let mut iter = src.iter().enumerate().peekable(); //search from start because we may have multiple messages on socket
-        loop {
-            let cur = iter.next();
-            let next = iter.peek();
-
-            match (cur, next) {
-                (Some((i, cur_ele)), Some((_, next_ele))) => {
-                    //both current and next ele are avail
-                    if cur_ele == &MllpCodec::BLOCK_FOOTER[0]
-                        && *next_ele == &MllpCodec::BLOCK_FOOTER[1]
-                    {
-                        trace!("MLLP: Found footer at index {}", i);
-                        return Some(i);
-                    }
-                }
-                (_, None) => {
-                    trace!("MLLP: Unable to find footer...");
-                    return None;
-                }
-                _ => {} //keep looping
-            }
-        }
+    /// **Non-standard:** Creates a codec for systems framing messages with the ASCII STX (`0x02`) / ETX (`0x03`)
+    /// convention rather than the MLLP block characters, for bridging such systems onto MLLP.  Use the builder's
+    /// [header](MllpCodecBuilder::header) and [footer](MllpCodecBuilder::footer) if other options are needed too.
+    /// Example:
+    /// ```
+    /// use bytes::BytesMut;
+    /// use hl7_mllp_codec::MllpCodec;
+    /// use tokio_util::codec::Decoder;
+    ///
+    /// let frame = MllpCodec::stx_etx().decode(&mut BytesMut::from("\x02Hello\x03")).unwrap();
+    /// assert_eq!(frame.as_deref(), Some(&b"Hello"[..]));
+    /// ```
+    pub fn stx_etx() -> Self {
+        MllpCodec::builder()
+            .header(STX)
+            .footer(&[ETX])
+            .build()
+            .expect("STX/ETX framing is a valid configuration")
     }
 
-    /// this is the spec-compliant version, that knows there can only be at most one message in the buffer due to the synchronous nature of the spec
-    #[cfg(not(feature = "noncompliance"))]
-    fn get_footer_position(src: &BytesMut) -> Option<usize> {
-        let mut iter = src.iter().rev().enumerate().peekable(); //search from end (footer should be right at the end per spec)
-        loop {
-            let cur = iter.next();
-            let next = iter.peek();
-
-            match (cur, next) {
-                (Some((_, cur_ele)), Some((i, next_ele))) => {
-                    //both current and next ele are avail
-                    if cur_ele == &MllpCodec::BLOCK_FOOTER[1]
-                        && *next_ele == &MllpCodec::BLOCK_FOOTER[0]
-                    {
-                        //if the bytes are our footer
-                        let index = src.len() - i - 1; //need an extra byte removed
-                        trace!("MLLP: Found footer at index {}", index);
-                        return Some(index);
-                    }
-                }
-                (_, None) => {
-                    trace!("MLLP: Unable to find footer...");
-                    return None;
-                }
-                _ => {} //keep looping
-            }
+    /// Returns a [MllpCodecBuilder] for configuring non-default behaviours.
+    pub fn builder() -> MllpCodecBuilder {
+        MllpCodecBuilder::default()
+    }
+
+    /// Creates a codec sharing an existing configuration, which is cheaper than building (and validating) a new
+    /// configuration per codec when a server needs a codec for each of thousands of connections.
+    /// Example:
+    /// ```
+    /// use hl7_mllp_codec::MllpCodec;
+    /// use std::sync::Arc;
+    ///
+    /// let config = Arc::new(MllpCodec::builder().max_frame_size(1024 * 1024).build_config().unwrap());
+    /// let codecs: Vec<MllpCodec> = (0..1000).map(|_| MllpCodec::from_config(config.clone())).collect();
+    /// ```
+    pub fn from_config(config: Arc<MllpConfig>) -> Self {
+        MllpCodec {
+            buffer: BytesMut::new(),
+            config,
+            skipping: false,
+            skipped_frame_len: None,
+            scan_forward: false,
+            footer_searched: 0,
+            header_at: None,
+            footer_count: None,
+            resynced: false,
+            last_offsets: None,
+            tee: None,
+            tee_pending: 0,
+            stats: MllpStats::default(),
+            output: PhantomData,
         }
     }
 }
 
-// Support encoding data as an MLLP Frame.
-// This is used for both the primary HL7 message sent from a publisher, and also any ACK/NACK messages sent from a Listener.
-impl Encoder<BytesMut> for MllpCodec {
-    type Error = std::io::Error; // Just to get rolling, custom error type later when needed.
-
-    fn encode(&mut self, event: BytesMut, dst: &mut BytesMut) -> Result<(), Self::Error> {
-        dst.reserve(event.len() + 3); //we need an extra 3 bytes of space on top of the message proper
-        dst.put_u8(MllpCodec::BLOCK_HEADER); //header
-
-        dst.put_slice(&event); //data
-
-        dst.put_slice(&MllpCodec::BLOCK_FOOTER); //footer
+impl<O> MllpCodec<O> {
+    /// Converts this codec to one decoding frames as `P`, eg `Bytes` for frames fanned out to several subscribers,
+    /// where cloning a `BytesMut` frame would copy it.  Anything buffered, the stats etc carry over.
+    /// Example:
+    /// ```
+    /// use bytes::{Bytes, BytesMut};
+    /// use hl7_mllp_codec::MllpCodec;
+    /// use tokio_util::codec::Decoder;
+    ///
+    /// let mut codec = MllpCodec::new().with_output::<Bytes>();
+    /// let frame: Bytes = codec.decode(&mut BytesMut::from("\x0BHello\x1C\x0D")).unwrap().unwrap();
+    /// let for_subscriber = frame.clone(); // no copy
+    /// assert_eq!(for_subscriber, Bytes::from("Hello"));
+    /// ```
+    pub fn with_output<P: FromBytesMut>(self) -> MllpCodec<P> {
+        MllpCodec {
+            buffer: self.buffer,
+            config: self.config,
+            skipping: self.skipping,
+            skipped_frame_len: self.skipped_frame_len,
+            scan_forward: self.scan_forward,
+            footer_searched: self.footer_searched,
+            header_at: self.header_at,
+            footer_count: self.footer_count,
+            resynced: self.resynced,
+            last_offsets: self.last_offsets,
+            tee: self.tee,
+            tee_pending: self.tee_pending,
+            stats: self.stats,
+            output: PhantomData,
+        }
+    }
 
-        debug!("MLLP: Encoded value for send: '{:?}'", dst);
-        Ok(())
+    /// Wraps `io` (generally a `TcpStream`) in a [Framed] transport using this codec, the canonical way to put the
+    /// codec to work.
+    /// Example:
+    /// ```no_run
+    /// use bytes::BytesMut;
+    /// use futures::SinkExt;
+    /// use hl7_mllp_codec::MllpCodec;
+    /// use tokio::net::TcpStream;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    ///     let stream = TcpStream::connect("127.0.0.1:8080").await?;
+    ///     let mut transport = MllpCodec::new().frame(stream);
+    ///     transport.send(BytesMut::from("Hello World")).await?;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn frame<T: tokio::io::AsyncRead + tokio::io::AsyncWrite>(self, io: T) -> Framed<T, Self> {
+        Framed::new(io, self)
     }
-}
 
-// Support decoding data from an MLLP Frame.
-// This is used for receiving the primary HL7 message in a listener, and also decoding any ACK/NACK responses in a publisher.
-impl Decoder for MllpCodec {
-    type Item = BytesMut; // For the moment all we do is return the underlying byte array, I'm not getting into message parsing here.
-    type Error = std::io::Error; // Just to get rolling, custom error type later when needed.
+    /// Copies every byte `decode` is given to `sink` as it arrives, framing, junk and all, so a full wire capture can be
+    /// recorded alongside normal processing (eg to a capture file, to replay later).  Bytes
+    /// left in `src` by one call (as `Framed` does with the start of the next frame) aren't copied again by the next.
+    /// A failing sink is logged and otherwise ignored, so it never interrupts decoding.
+    /// Example:
+    /// ```
+    /// use hl7_mllp_codec::MllpCodec;
+    /// use std::fs::File;
+    ///
+    /// let path = std::env::temp_dir().join("wire.mllp");
+    /// let codec = MllpCodec::new().with_tee(File::create(&path).unwrap());
+    /// # std::fs::remove_file(&path).unwrap();
+    /// ```
+    pub fn with_tee(mut self, sink: impl Write + Send + Sync + 'static) -> Self {
+        self.tee = Some(Box::new(sink));
+        self
+    }
 
-    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
-        // We're lucky the MLLP is specced as synchronous, and requires an ACK before sending the
-        // next message, so we don't have to worry about multiple messages in the buffer (Edit: See the `noncompliance` feature flag for unpleasantness).
+    /// Whether the last call to `decode` had to resynchronise with the stream, ie discard junk ahead of a header or a
+    /// stray footer, to get to its frame.  For alerting on (or counting) peers whose framing is slipping.
+    /// Example:
+    /// ```
+    /// use bytes::BytesMut;
+    /// use hl7_mllp_codec::MllpCodec;
+    /// use tokio_util::codec::Decoder;
+    ///
+    /// let mut codec = MllpCodec::new();
+    /// codec.decode(&mut BytesMut::from("junk\x0BHello\x1C\x0D")).unwrap();
+    /// assert!(codec.last_decode_resynced());
+    /// ```
+    pub fn last_decode_resynced(&self) -> bool {
+        self.resynced
+    }
 
-        // we DO have to ignore any bytes prior to the BLOCK_HEADER per the spec
+    /// Where the header of the most recently decoded frame was, within the buffer it was decoded from (ie anything
+    /// buffered by previous calls followed by the `src` passed in, after any stray footers ahead of it were dropped).
+    /// Any junk before the frame puts this past `0`.  Returns `None` before the first frame is decoded, or if the frame
+    /// had no header (see [allow_missing_header](MllpCodecBuilder::allow_missing_header)).
+    /// Example:
+    /// ```
+    /// use bytes::BytesMut;
+    /// use hl7_mllp_codec::MllpCodec;
+    /// use tokio_util::codec::Decoder;
+    ///
+    /// let mut codec = MllpCodec::new();
+    /// codec.decode(&mut BytesMut::from("junk\x0BHello\x1C\x0D")).unwrap();
+    /// assert_eq!(codec.last_header_offset(), Some(4));
+    /// assert_eq!(codec.last_footer_offset(), Some(10));
+    /// ```
+    pub fn last_header_offset(&self) -> Option<usize> {
+        self.last_offsets.and_then(|(header, _)| header)
+    }
 
-        // If we don't have anything outstanding from a previous call just use the buffer passed in
-        let result = if self.buffer.is_empty() {
-            trace!("Empty local buffer, operating on passed buffer only");
-            decode_internal(src)
-        } else {
-            // otherwise concat the previous data and current and work on that
-            self.buffer.reserve(src.len());
-            self.buffer.put_slice(src);
-            src.advance(src.len()); // this consumes the whole src buffer and keeps tokio happy
+    /// Where the footer of the most recently decoded frame was, within the same buffer as the
+    /// [header offset](MllpCodec::last_header_offset).  Returns `None` before the first frame is decoded.
+    pub fn last_footer_offset(&self) -> Option<usize> {
+        self.last_offsets.map(|(_, footer)| footer)
+    }
 
-            trace!("Operating on concat of previous and current buffers");
-            decode_internal(&mut self.buffer)
-        };
+    /// The configuration this codec is using.
+    pub fn config(&self) -> &Arc<MllpConfig> {
+        &self.config
+    }
 
-        if let Ok(None) = result {
-            // we didn't find a message
+    /// The number of bytes the codec is holding between calls to `decode`, ie the start of a frame whose footer hasn't arrived yet.
+    pub fn buffered_len(&self) -> usize {
+        self.buffer.len()
+    }
 
-            if self.buffer.is_empty() {
-                // if there's already data in the buffer we concatted it above, no need to do so again
-                // if here we need to concat the src buffer locally for future calls...
+    /// Whether `src` (on top of anything already buffered from previous calls) holds a complete frame, ie a header
+    /// followed by a footer, without consuming anything.  For select-loop style code that wants to avoid calling
+    /// `decode` until there's something to decode.
+    /// Example:
+    /// ```
+    /// use bytes::BytesMut;
+    /// use hl7_mllp_codec::MllpCodec;
+    ///
+    /// let mllp = MllpCodec::new();
+    /// assert!(mllp.has_complete_frame(&BytesMut::from("\x0BHello\x1C\x0D")));
+    /// assert!(!mllp.has_complete_frame(&BytesMut::from("\x0BHel")));
+    /// ```
+    pub fn has_complete_frame(&self, src: &BytesMut) -> bool {
+        let header = self.config.header;
+        let footer = &self.config.footer[..];
+        let has_footer = |buf: &[u8]| buf.windows(footer.len()).any(|w| w == footer);
 
-                self.buffer.reserve(src.len());
-                self.buffer.put_slice(src);
-                src.advance(src.len()); // this consumes the whole src buffer and keeps tokio happy, but breaks the non-compliant variant that can have multiple messages in the buffer
+        let buffered_header = find_byte(header, &self.buffer);
+        match buffered_header {
+            Some(header_offset) => {
+                let buffered = &self.buffer[header_offset + 1..];
+                // the footer may be split between what's buffered and what's arrived
+                let tail = &buffered[buffered.len().saturating_sub(footer.len() - 1)..];
+                let head = &src[..src.len().min(footer.len() - 1)];
+                has_footer(buffered) || has_footer(&[tail, head].concat()) || has_footer(src)
             }
+            None => match find_byte(header, src) {
+                Some(header_offset) => has_footer(&src[header_offset + 1..]),
+                None if self.config.allow_missing_header => {
+                    has_footer(&[&self.buffer[..], &src[..]].concat())
+                }
+                None => false,
+            },
         }
-
-        result
     }
-}
 
-fn decode_internal(buf_to_process: &mut BytesMut) -> Result<Option<BytesMut>, std::io::Error> {
-    if let Some(start_offset) = buf_to_process
-        .iter()
-        .position(|b| *b == MllpCodec::BLOCK_HEADER)
-    {
-        //yes we do, do we have a footer?
+    /// Classifies what `src` (on top of anything already buffered from previous calls) holds, without consuming
+    /// anything, for state machine driven consumers (eg starting a timer once a frame is
+    /// [started](FrameState::PartialHeaderSeen)).
+    /// Example:
+    /// ```
+    /// use bytes::BytesMut;
+    /// use hl7_mllp_codec::{FrameState, MllpCodec};
+    ///
+    /// let mllp = MllpCodec::new();
+    /// assert_eq!(mllp.classify(&BytesMut::from("\x0BHello\x1C\x0D")), FrameState::Complete);
+    /// assert_eq!(mllp.classify(&BytesMut::from("\x0BHel")), FrameState::PartialHeaderSeen);
+    /// assert_eq!(mllp.classify(&BytesMut::from("junk")), FrameState::NoHeaderYet);
+    /// ```
+    pub fn classify(&self, src: &BytesMut) -> FrameState {
+        if self.has_complete_frame(src) {
+            return FrameState::Complete;
+        }
 
-        //trace!("MLLP: Found message header at index {}", start_offset);
+        // a partial frame is only over the limit once it couldn't be a footer short of it
+        let footer_slack = self.config.footer.len() - 1;
+        match self.current_frame_progress(src) {
+            Some(progress) => match self.config.max_frame_size {
+                Some(max) if progress > max + footer_slack => FrameState::Invalid,
+                _ => FrameState::PartialHeaderSeen,
+            },
+            None => match self.config.max_prefix_skip {
+                Some(max) if self.buffer.len() + src.len() > max => FrameState::Invalid,
+                _ => FrameState::NoHeaderYet,
+            },
+        }
+    }
 
-        if let Some(end_offset) = MllpCodec::get_footer_position(buf_to_process) {
-            //Is it worth passing a slice of src so we don't search the header chars?
-            //Most of the time the start_offset == 0, so not sure it's worth it.
+    /// How many content bytes of the frame currently in progress (its header seen but not its footer) are in `src`
+    /// and the codec's buffer, for showing progress while a large message arrives.  Returns `None` if no frame has been
+    /// started, or if the frame is complete (and so ready to `decode`).
+    /// Example:
+    /// ```
+    /// use bytes::BytesMut;
+    /// use hl7_mllp_codec::MllpCodec;
+    ///
+    /// let mllp = MllpCodec::new();
+    /// assert_eq!(mllp.current_frame_progress(&BytesMut::from("\x0BMSH|^~")), Some(6));
+    /// ```
+    pub fn current_frame_progress(&self, src: &BytesMut) -> Option<usize> {
+        if self.has_complete_frame(src) {
+            return None;
+        }
 
-            let mut result = buf_to_process
-                .split_to(end_offset + 2) //get the footer bytes
-                .split_to(end_offset); // grab our data from the buffer, consuming (and losing) the footer
+        let header = self.config.header;
+        match find_byte(header, &self.buffer) {
+            Some(header_offset) => Some(self.buffer.len() - header_offset - 1 + src.len()),
+            None => find_byte(header, src).map(|header_offset| src.len() - header_offset - 1),
+        }
+    }
 
-            result.advance(start_offset + 1); //move to start of data
+    /// [current_frame_progress](MllpCodec::current_frame_progress) as a percentage of `max` content bytes (typically the
+    /// configured [max_frame_size](MllpCodecBuilder::max_frame_size)), capped at 100.  Returns `None` when no frame is in
+    /// progress, or if `max` is zero.
+    /// Example:
+    /// ```
+    /// use bytes::BytesMut;
+    /// use hl7_mllp_codec::MllpCodec;
+    ///
+    /// let mllp = MllpCodec::new();
+    /// assert_eq!(mllp.current_frame_progress_pct(&BytesMut::from("\x0BMSH|^~"), 24), Some(25.0));
+    /// ```
+    pub fn current_frame_progress_pct(&self, src: &BytesMut, max: usize) -> Option<f32> {
+        if max == 0 {
+            return None;
+        }
+        self.current_frame_progress(src)
+            .map(|progress| (progress as f32 / max as f32 * 100.0).min(100.0))
+    }
 
-            return Ok(Some(result));
+    /// The size of the frame a payload of `payload_len` bytes encodes to, ie `payload_len + 3` for standard MLLP framing
+    /// (more or less if a custom footer is configured, and rounded up to the block size if frames are
+    /// [padded](MllpCodecBuilder::pad_to)).  Handy for pre-allocating buffers without magic numbers.
+    /// Example:
+    /// ```
+    /// use hl7_mllp_codec::MllpCodec;
+    /// assert_eq!(MllpCodec::new().framed_len(4), 7);
+    /// ```
+    pub fn framed_len(&self, payload_len: usize) -> usize {
+        let len = payload_len + self.framing_len();
+        match self.config.pad_to {
+            Some(block_size) => match len % block_size {
+                0 => len,
+                short => len + block_size - short, // up to the next whole block
+            },
+            None => len,
         }
     }
 
-    Ok(None)
-}
+    /// The bytes a frame adds on top of its payload, not counting any padding
+    fn framing_len(&self) -> usize {
+        let checksum_len = if self.config.frame_checksum {
+            checksum::CHECKSUM_LEN
+        } else {
+            0
+        };
+        1 + checksum_len + self.config.footer.len()
+    }
 
-//////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use bytes::Bytes;
+    /// Whether the data waiting to be decoded (`src` plus anything the codec has buffered) has grown past the configured
+    /// [high_water_mark](MllpCodecBuilder::high_water_mark), meaning frames are arriving faster than they're consumed.
+    /// Callers can stop reading from the socket until this clears, as frames are decoded.  Always `false` without a
+    /// high-water mark.
+    /// Example:
+    /// ```
+    /// use bytes::BytesMut;
+    /// use hl7_mllp_codec::MllpCodec;
+    ///
+    /// let mllp = MllpCodec::builder().high_water_mark(1024 * 1024).build().unwrap();
+    /// assert!(!mllp.is_above_high_water(&BytesMut::from("\x0BHello\x1C\x0D")));
+    /// ```
+    pub fn is_above_high_water(&self, src: &BytesMut) -> bool {
+        match self.config.high_water_mark {
+            Some(mark) => self.buffered_len() + src.len() > mark,
+            None => false,
+        }
+    }
 
-    fn wrap_for_mllp(s: &str) -> Bytes {
-        Bytes::from(format!("\x0B{}\x1C\x0D", s))
+    /// Wraps `payload` in MLLP framing, appending the frame to `dst`.  This is what `encode` does, but borrowing the
+    /// payload rather than taking ownership, so a pass-through proxy re-wrapping a payload (eg from a length-prefixed
+    /// transport) into MLLP doesn't need to copy it into an owned `BytesMut` first.
+    /// Example:
+    /// ```
+    /// use bytes::{Bytes, BytesMut};
+    /// use hl7_mllp_codec::MllpCodec;
+    ///
+    /// let payload = Bytes::from_static(b"abcd");
+    /// let mut dst = BytesMut::new();
+    /// MllpCodec::new().reframe(&payload, &mut dst).unwrap();
+    /// assert_eq!(&dst[..], b"\x0Babcd\x1C\x0D");
+    /// ```
+    pub fn reframe(&mut self, payload: &[u8], dst: &mut BytesMut) -> Result<(), MllpError> {
+        let payload = self.prepare_payload(payload)?;
+        dst.reserve(self.framed_len(payload.len())); //we need space for the framing on top of the message proper
+        self.put_frame(&payload, dst);
+        Ok(())
     }
 
-    fn wrap_for_mllp_mut(s: &str) -> BytesMut {
-        BytesMut::from(format!("\x0B{}\x1C\x0D", s).as_str())
+    /// Wraps `payload` in MLLP framing as a frame of its own, for storing or queueing complete frames outside of a
+    /// `Framed` transport.  The frame is allocated at exactly its framed size.
+    /// Example:
+    /// ```
+    /// use hl7_mllp_codec::MllpCodec;
+    ///
+    /// let frame = MllpCodec::new().encode_to_bytes(b"abcd").unwrap();
+    /// assert_eq!(&frame[..], b"\x0Babcd\x1C\x0D");
+    /// ```
+    pub fn encode_to_bytes(&mut self, payload: &[u8]) -> Result<Bytes, MllpError> {
+        let mut dst = BytesMut::new();
+        self.reframe(payload, &mut dst)?; // which reserves the exact size
+        Ok(dst.freeze())
     }
 
-    #[test]
-    fn can_construct_without_error() {
-        let _m = MllpCodec::new();
+    /// Encodes `event` as `encode` does, but without first reserving room for the frame in `dst`, for hot paths that
+    /// have already reserved exactly what they need (see [framed_len](MllpCodec::framed_len)).  The caller must
+    /// ensure `dst` has the capacity: debug builds assert that it does, while release builds fall back to growing
+    /// `dst` as it's written, which is still safe but loses the point of calling this.
+    /// Example:
+    /// ```
+    /// use bytes::BytesMut;
+    /// use hl7_mllp_codec::MllpCodec;
+    ///
+    /// let mut codec = MllpCodec::new();
+    /// let mut dst = BytesMut::with_capacity(codec.framed_len(4));
+    /// codec.encode_unchecked(BytesMut::from("abcd"), &mut dst).unwrap();
+    /// assert_eq!(&dst[..], b"\x0Babcd\x1C\x0D");
+    /// ```
+    pub fn encode_unchecked(
+        &mut self,
+        event: BytesMut,
+        dst: &mut BytesMut,
+    ) -> Result<(), MllpError> {
+        let payload = self.prepare_payload(&event)?;
+        debug_assert!(
+            dst.capacity() - dst.len() >= self.framed_len(payload.len()),
+            "encode_unchecked needs {} bytes of spare capacity, dst has {}",
+            self.framed_len(payload.len()),
+            dst.capacity() - dst.len()
+        );
+        self.put_frame(&payload, dst);
+        Ok(())
     }
 
-    #[test]
-    fn implements_default() {
-        let _m = MllpCodec::default();
+    /// Appends the frame for an already [prepared](MllpCodec::prepare_payload) payload to `dst`
+    fn put_frame(&mut self, payload: &[u8], dst: &mut BytesMut) {
+        dst.put_u8(self.config.header); //header
+        let start = dst.len();
+        dst.put_slice(payload); //data
+        self.finish_frame(start, dst);
     }
 
-    #[test]
-    fn wraps_simple_data() {
-        let data = BytesMut::from("abcd");
-        let mut m = MllpCodec::new();
+    /// Appends the checksum, footer and padding to the frame whose payload runs from `start` to the end of `dst`
+    fn finish_frame(&mut self, start: usize, dst: &mut BytesMut) {
+        let payload_len = dst.len() - start;
+        if self.config.frame_checksum {
+            let checksum = checksum::encode(&dst[start..]);
+            dst.put_slice(&checksum);
+        }
 
-        let mut output_buf = BytesMut::with_capacity(64);
+        dst.put_slice(&self.config.footer); //footer
 
-        match m.encode(data, &mut output_buf) {
-            Ok(()) => {}
-            _ => panic!("Non OK value returned from encode"),
+        let padding = self.framed_len(payload_len) - (payload_len + self.framing_len());
+        dst.put_bytes(PAD_BYTE, padding); //any block padding, outside the frame proper
+
+        self.stats.frames_encoded += 1;
+        debug!("MLLP: Encoded value for send: '{:?}'", dst);
+    }
+
+    /// Encodes a payload produced as a sequence of chunks (eg one per segment) as a single frame, without first
+    /// concatenating them into one buffer.  The chunks are written straight into `dst` behind the header as they're
+    /// produced, so `dst` grows as they arrive rather than being reserved up front.  Options that rework the payload as
+    /// a whole ([strip_trailing_terminator](MllpCodecBuilder::strip_trailing_terminator),
+    /// [auto_control_id](MllpCodecBuilder::auto_control_id), [encode_transform](MllpCodecBuilder::encode_transform)
+    /// and [prepend_bom](MllpCodecBuilder::prepend_bom)) do need it in one piece, so with any of those configured the
+    /// chunks are concatenated after all.
+    /// Example:
+    /// ```
+    /// use bytes::BytesMut;
+    /// use hl7_mllp_codec::MllpCodec;
+    ///
+    /// let mut dst = BytesMut::new();
+    /// MllpCodec::new().encode_chunks(["ab", "cd"], &mut dst).unwrap();
+    /// assert_eq!(&dst[..], b"\x0Babcd\x1C\x0D");
+    /// ```
+    pub fn encode_chunks<I, C>(&mut self, chunks: I, dst: &mut BytesMut) -> Result<(), MllpError>
+    where
+        I: IntoIterator<Item = C>,
+        C: AsRef<[u8]>,
+    {
+        let config = &self.config;
+        if config.strip_trailing_terminator
+            || config.auto_control_id
+            || config.encode_transform.is_some()
+            || config.prepend_bom
+        {
+            let mut payload = Vec::new();
+            for chunk in chunks {
+                payload.extend_from_slice(chunk.as_ref());
+            }
+            return self.reframe(&payload, dst);
         }
-        let encoded_msg = output_buf.freeze();
-        println!("Encoded: {:?}", encoded_msg);
-        assert_eq!(encoded_msg, wrap_for_mllp("abcd"));
+
+        dst.reserve(self.framing_len());
+        dst.put_u8(self.config.header); //header
+        let start = dst.len();
+        for chunk in chunks {
+            dst.put_slice(chunk.as_ref()); //data
+        }
+        if self.config.reject_framed_payloads && self.is_framed(&dst[start..]) {
+            dst.truncate(start - 1); // back out the header and payload
+            return Err(MllpError::AlreadyFramed);
+        }
+        if let Err(e) = self.check_encoded_size(&dst[start..]) {
+            dst.truncate(start - 1);
+            return Err(e);
+        }
+        self.finish_frame(start, dst);
+        Ok(())
     }
 
-    #[test]
-    fn find_footer_location() {
-        let data = wrap_for_mllp_mut("abcd"); //this gets the footer at position 5, as there's a leading byte added
-        let result = MllpCodec::get_footer_position(&data);
+    /// Wraps `messages` in a [batch file](batch::build_batch) and encodes the batch as a single frame, for sending a
+    /// bundle of messages in one transmission.  The receiver can pull the messages back out with [batch::split_batch].
+    /// Example:
+    /// ```
+    /// use bytes::BytesMut;
+    /// use hl7_mllp_codec::{batch, MllpCodec};
+    /// use tokio_util::codec::Decoder;
+    ///
+    /// let mut codec = MllpCodec::new();
+    /// let mut framed = BytesMut::new();
+    /// codec.encode_batch(&[b"MSH|^~\\&|LAB|1\r", b"MSH|^~\\&|LAB|2\r"], &mut framed).unwrap();
+    ///
+    /// let frame = codec.decode(&mut framed).unwrap().unwrap();
+    /// assert_eq!(batch::split_batch(&frame).len(), 2);
+    /// ```
+    pub fn encode_batch(
+        &mut self,
+        messages: &[&[u8]],
+        dst: &mut BytesMut,
+    ) -> Result<(), MllpError> {
+        self.reframe(&batch::build_batch(messages), dst)
+    }
 
-        assert_eq!(result, Some(5));
+    /// Writes `framed` to `dst` verbatim, _without_ adding MLLP framing, for proxies forwarding bytes that are already
+    /// framed (eg captured off the wire) where `encode` would wrap them a second time.
+    ///
+    /// Nothing is checked: it's entirely up to the caller that `framed` holds complete, well formed frames.  Passing a
+    /// bare payload here puts unframed bytes on the wire, which the receiver will discard or (worse) merge into the
+    /// next frame.  As the bytes aren't framed by the codec they don't count towards [stats](MllpCodec::stats).
+    /// Example:
+    /// ```
+    /// use bytes::BytesMut;
+    /// use hl7_mllp_codec::MllpCodec;
+    ///
+    /// let mut dst = BytesMut::new();
+    /// MllpCodec::new().encode_raw(b"\x0Babcd\x1C\x0D", &mut dst).unwrap();
+    /// assert_eq!(&dst[..], b"\x0Babcd\x1C\x0D");
+    /// ```
+    pub fn encode_raw(&mut self, framed: &[u8], dst: &mut BytesMut) -> Result<(), MllpError> {
+        dst.extend_from_slice(framed);
+        trace!("MLLP: Passed through {} pre-framed bytes", framed.len());
+        Ok(())
     }
 
-    #[test]
-    fn missing_footer_detected() {
-        let data = BytesMut::from("no footer");
-        let result = MllpCodec::get_footer_position(&data);
+    /// Decodes every complete frame in `src` (and anything buffered from previous calls), for senders that pipeline
+    /// multiple frames without waiting for ACKs.  Any trailing partial frame is buffered as usual.
+    /// Example:
+    /// ```
+    /// use bytes::BytesMut;
+    /// use hl7_mllp_codec::MllpCodec;
+    ///
+    /// let mut src = BytesMut::from("\x0BFirst\x1C\x0D\x0BSecond\x1C\x0D\x0BThi");
+    /// let frames = MllpCodec::new().decode_all(&mut src).unwrap();
+    /// assert_eq!(frames, vec!["First", "Second"]);
+    /// ```
+    pub fn decode_all(&mut self, src: &mut BytesMut) -> Result<Vec<BytesMut>, MllpError> {
+        // a quick count of the footers gives an upper bound on the frames to come, sparing the Vec from regrowing
+        // when decoding large batches
+        let mut frames =
+            Vec::with_capacity(self.count_footers(&self.buffer) + self.count_footers(src));
+        self.decode_all_into(src, &mut frames)?;
+        Ok(frames)
+    }
+
+    /// [decode_all](MllpCodec::decode_all), but appending the frames to `frames`, so a `Vec` can be reused across
+    /// batches.  Unlike `decode_all` no room is reserved up front, the caller's in charge of `frames`' capacity.  On an
+    /// error the frames decoded ahead of it are left in `frames`.
+    /// Example:
+    /// ```
+    /// use bytes::BytesMut;
+    /// use hl7_mllp_codec::MllpCodec;
+    ///
+    /// let mut codec = MllpCodec::new();
+    /// let mut frames = Vec::new();
+    /// codec.decode_all_into(&mut BytesMut::from("\x0BFirst\x1C\x0D\x0BSec"), &mut frames).unwrap();
+    /// codec.decode_all_into(&mut BytesMut::from("ond\x1C\x0D"), &mut frames).unwrap();
+    /// assert_eq!(frames, vec!["First", "Second"]);
+    /// ```
+    pub fn decode_all_into(
+        &mut self,
+        src: &mut BytesMut,
+        frames: &mut Vec<BytesMut>,
+    ) -> Result<(), MllpError> {
+        let previous = std::mem::replace(&mut self.scan_forward, true);
+
+        let result = loop {
+            match self.decode_frame(src) {
+                Ok(Some(frame)) => frames.push(frame),
+                Ok(None) => break Ok(()),
+                Err(e) => break Err(e),
+            }
+        };
+
+        self.scan_forward = previous;
+        result
+    }
+
+    /// Decodes every complete frame left in `src` (and anything buffered) when a connection is closing, then discards
+    /// whatever's left over, so nothing is left on the stream for tokio to complain about.  The discarded tail (a
+    /// partial frame, or a frame that failed to decode) is logged and counted in [MllpStats::bytes_skipped], and the
+    /// codec is left empty, ready for reuse.
+    /// Example:
+    /// ```
+    /// use bytes::BytesMut;
+    /// use hl7_mllp_codec::MllpCodec;
+    ///
+    /// let mut codec = MllpCodec::new();
+    /// let mut src = BytesMut::from("\x0BFirst\x1C\x0D\x0BSecond\x1C\x0D\x0BThi");
+    /// assert_eq!(codec.drain(&mut src), vec!["First", "Second"]);
+    /// assert!(src.is_empty());
+    /// assert_eq!(codec.stats().bytes_skipped, 4);
+    /// ```
+    pub fn drain(&mut self, src: &mut BytesMut) -> Vec<Bytes> {
+        let previous = std::mem::replace(&mut self.scan_forward, true);
+
+        let mut frames = Vec::new();
+        loop {
+            match self.decode_frame(src) {
+                Ok(Some(frame)) => frames.push(frame.freeze()),
+                Ok(None) => break,
+                Err(e) => {
+                    warn!("MLLP: Stopped draining after {}", e);
+                    break;
+                }
+            }
+        }
+        self.scan_forward = previous;
+
+        let leftover = self.buffer.len() + src.len();
+        if leftover > 0 {
+            warn!(
+                "MLLP: Discarding {} bytes of incomplete frame at close",
+                leftover
+            );
+            self.stats.record_skipped(leftover);
+        }
+        self.buffer.clear();
+        src.clear();
+        self.skipping = false;
+        self.skipped_frame_len = None;
+        self.reset_search();
+
+        frames
+    }
+
+    /// Decodes the next frame along with its MSH fields, parsed in the same pass, for callers that would otherwise
+    /// decode and then pick through the MSH with several [hl7] helpers.  A frame without an MSH segment is consumed
+    /// and fails with [MllpError::InvalidFrame].
+    /// Example:
+    /// ```
+    /// use bytes::BytesMut;
+    /// use hl7_mllp_codec::MllpCodec;
+    ///
+    /// let mut src = BytesMut::from("\x0BMSH|^~\\&|ZIS||||||ADT^A01|ID123|P|2.3\x1C\x0D");
+    /// let (frame, header) = MllpCodec::new().decode_with_header(&mut src).unwrap().unwrap();
+    /// assert_eq!(header.control_id.as_deref(), Some("ID123"));
+    /// assert!(frame.starts_with(b"MSH"));
+    /// ```
+    pub fn decode_with_header(
+        &mut self,
+        src: &mut BytesMut,
+    ) -> Result<Option<(Bytes, hl7::MshHeader)>, MllpError> {
+        let frame = match self.decode_frame(src)? {
+            Some(frame) => frame.freeze(),
+            None => return Ok(None),
+        };
+        match hl7::MshHeader::parse(&self.config.segment_ids(&frame)) {
+            Some(header) => Ok(Some((frame, header))),
+            None => Err(MllpError::InvalidFrame(
+                "frame has no MSH segment".to_owned(),
+            )),
+        }
+    }
+
+    /// Decodes the next frame as UTF-8 text, for feeds known to be UTF-8 (by configuration, or because MSH-18 says so).
+    /// Frames that aren't valid UTF-8 are consumed and fail with [MllpError::InvalidUtf8].  Latin-1 and other
+    /// single-byte feeds should stick to the byte-oriented `decode`.
+    /// Example:
+    /// ```
+    /// use bytes::BytesMut;
+    /// use hl7_mllp_codec::MllpCodec;
+    ///
+    /// let mut src = BytesMut::from("\x0BMSH|^~\\&|Zürich\x1C\x0D");
+    /// let frame = MllpCodec::new().decode_str(&mut src).unwrap();
+    /// assert_eq!(frame.as_deref(), Some("MSH|^~\\&|Zürich"));
+    /// ```
+    pub fn decode_str(&mut self, src: &mut BytesMut) -> Result<Option<String>, MllpError> {
+        match self.decode_frame(src)? {
+            Some(frame) => match std::str::from_utf8(&frame) {
+                Ok(text) => Ok(Some(text.to_owned())),
+                Err(e) => Err(MllpError::InvalidUtf8(e)),
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Decodes the next frame straight into `sink` (a file being spooled to, say), returning the number of payload bytes
+    /// written, or `Ok(None)` if no complete frame is available yet.  Decode errors are returned as `io::Error`s
+    /// (with the [MllpError] as the inner error), so this slots into otherwise `io::Result` based code.
+    /// Example:
+    /// ```
+    /// use bytes::BytesMut;
+    /// use hl7_mllp_codec::MllpCodec;
+    ///
+    /// let mut spool = Vec::new();
+    /// let written = MllpCodec::new()
+    ///     .decode_into(&mut BytesMut::from("\x0BHello\x1C\x0D"), &mut spool)
+    ///     .unwrap();
+    /// assert_eq!(written, Some(5));
+    /// assert_eq!(spool, b"Hello");
+    /// ```
+    pub fn decode_into<W: std::io::Write>(
+        &mut self,
+        src: &mut BytesMut,
+        sink: &mut W,
+    ) -> std::io::Result<Option<usize>> {
+        match self.decode_frame(src)? {
+            Some(frame) => {
+                sink.write_all(&frame)?;
+                Ok(Some(frame.len()))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Decodes the next frame into `out`, replacing whatever it held, and returns whether a frame was decoded.  Reusing
+    /// the same `out` across calls means its allocation is reused too, for hot loops that would rather not allocate per
+    /// frame.  `out` is cleared even when no complete frame is available yet.  Errors are returned as for
+    /// [decode_into](MllpCodec::decode_into).
+    /// Example:
+    /// ```
+    /// use bytes::BytesMut;
+    /// use hl7_mllp_codec::MllpCodec;
+    ///
+    /// let mut codec = MllpCodec::new();
+    /// let mut out = Vec::new();
+    /// for read in ["\x0BHello\x1C\x0D", "\x0BWor", "ld\x1C\x0D"] {
+    ///     if codec.decode_into_buf(&mut BytesMut::from(read), &mut out).unwrap() {
+    ///         println!("{:?}", out);
+    ///     }
+    /// }
+    /// assert_eq!(out, b"World");
+    /// ```
+    pub fn decode_into_buf(
+        &mut self,
+        src: &mut BytesMut,
+        out: &mut Vec<u8>,
+    ) -> std::io::Result<bool> {
+        out.clear();
+        Ok(self.decode_into(src, out)?.is_some())
+    }
+
+    /// Applies the content transforms `decode` runs over each frame (the
+    /// [decode_transform](MllpCodecBuilder::decode_transform), [transcode_utf16](MllpCodecBuilder::transcode_utf16),
+    /// [strip_bom](MllpCodecBuilder::strip_bom) and [nul_handling](MllpCodecBuilder::nul_handling), in that order) to
+    /// `content`, for frame content that didn't come through `decode`, eg split out of a capture file.
+    ///
+    /// The result borrows from `content` unless a transform had to rewrite it, so with no transforms configured (or
+    /// none applying, eg no BOM to strip) nothing is allocated.  `decode` does the same, only copying a frame out of the
+    /// read buffer when a transform rewrites it.
+    /// Example:
+    /// ```
+    /// use hl7_mllp_codec::MllpCodec;
+    /// use std::borrow::Cow;
+    ///
+    /// let codec = MllpCodec::builder().strip_bom(true).build().unwrap();
+    /// let content = codec.transform_content(b"\xEF\xBB\xBFMSH|^~\\&|ZIS\r").unwrap();
+    /// assert!(matches!(content, Cow::Borrowed(b"MSH|^~\\&|ZIS\r")));
+    /// ```
+    pub fn transform_content<'a>(&self, content: &'a [u8]) -> Result<Cow<'a, [u8]>, MllpError> {
+        let content = self.transform_inbound(content);
+        let content = self.transcode_utf16(content)?;
+        let content = self.strip_bom(content);
+        self.handle_nuls(content)
+    }
+
+    /// Returns a snapshot of the counters this codec has accumulated (frames decoded/encoded, bytes skipped etc).
+    /// Example:
+    /// ```
+    /// use bytes::BytesMut;
+    /// use hl7_mllp_codec::MllpCodec;
+    /// use tokio_util::codec::Decoder;
+    ///
+    /// let mut mllp = MllpCodec::new();
+    /// mllp.decode(&mut BytesMut::from("\x0BHello\x1C\x0D")).unwrap();
+    /// assert_eq!(mllp.stats().frames_decoded, 1);
+    /// ```
+    pub fn stats(&self) -> MllpStats {
+        self.stats
+    }
+
+    /// Returns the counters accumulated since the last drain (or since the codec was created) and resets them, for
+    /// reporters that poll on an interval and want per-interval figures.  The
+    /// [max_frame_bytes](MllpStats::max_frame_bytes) is then the largest frame of the interval.
+    /// Example:
+    /// ```
+    /// use bytes::BytesMut;
+    /// use hl7_mllp_codec::{MllpCodec, MllpStats};
+    /// use tokio_util::codec::Decoder;
+    ///
+    /// let mut mllp = MllpCodec::new();
+    /// mllp.decode(&mut BytesMut::from("\x0BHello\x1C\x0D")).unwrap();
+    /// assert_eq!(mllp.drain_stats().frames_decoded, 1);
+    /// assert_eq!(mllp.stats(), MllpStats::default());
+    /// ```
+    pub fn drain_stats(&mut self) -> MllpStats {
+        std::mem::take(&mut self.stats)
+    }
+
+    /// Writes the MLLP frame for `event` into `dst` starting at `offset`, for scatter/gather style code that has reserved
+    /// a region of a larger buffer for the frame.
+    ///
+    /// Bytes before `offset` are untouched.  The frame _overwrites_ the [framed_len](MllpCodec::framed_len) bytes from `offset` onwards
+    /// (growing `dst` if it's too short), and anything after that region is left as-is.
+    ///
+    /// Panics if `offset > dst.len()`.
+    /// Example:
+    /// ```
+    /// use bytes::BytesMut;
+    /// use hl7_mllp_codec::MllpCodec;
+    ///
+    /// let mut dst = BytesMut::from("prefix:");
+    /// MllpCodec::new().encode_at(BytesMut::from("abcd"), &mut dst, 7).unwrap();
+    /// assert_eq!(&dst[..], b"prefix:\x0Babcd\x1C\x0D");
+    /// ```
+    pub fn encode_at(
+        &mut self,
+        event: BytesMut,
+        dst: &mut BytesMut,
+        offset: usize,
+    ) -> Result<(), MllpError> {
+        assert!(
+            offset <= dst.len(),
+            "encode_at offset {} is past the end of the buffer ({})",
+            offset,
+            dst.len()
+        );
+
+        let event = self.prepare_payload(&event)?;
+        let event = &event[..];
+        let end = offset + self.framed_len(event.len()); //we need space for the framing on top of the message proper
+        let footer = &self.config.footer;
+        if dst.len() < end {
+            dst.resize(end, 0);
+        }
+
+        let mut footer_start = offset + 1 + event.len();
+        dst[offset] = self.config.header;
+        dst[offset + 1..footer_start].copy_from_slice(event);
+        if self.config.frame_checksum {
+            let checksum_end = footer_start + checksum::CHECKSUM_LEN;
+            dst[footer_start..checksum_end].copy_from_slice(&checksum::encode(event));
+            footer_start = checksum_end;
+        }
+        dst[footer_start..footer_start + footer.len()].copy_from_slice(footer);
+        dst[footer_start + footer.len()..end].fill(PAD_BYTE);
+
+        self.stats.frames_encoded += 1;
+        debug!(
+            "MLLP: Encoded value for send at offset {}: '{:?}'",
+            offset,
+            &dst[offset..end]
+        );
+        Ok(())
+    }
+
+    /// Applies any configured encode-time checks and fix-ups to a payload (stripping a trailing segment terminator,
+    /// filling in a missing control ID etc), borrowing it unless something had to be inserted.
+    fn prepare_payload<'a>(&self, payload: &'a [u8]) -> Result<Cow<'a, [u8]>, MllpError> {
+        if self.config.reject_framed_payloads && self.is_framed(payload) {
+            return Err(MllpError::AlreadyFramed);
+        }
+
+        let payload = if self.config.strip_trailing_terminator {
+            payload
+                .strip_suffix(b"\r\n")
+                .or_else(|| payload.strip_suffix(b"\r"))
+                .or_else(|| payload.strip_suffix(b"\n"))
+                .unwrap_or(payload)
+        } else {
+            payload
+        };
+        let payload = if self.config.auto_control_id && hl7::control_id(payload).is_none() {
+            hl7::fill_control_id(payload, &hl7::generate_control_id())
+        } else {
+            Cow::Borrowed(payload)
+        };
+        let payload = match &self.config.encode_transform {
+            Some(transform) => Cow::Owned(transform(&payload)),
+            None => payload,
+        };
+        let payload = if self.config.prepend_bom && !payload.starts_with(UTF8_BOM) {
+            Cow::Owned([UTF8_BOM, &payload].concat())
+        } else {
+            payload
+        };
+        self.check_encoded_size(&payload)?;
+        Ok(payload)
+    }
+
+    /// Checks the frame for `payload` isn't over the max frame size, see [MllpCodecBuilder::check_encoded_size]
+    fn check_encoded_size(&self, payload: &[u8]) -> Result<(), MllpError> {
+        if !self.config.check_encoded_size {
+            return Ok(());
+        }
+        match self.config.max_frame_size_of(payload) {
+            Some(max) if payload.len() + self.framing_len() > max => {
+                Err(MllpError::FrameTooLarge {
+                    size: payload.len() + self.framing_len(),
+                    max,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether `payload` looks to be a whole frame already, see [MllpCodecBuilder::reject_framed_payloads]
+    fn is_framed(&self, payload: &[u8]) -> bool {
+        payload.first() == Some(&self.config.header)
+            && payload.len() > self.config.footer.len()
+            && payload.ends_with(&self.config.footer)
+    }
+}
+
+// The footer search, which doesn't depend on the output type
+impl MllpCodec {
+    #[cfg(feature = "noncompliance")]
+    fn get_footer_position(src: &[u8], footer: &[u8]) -> Option<usize> {
+        //search from start because we may have multiple messages on socket
+        let index = find_slice(footer, src);
+        match index {
+            Some(i) => trace!("MLLP: Found footer at index {}", i),
+            None => trace!("MLLP: Unable to find footer..."),
+        }
+        index
+    }
+
+    /// this is the spec-compliant version, that knows there can only be at most one message in the buffer due to the synchronous nature of the spec
+    #[cfg(not(feature = "noncompliance"))]
+    fn get_footer_position(src: &[u8], footer: &[u8]) -> Option<usize> {
+        //search from end (footer should be right at the end per spec)
+        let index = rfind_slice(footer, src);
+        match index {
+            Some(i) => trace!("MLLP: Found footer at index {}", i),
+            None => trace!("MLLP: Unable to find footer..."),
+        }
+        index
+    }
+}
+
+// Support encoding data as an MLLP Frame.
+// This is used for both the primary HL7 message sent from a publisher, and also any ACK/NACK messages sent from a Listener.
+impl<O> Encoder<BytesMut> for MllpCodec<O> {
+    type Error = MllpError;
+
+    fn encode(&mut self, event: BytesMut, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        self.reframe(&event, dst)
+    }
+}
+
+// Support decoding data from an MLLP Frame.
+// This is used for receiving the primary HL7 message in a listener, and also decoding any ACK/NACK responses in a publisher.
+impl<O: FromBytesMut> Decoder for MllpCodec<O> {
+    type Item = O; // For the moment all we do is return the underlying byte array, I'm not getting into message parsing here.
+    type Error = MllpError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        Ok(self.decode_frame(src)?.map(O::from_bytes_mut))
+    }
+}
+
+impl<O> MllpCodec<O> {
+    /// The body of [decode](Decoder::decode), before conversion to the output type
+    fn decode_frame(&mut self, src: &mut BytesMut) -> Result<Option<BytesMut>, MllpError> {
+        // We're lucky the MLLP is specced as synchronous, and requires an ACK before sending the
+        // next message, so we don't have to worry about multiple messages in the buffer (Edit: See the `noncompliance` feature flag for unpleasantness).
+
+        // we DO have to ignore any bytes prior to the BLOCK_HEADER per the spec
+        let started = self.config.slow_decode_threshold.map(|_| Instant::now());
+        self.resynced = false;
+        self.tee_input(src);
+
+        // If we don't have anything outstanding from a previous call just use the buffer passed in
+        let result = if self.buffer.is_empty() {
+            trace!("Empty local buffer, operating on passed buffer only");
+            self.decode_recovering(src)
+        } else {
+            // otherwise concat the previous data and current and work on that
+            self.buffer.reserve(src.len());
+            self.buffer.put_slice(src);
+            src.advance(src.len()); // this consumes the whole src buffer and keeps tokio happy
+
+            trace!("Operating on concat of previous and current buffers");
+            let mut buffer = std::mem::take(&mut self.buffer); // so we can borrow self for the options while we work
+            let result = self.decode_recovering(&mut buffer);
+            self.buffer = buffer;
+            result
+        };
+
+        if let Ok(None) = result {
+            // we didn't find a message
+
+            if self.buffer.is_empty() {
+                // if there's already data in the buffer we concatted it above, no need to do so again
+                // if here we need to concat the src buffer locally for future calls...
+
+                self.buffer.reserve(src.len());
+                self.buffer.put_slice(src);
+                src.advance(src.len()); // this consumes the whole src buffer and keeps tokio happy, but breaks the non-compliant variant that can have multiple messages in the buffer
+            }
+        }
+
+        match &result {
+            Ok(Some(frame)) => self.stats.record_decoded(frame.len()),
+            Ok(None) => {}
+            Err(_) => self.stats.decode_errors += 1,
+        }
+
+        // Only a buffer we're waiting on more data for is worth remembering the search position in, anything else has
+        // had bytes consumed (or repaired) from under the cached position
+        match result {
+            Ok(None) => self.footer_searched = self.buffer.len(),
+            _ => self.reset_search(),
+        }
+
+        if let Some(started) = started {
+            let frame_len = match &result {
+                Ok(Some(frame)) => frame.len(),
+                _ => self.buffer.len(),
+            };
+            self.log_if_slow(started.elapsed(), frame_len);
+        }
+
+        self.tee_pending = src.len(); // whatever's left has been copied
+        result
+    }
+
+    /// Copies the bytes of `src` not already copied by a previous call to the [tee](MllpCodec::with_tee), if any
+    fn tee_input(&mut self, src: &BytesMut) {
+        let sink = match &mut self.tee {
+            Some(sink) => sink,
+            None => return,
+        };
+        let new = &src[self.tee_pending.min(src.len())..];
+        if let Err(e) = sink.write_all(new) {
+            warn!("MLLP: Failed to tee {} received bytes: {}", new.len(), e);
+        }
+    }
+
+    /// Logs a decode that took longer than the configured threshold, returning whether it did
+    fn log_if_slow(&self, elapsed: Duration, frame_len: usize) -> bool {
+        match self.config.slow_decode_threshold {
+            Some(threshold) if elapsed >= threshold => {
+                trace!(
+                    "MLLP: Slow decode, {:?} spent on a {} byte frame",
+                    elapsed,
+                    frame_len
+                );
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Decodes and validates the next frame, handing any errors to the error hook to decide what happens next
+    fn decode_recovering(&mut self, buf: &mut BytesMut) -> Result<Option<BytesMut>, MllpError> {
+        // a hook that keeps rewriting the buffer without ever fixing it mustn't keep us here forever
+        const MAX_RETRIES: usize = 16;
+        let mut retries = 0;
+        loop {
+            let error = match self.decode_internal(buf) {
+                Ok(Some(mut frame)) => match self
+                    .verify_checksum(&mut frame)
+                    .and_then(|()| self.apply_transforms(&mut frame))
+                    .and_then(|()| self.validate(&frame))
+                {
+                    Ok(()) => return Ok(Some(frame)),
+                    Err(e) => e, // the frame's already been consumed
+                },
+                Ok(None) => return Ok(None),
+                Err(e) => e,
+            };
+
+            self.reset_search(); // the hook may rewrite the buffer
+            self.footer_count = None;
+            let hook = match &self.config.on_error {
+                Some(hook) => hook,
+                None => return Err(error),
+            };
+
+            let before = (buf.as_ptr(), buf.len()); // enough to tell a repair, without copying the buffer
+            match hook(&error, buf) {
+                RecoveryAction::Abort => return Err(error),
+                RecoveryAction::Retry if (buf.as_ptr(), buf.len()) == before => {
+                    debug!("MLLP: Retry requested without repairing the buffer, aborting");
+                    return Err(error);
+                }
+                RecoveryAction::Retry if retries == MAX_RETRIES => {
+                    debug!("MLLP: Still failing after {} retries, aborting", retries);
+                    return Err(error);
+                }
+                RecoveryAction::Retry => {
+                    retries += 1;
+                    debug!("MLLP: Retrying decode after {}", error);
+                }
+                RecoveryAction::Skip => {
+                    debug!("MLLP: Skipping frame after {}", error);
+                    if let MllpError::TooManyFrames { .. } = error {
+                        self.skipping = true; // nothing's been discarded yet, drop the first frame
+                    }
+                }
+            }
+        }
+    }
+
+    fn decode_internal(
+        &mut self,
+        buf_to_process: &mut BytesMut,
+    ) -> Result<Option<BytesMut>, MllpError> {
+        loop {
+            if self.skipping && !self.skip_to_next_footer(buf_to_process) {
+                return Ok(None);
+            }
+
+            // a read of just a footer (with nothing buffered) can be dealt with up front
+            if !self.config.allow_missing_header && buf_to_process[..] == self.config.footer[..] {
+                let footer_len = buf_to_process.len();
+                buf_to_process.clear();
+                self.stats.record_skipped(footer_len);
+                self.reset_search();
+                if self.config.reject_bare_footer {
+                    return Err(MllpError::InvalidFrame(
+                        "footer received with no frame".to_owned(),
+                    ));
+                }
+                trace!("MLLP: Discarding bare footer");
+                self.resynced = true;
+                return Ok(None);
+            }
+
+            // A stray footer left ahead of the next frame (eg by a sender doubling up its footers) would otherwise be
+            // taken as the end of that frame, so drop it as junk.  Without a header though, it really is an empty frame.
+            let footer_len = self.config.footer.len();
+            while (!self.config.allow_missing_header || self.config.footer_ends_previous_frame)
+                && buf_to_process.starts_with(&self.config.footer)
+            {
+                trace!("MLLP: Discarding stray footer");
+                self.resynced = true;
+                buf_to_process.advance(footer_len);
+                self.stats.record_skipped(footer_len);
+                self.reset_search();
+            }
+            // the LF of a footer split from it across reads
+            if !self.config.allow_missing_header {
+                self.consume_trailing_lf(buf_to_process);
+            }
+
+            // a buffer that's only been appended to since the last call still has its header where it was, and no
+            // header in the part that was searched without finding one
+            let header_offset = match self.header_at {
+                Some(header_offset) => Some(header_offset),
+                None => {
+                    let searched = self.footer_searched.min(buf_to_process.len());
+                    find_byte(self.config.header, &buf_to_process[searched..])
+                        .map(|offset| offset + searched)
+                }
+            };
+            self.header_at = header_offset;
+
+            let (start_offset, junk) = match header_offset {
+                Some(header_offset) => (header_offset + 1, header_offset), // data starts after the header byte
+                None if self.config.allow_missing_header => {
+                    trace!("MLLP: No header found, treating buffer start as start of data");
+                    (0, 0)
+                }
+                None => match self.config.max_prefix_skip {
+                    Some(max) if buf_to_process.len() > max => {
+                        let size = buf_to_process.len();
+                        debug!("MLLP: Discarding {} bytes received without a header", size);
+                        buf_to_process.clear();
+                        self.stats.record_skipped(size);
+                        self.reset_search();
+                        return Err(MllpError::PrefixTooLong { size, max });
+                    }
+                    _ => return Ok(None),
+                },
+            };
+
+            let start_offset = if self.config.collapse_repeated_headers {
+                let repeats = buf_to_process[start_offset..]
+                    .iter()
+                    .take_while(|b| **b == self.config.header)
+                    .count();
+                if repeats > 0 {
+                    trace!("MLLP: Collapsing {} repeated headers", repeats);
+                }
+                start_offset + repeats
+            } else {
+                start_offset
+            };
+
+            // we have a start of data, do we have a footer?
+
+            if self.config.one_frame_per_buffer {
+                let footer = &self.config.footer;
+                let first_end = find_slice(footer, &buf_to_process[start_offset..])
+                    .map(|i| start_offset + i + footer.len());
+                if let Some(first_end) = first_end {
+                    if find_byte(self.config.header, &buf_to_process[first_end..]).is_some() {
+                        buf_to_process.advance(first_end);
+                        self.stats.record_skipped(first_end);
+                        self.reset_search();
+                        return Err(MllpError::PipelinedFrame);
+                    }
+                }
+            }
+
+            if let Some(max) = self.config.max_buffered_frames {
+                let complete_frames = self.buffered_frames(buf_to_process);
+                if complete_frames > max {
+                    return Err(MllpError::TooManyFrames {
+                        count: complete_frames,
+                        max,
+                    });
+                }
+            }
+
+            if start_offset == buf_to_process.len() {
+                trace!("MLLP: Header is the last byte received, waiting on content");
+                return Ok(None); // nothing after the header to search for a footer in
+            }
+
+            let end_offset = match self.find_footer(buf_to_process, start_offset) {
+                Some(end_offset) => end_offset,
+                None => {
+                    // not yet, but there's no point buffering a frame we're going to reject anyway
+                    // (allowing for all but the last byte of the footer having arrived on their own)
+                    let size_so_far = buf_to_process.len() - start_offset;
+                    let content = &buf_to_process[start_offset..];
+                    if let Some(max) = self.config.max_frame_size_of(content) {
+                        if size_so_far > max + self.config.footer.len() - 1 {
+                            buf_to_process.advance(start_offset); // only count the content towards the frame size
+                            self.stats.record_skipped(start_offset);
+                            self.skipping = true;
+                            if self.config.oversize_action == OversizeAction::Skip {
+                                self.skipped_frame_len = Some(0);
+                            }
+                            self.skip_to_next_footer(buf_to_process);
+                            return self.oversized(size_so_far, max);
+                        }
+                    }
+                    return Ok(None);
+                }
+            };
+
+            //Is it worth passing a slice of src so we don't search the header chars?
+            //Most of the time the start_offset == 1, so not sure it's worth it.
+
+            let frame_end = end_offset + self.config.footer.len();
+            let size = end_offset.saturating_sub(start_offset);
+            let content = &buf_to_process[start_offset.min(end_offset)..end_offset];
+            if let Some(max) = self.config.max_frame_size_of(content) {
+                if size > max {
+                    buf_to_process.advance(frame_end); // drop the whole frame, footer and all
+                    self.reset_search();
+                    self.stats.record_skipped(frame_end);
+                    if self.config.oversize_action == OversizeAction::Skip {
+                        self.stats.record_frame_size(size);
+                    }
+                    self.oversized(size, max)?;
+                    continue; // we're skipping, see if there's anything else in the buffer
+                }
+            }
+
+            self.uncount_footers(buf_to_process, frame_end);
+            let mut result = buf_to_process
+                .split_to(frame_end) //get the footer bytes
+                .split_to(end_offset); // grab our data from the buffer, consuming (and losing) the footer
+
+            result.advance(start_offset); //move to start of data
+            self.last_offsets = Some((header_offset, end_offset));
+            self.stats.record_skipped(junk);
+            self.resynced |= junk > 0;
+            self.consume_trailing_lf(buf_to_process);
+
+            if result.is_empty() && self.config.skip_empty_frames {
+                trace!("MLLP: Skipping empty frame");
+                self.stats.record_skipped(frame_end - junk);
+                self.reset_search();
+                continue;
+            }
+
+            return Ok(Some(result));
+        }
+    }
+
+    /// Finds the footer terminating a frame whose data starts at `from`
+    fn find_footer(&self, buf: &BytesMut, from: usize) -> Option<usize> {
+        let footer = &self.config.footer[..];
+        // bytes already searched by a previous call can't hold a footer, bar the start of one split across reads
+        let searched = self.footer_searched.saturating_sub(footer.len() - 1);
+        // a footer ahead of the header (eg behind junk) can't end this frame, only one after it can
+        let from = from.max(searched);
+        if !self.config.allow_reversed_footer && !self.scan_forward {
+            let mut end = MllpCodec::get_footer_position(&buf[from..], footer)? + from;
+            if self.config.collapse_repeated_footers {
+                // the last footer's just the end of a run of them, the frame ends at the first
+                while end >= from + footer.len() && buf[..end].ends_with(footer) {
+                    end -= footer.len();
+                }
+            }
+            return Some(end);
+        }
+        if !self.config.allow_reversed_footer {
+            return find_slice(footer, &buf[from..]).map(|i| i + from);
+        }
+
+        // Search forwards for the first footer (in whichever byte order turns up first if reversed footers are allowed)
+        let candidates = buf[from..].windows(footer.len()).enumerate();
+        candidates
+            .map(|(i, window)| (i + from, window))
+            .find_map(|(i, window)| {
+                if window == footer {
+                    return Some(i);
+                }
+                if self.config.allow_reversed_footer && window.iter().eq(footer.iter().rev()) {
+                    if buf[i + 1..].starts_with(footer) {
+                        return Some(i + 1); // a trailing CR on the content, followed by a proper footer
+                    }
+                    trace!("MLLP: Found reversed footer at index {}", i);
+                    return Some(i);
+                }
+                None
+            })
+    }
+
+    /// Handles a frame over the max_frame_size, either erroring or carrying on (having discarded it) per the [OversizeAction].
+    fn oversized(&self, size: usize, max: usize) -> Result<Option<BytesMut>, MllpError> {
+        match self.config.oversize_action {
+            OversizeAction::Error => Err(MllpError::FrameTooLarge { size, max }),
+            OversizeAction::Skip => {
+                debug!(
+                    "MLLP: Skipping oversized frame ({} bytes, max {})",
+                    size, max
+                );
+                Ok(None)
+            }
+        }
+    }
+
+    /// Drops a LF directly after a footer (ie at the start of the buffer), if configured to
+    fn consume_trailing_lf(&mut self, buf: &mut BytesMut) {
+        if self.config.consume_trailing_lf && buf.first() == Some(&b'\n') {
+            trace!("MLLP: Consuming LF after footer");
+            self.uncount_footers(buf, 1);
+            buf.advance(1);
+            self.reset_search();
+        }
+    }
+
+    /// Forgets the cached search positions, for when the buffer has changed other than by appending to it
+    fn reset_search(&mut self) {
+        self.footer_searched = 0;
+        self.header_at = None;
+    }
+
+    /// Discards bytes up to and including the next footer, returning whether the footer was found (and we're back in sync).
+    fn skip_to_next_footer(&mut self, buf: &mut BytesMut) -> bool {
+        self.reset_search();
+        let footer = &self.config.footer;
+        match buf.windows(footer.len()).position(|w| w == footer) {
+            Some(footer_offset) => {
+                trace!("MLLP: Resynced at footer at index {}", footer_offset);
+                let frame_end = footer_offset + footer.len();
+                buf.advance(frame_end);
+                self.stats.record_skipped(frame_end);
+                if let Some(skipped) = self.skipped_frame_len.take() {
+                    self.stats.record_frame_size(skipped + footer_offset);
+                }
+                self.skipping = false;
+                true
+            }
+            None => {
+                // hang on to the start of a footer in case the rest of the footer is in the next packet
+                let keep = (1..footer.len())
+                    .rev()
+                    .find(|&n| buf.ends_with(&footer[..n]))
+                    .unwrap_or(0);
+                self.stats.record_skipped(buf.len() - keep);
+                if let Some(skipped) = &mut self.skipped_frame_len {
+                    *skipped += buf.len() - keep;
+                }
+                buf.advance(buf.len() - keep);
+                false
+            }
+        }
+    }
+
+    /// How many footers appear in `buf`, as an estimate of the frames it holds
+    fn count_footers(&self, buf: &[u8]) -> usize {
+        let footer = &self.config.footer[..];
+        buf.windows(footer.len()).filter(|w| *w == footer).count()
+    }
+
+    /// How many complete frames (ie footers) `buf` holds, only counting the bytes appended since the last count.  A
+    /// buffer that's moved (or had bytes consumed other than by [uncount_footers](MllpCodec::uncount_footers)) is
+    /// counted afresh.
+    fn buffered_frames(&mut self, buf: &[u8]) -> usize {
+        let start = buf.as_ptr() as usize;
+        let (counted, count) = match self.footer_count {
+            Some((at, counted, count)) if at == start && counted <= buf.len() => (counted, count),
+            _ => (0, 0),
+        };
+        // a footer split across the end of the counted bytes wasn't counted
+        let from = counted.saturating_sub(self.config.footer.len() - 1);
+        let count = count + self.count_footers(&buf[from..]);
+        self.footer_count = Some((start, buf.len(), count));
+        count
+    }
+
+    /// Drops the footers starting in the first `n` bytes of `buf` from the [buffered_frames](MllpCodec::buffered_frames)
+    /// count, as they're about to be consumed
+    fn uncount_footers(&mut self, buf: &[u8], n: usize) {
+        if let Some((start, counted, count)) = self.footer_count {
+            if start != buf.as_ptr() as usize || counted > buf.len() || n > counted {
+                self.footer_count = None; // stale, count afresh next time
+                return;
+            }
+            let consumed =
+                self.count_footers(&buf[..(n + self.config.footer.len() - 1).min(counted)]);
+            self.footer_count = Some((start + n, counted - n, count - consumed));
+        }
+    }
+
+    /// Runs the [content transforms](MllpCodec::transform_content) over a decoded frame, only copying it if they
+    /// rewrote it
+    fn apply_transforms(&self, frame: &mut BytesMut) -> Result<(), MllpError> {
+        let kept = match self.transform_content(frame)? {
+            Cow::Borrowed(content) => Ok((
+                content.as_ptr() as usize - frame.as_ptr() as usize,
+                content.len(),
+            )),
+            Cow::Owned(content) => Err(content),
+        };
+        match kept {
+            Ok((start, len)) => {
+                frame.advance(start);
+                frame.truncate(len);
+            }
+            Err(content) => *frame = BytesMut::from(&content[..]),
+        }
+        Ok(())
+    }
+
+    /// Strips or rejects NUL bytes in the frame, per the configured [NulHandling]
+    fn handle_nuls<'a>(&self, content: Cow<'a, [u8]>) -> Result<Cow<'a, [u8]>, MllpError> {
+        let first = match self.config.nul_handling {
+            NulHandling::PassThrough => return Ok(content),
+            _ => match content.iter().position(|b| *b == 0) {
+                Some(first) => first,
+                None => return Ok(content),
+            },
+        };
+        if self.config.nul_handling == NulHandling::Error {
+            return Err(MllpError::InvalidFrame(format!(
+                "NUL byte in frame content at offset {}",
+                first
+            )));
+        }
+        Ok(Cow::Owned(
+            content.iter().copied().filter(|b| *b != 0).collect(),
+        ))
+    }
+
+    fn transcode_utf16<'a>(&self, content: Cow<'a, [u8]>) -> Result<Cow<'a, [u8]>, MllpError> {
+        if !self.config.transcode_utf16 {
+            return Ok(content);
+        }
+        Ok(
+            match utf16::to_utf8(&content).map_err(MllpError::InvalidFrame)? {
+                Some(transcoded) => {
+                    trace!("MLLP: Transcoded {} bytes of UTF-16 content", content.len());
+                    Cow::Owned(transcoded.into_bytes())
+                }
+                None => content,
+            },
+        )
+    }
+
+    fn strip_bom<'a>(&self, content: Cow<'a, [u8]>) -> Cow<'a, [u8]> {
+        if !self.config.strip_bom {
+            return content;
+        }
+        match content {
+            Cow::Borrowed(content) => {
+                Cow::Borrowed(content.strip_prefix(UTF8_BOM).unwrap_or(content))
+            }
+            Cow::Owned(mut content) => {
+                if content.starts_with(UTF8_BOM) {
+                    content.drain(..UTF8_BOM.len());
+                }
+                Cow::Owned(content)
+            }
+        }
+    }
+
+    /// Applies any configured [decode_transform](MllpCodecBuilder::decode_transform) to the content
+    fn transform_inbound<'a>(&self, content: &'a [u8]) -> Cow<'a, [u8]> {
+        match &self.config.decode_transform {
+            Some(transform) => Cow::Owned(transform(content)),
+            None => Cow::Borrowed(content),
+        }
+    }
+
+    /// Checks and strips the frame's checksum, if configured to expect one
+    fn verify_checksum(&self, frame: &mut BytesMut) -> Result<(), MllpError> {
+        if !self.config.frame_checksum {
+            return Ok(());
+        }
+        match checksum::split(frame) {
+            Some((payload, expected)) if checksum::crc32(payload) == expected => {
+                frame.truncate(payload.len());
+                Ok(())
+            }
+            Some((payload, expected)) => Err(MllpError::ChecksumMismatch {
+                expected: Some(expected),
+                actual: checksum::crc32(payload),
+            }),
+            None => Err(MllpError::ChecksumMismatch {
+                expected: None,
+                actual: checksum::crc32(frame),
+            }),
+        }
+    }
+
+    fn validate(&self, frame: &[u8]) -> Result<(), MllpError> {
+        if frame.len() < self.config.min_frame_size {
+            return Err(MllpError::FrameTooSmall {
+                size: frame.len(),
+                min: self.config.min_frame_size,
+            });
+        }
+
+        let frame = &self.config.segment_ids(frame)[..];
+        if self.config.validate_hl7 {
+            validate::validate_hl7(frame, self.config.max_segment_len)
+                .map_err(MllpError::InvalidFrame)?;
+            if self.config.strict_encoding_characters {
+                validate::validate_encoding_characters(frame).map_err(MllpError::InvalidFrame)?;
+            }
+        }
+
+        if let Some(expected) = &self.config.expected_delimiters {
+            let separator = hl7::msh_field(frame, 1);
+            let encoding = hl7::msh_field(frame, 2);
+            let matches = match (separator, encoding) {
+                (Some(separator), Some(encoding)) => {
+                    expected.strip_prefix(separator) == Some(encoding)
+                }
+                _ => false,
+            };
+            if !matches {
+                return Err(MllpError::InvalidFrame(format!(
+                    "MSH delimiters {}{} don't match the expected {}",
+                    separator.unwrap_or("(missing)"),
+                    encoding.unwrap_or(""),
+                    expected
+                )));
+            }
+        }
+
+        if let Some(allowed) = &self.config.allowed_message_types {
+            let code = hl7::message_type(frame);
+            let trigger = hl7::trigger_event(frame);
+            let is_allowed = match code {
+                Some(code) => allowed.iter().any(|entry| match entry.split_once('^') {
+                    Some((entry_code, entry_trigger)) => {
+                        entry_code == code && Some(entry_trigger) == trigger
+                    }
+                    None => entry == code,
+                }),
+                None => false,
+            };
+            if !is_allowed {
+                return Err(MllpError::DisallowedMessageType {
+                    message_type: hl7::msh_field(frame, 9)
+                        .filter(|t| !t.is_empty())
+                        .map(str::to_owned),
+                });
+            }
+        }
+
+        match &self.config.validator {
+            Some(validator) => validator
+                .validate(frame, hl7::hl7_version(frame))
+                .map_err(MllpError::InvalidFrame),
+            None => Ok(()),
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wrap_for_mllp(s: &str) -> Bytes {
+        Bytes::from(format!("\x0B{}\x1C\x0D", s))
+    }
+
+    fn wrap_for_mllp_mut(s: &str) -> BytesMut {
+        BytesMut::from(format!("\x0B{}\x1C\x0D", s).as_str())
+    }
+
+    #[test]
+    fn frame_split_at_every_point() {
+        // content with footer-ish bytes in it, so a stale search position would show up as a short frame
+        let payload = "ab\x1Ccd\rOBX|1\r\x1C";
+        let stream = [b"junk".as_ref(), &wrap_for_mllp(payload)].concat();
+
+        for split in 0..stream.len() {
+            let mut m = MllpCodec::new();
+            assert_eq!(
+                m.decode(&mut BytesMut::from(&stream[..split])).unwrap(),
+                None,
+                "decoded early when split at {}",
+                split
+            );
+            let frame = m.decode(&mut BytesMut::from(&stream[split..])).unwrap();
+            assert_eq!(
+                frame.as_deref(),
+                Some(payload.as_bytes()),
+                "split at {}",
+                split
+            );
+            assert_eq!(m.buffered_len(), 0);
+        }
+    }
+
+    #[test]
+    fn frame_trickled_a_byte_at_a_time() {
+        let mut m = MllpCodec::new();
+        let stream = wrap_for_mllp("MSH|^~\\&|A\x1C|\rPID|1\x1C");
+
+        let mut frames = Vec::new();
+        for _ in 0..2 {
+            for b in stream.iter() {
+                let mut src = BytesMut::from(&[*b][..]);
+                if let Some(frame) = m.decode(&mut src).unwrap() {
+                    frames.push(frame);
+                }
+                // calls with nothing new (eg a read that was cancelled) mustn't lose our place either
+                assert_eq!(m.decode(&mut BytesMut::new()).unwrap(), None);
+            }
+        }
+        assert_eq!(frames, vec!["MSH|^~\\&|A\x1C|\rPID|1\x1C"; 2]);
+    }
+
+    #[test]
+    fn search_position_reset_between_pipelined_frames() {
+        let mut m = MllpCodec::new();
+        let mut frames = m
+            .decode_all(&mut BytesMut::from("\x0BFirst, long"))
+            .unwrap();
+        // the rest of the first frame completes in the same read as a short second frame
+        frames.extend(
+            m.decode_all(&mut BytesMut::from(" frame\x1C\x0D\x0BB\x1C\x0D"))
+                .unwrap(),
+        );
+        frames.extend(m.decode_all(&mut BytesMut::from("\x0BC")).unwrap());
+        frames.extend(m.decode_all(&mut BytesMut::from("\x1C\x0D")).unwrap());
+        assert_eq!(frames, vec!["First, long frame", "B", "C"]);
+    }
+
+    #[test]
+    fn lone_trailing_header_is_remembered() {
+        let mut m = MllpCodec::new();
+
+        // a read ending on the header byte is just a frame that's yet to arrive
+        assert_eq!(m.decode(&mut BytesMut::from("junk\x0B")).unwrap(), None);
+        assert_eq!(m.header_at, Some(4));
+
+        assert_eq!(m.decode(&mut BytesMut::from("Content")).unwrap(), None);
+        assert_eq!(m.header_at, Some(4)); // found where it was, rather than searched for again
+
+        let frame = m.decode(&mut BytesMut::from("\x1C\x0D")).unwrap();
+        assert_eq!(frame.unwrap(), "Content");
+        assert_eq!(m.header_at, None);
+        assert_eq!(m.stats().bytes_skipped, 4);
+
+        // junk with no header yet isn't searched again either
+        assert_eq!(m.decode(&mut BytesMut::from("noise")).unwrap(), None);
+        assert_eq!(m.decode(&mut BytesMut::from("\x0BNext")).unwrap(), None);
+        assert_eq!(m.header_at, Some(5));
+        let frame = m.decode(&mut BytesMut::from("\x1C\x0D")).unwrap();
+        assert_eq!(frame.unwrap(), "Next");
+    }
+
+    #[test]
+    fn header_as_last_byte_waits_for_content() {
+        let mut m = MllpCodec::new();
+        assert_eq!(m.decode(&mut BytesMut::from("\x0B")).unwrap(), None);
+        assert_eq!(m.buffered_len(), 1);
+        assert_eq!(m.header_at, Some(0));
+
+        let frame = m.decode(&mut BytesMut::from("Hello\x1C\x0D")).unwrap();
+        assert_eq!(frame.unwrap(), "Hello");
+        assert_eq!(m.stats().bytes_skipped, 0);
+    }
+
+    #[tokio::test]
+    async fn frame_wraps_io() {
+        use futures::{SinkExt, StreamExt};
+
+        let (local, remote) = tokio::io::duplex(1024);
+        let mut sender = MllpCodec::new().frame(local);
+        let mut receiver = MllpCodec::new().frame(remote);
+
+        sender.send(BytesMut::from("Hello")).await.unwrap();
+        assert_eq!(receiver.next().await.unwrap().unwrap(), "Hello");
+    }
+
+    #[tokio::test]
+    async fn transforms_round_trip() {
+        use futures::SinkExt;
+        use tokio::io::AsyncReadExt;
+
+        fn xor(payload: &[u8]) -> Vec<u8> {
+            payload.iter().map(|b| b ^ 0x20).collect()
+        }
+        let transforming = || {
+            MllpCodec::builder()
+                .encode_transform(xor)
+                .decode_transform(xor)
+                .build()
+                .unwrap()
+        };
+
+        let (local, mut remote) = tokio::io::duplex(1024);
+        let mut sender = transforming().frame(local);
+        sender
+            .send(BytesMut::from("MSH|^~\\&|LAB|1"))
+            .await
+            .unwrap();
+
+        let mut wire = BytesMut::new();
+        remote.read_buf(&mut wire).await.unwrap();
+        assert_eq!(
+            &wire[..],
+            &[&b"\x0B"[..], &xor(b"MSH|^~\\&|LAB|1"), b"\x1C\x0D"].concat()[..]
+        );
+
+        let frame = transforming().decode(&mut wire).unwrap().unwrap();
+        assert_eq!(frame, "MSH|^~\\&|LAB|1");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn cancelled_read_loses_nothing() {
+        use futures::StreamExt;
+        use std::time::Duration;
+        use tokio::io::AsyncWriteExt;
+
+        let (mut local, remote) = tokio::io::duplex(1024);
+        let mut transport = Framed::new(remote, MllpCodec::new());
+
+        local.write_all(b"\x0BHello, ").await.unwrap();
+        let read = tokio::time::timeout(Duration::from_secs(1), transport.next()).await;
+        assert!(read.is_err(), "frame returned before its footer arrived");
+
+        local.write_all(b"World\x1C\x0D").await.unwrap();
+        let frame = transport.next().await.unwrap().unwrap();
+        assert_eq!(frame, "Hello, World");
+    }
+
+    #[test]
+    fn can_construct_without_error() {
+        let _m = MllpCodec::new();
+    }
+
+    #[test]
+    fn decodes_as_either_output_type() {
+        let mut exclusive = MllpCodec::new();
+        let mut frame: BytesMut = exclusive
+            .decode(&mut wrap_for_mllp_mut("abcd"))
+            .unwrap()
+            .unwrap();
+        frame[0] = b'z'; // ours to modify
+        assert_eq!(&frame[..], b"zbcd");
+
+        let mut shared = MllpCodec::new().with_output::<Bytes>();
+        let frame: Bytes = shared
+            .decode(&mut wrap_for_mllp_mut("abcd"))
+            .unwrap()
+            .unwrap();
+        let copy = frame.clone();
+        assert_eq!(copy.as_ptr(), frame.as_ptr()); // the clone shares the frame
+        assert_eq!(&copy[..], b"abcd");
+
+        // state carries over the conversion
+        let mut codec = MllpCodec::new();
+        assert_eq!(codec.decode(&mut BytesMut::from("\x0Bab")).unwrap(), None);
+        let mut codec = codec.with_output::<Bytes>();
+        let frame = codec.decode(&mut BytesMut::from("cd\x1C\x0D")).unwrap();
+        assert_eq!(frame, Some(Bytes::from_static(b"abcd")));
+        assert_eq!(codec.stats().frames_decoded, 1);
+
+        let mut dst = BytesMut::new();
+        codec.encode(BytesMut::from("abcd"), &mut dst).unwrap();
+        assert_eq!(&dst[..], b"\x0Babcd\x1C\x0D");
+    }
+
+    #[test]
+    fn implements_default() {
+        let _m = MllpCodec::default();
+    }
+
+    #[test]
+    fn codecs_share_config() {
+        let config = Arc::new(
+            MllpCodec::builder()
+                .max_frame_size(4)
+                .build_config()
+                .unwrap(),
+        );
+        let mut codecs: Vec<MllpCodec> = (0..100)
+            .map(|_| MllpCodec::from_config(config.clone()))
+            .collect();
+
+        assert_eq!(Arc::strong_count(&config), 101);
+
+        for codec in codecs.iter_mut() {
+            assert!(Arc::ptr_eq(codec.config(), &config));
+            match codec.decode(&mut wrap_for_mllp_mut("abcd")) {
+                Ok(Some(message)) => assert_eq!(&message[..], b"abcd"),
+                other => panic!("Shared config codec failed to decode: {:?}", other),
+            }
+            match codec.decode(&mut wrap_for_mllp_mut("Test Data")) {
+                Err(MllpError::FrameTooLarge { .. }) => {}
+                other => panic!("Shared config not applied: {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn wraps_simple_data() {
+        let data = BytesMut::from("abcd");
+        let mut m = MllpCodec::new();
+
+        let mut output_buf = BytesMut::with_capacity(64);
+
+        match m.encode(data, &mut output_buf) {
+            Ok(()) => {}
+            _ => panic!("Non OK value returned from encode"),
+        }
+        let encoded_msg = output_buf.freeze();
+        println!("Encoded: {:?}", encoded_msg);
+        assert_eq!(encoded_msg, wrap_for_mllp("abcd"));
+    }
+
+    #[test]
+    fn encode_at_preserves_surrounding_bytes() {
+        let mut dst = BytesMut::from("0123456789ABCDEFGHIJ");
+        let mut m = MllpCodec::new();
+
+        m.encode_at(BytesMut::from("abcd"), &mut dst, 5).unwrap();
+
+        assert_eq!(&dst[..5], b"01234");
+        assert_eq!(&dst[5..12], &wrap_for_mllp("abcd")[..]);
+        assert_eq!(&dst[12..], b"CDEFGHIJ");
+    }
+
+    #[test]
+    fn encode_at_grows_short_buffer() {
+        let mut dst = BytesMut::from("012");
+        let mut m = MllpCodec::new();
+
+        m.encode_at(BytesMut::from("abcd"), &mut dst, 2).unwrap();
+
+        assert_eq!(&dst[..2], b"01");
+        assert_eq!(&dst[2..], &wrap_for_mllp("abcd")[..]);
+    }
+
+    #[test]
+    fn reframe_matches_encode() {
+        let mut m = MllpCodec::new();
+        let payload = Bytes::from_static(b"abcd");
+
+        let mut reframed = BytesMut::new();
+        m.reframe(&payload, &mut reframed).unwrap();
+
+        let mut encoded = BytesMut::new();
+        m.encode(BytesMut::from("abcd"), &mut encoded).unwrap();
+
+        assert_eq!(reframed, encoded);
+        assert_eq!(reframed.freeze(), wrap_for_mllp("abcd"));
+    }
+
+    #[test]
+    fn encodes_to_standalone_bytes() {
+        let mut m = MllpCodec::new();
+        let frame = m.encode_to_bytes(b"abcd").unwrap();
+        assert_eq!(frame, wrap_for_mllp("abcd"));
+        assert_eq!(m.stats().frames_encoded, 1);
+
+        let mut m = MllpCodec::builder().frame_checksum(true).build().unwrap();
+        let mut encoded = BytesMut::new();
+        m.encode(BytesMut::from("abcd"), &mut encoded).unwrap();
+        assert_eq!(m.encode_to_bytes(b"abcd").unwrap(), encoded);
+    }
+
+    #[test]
+    fn trailing_terminator_stripped_when_configured() {
+        let mut m = MllpCodec::builder()
+            .strip_trailing_terminator(true)
+            .build()
+            .unwrap();
+
+        for payload in ["PID|1\r", "PID|1\n", "PID|1\r\n", "PID|1"] {
+            let mut encoded = BytesMut::new();
+            m.encode(BytesMut::from(payload), &mut encoded).unwrap();
+            assert_eq!(encoded.freeze(), wrap_for_mllp("PID|1"), "{:?}", payload);
+        }
+
+        // only a single terminator goes, anything more is the sender's business
+        let mut encoded = BytesMut::new();
+        m.reframe(b"PID|1\r\r", &mut encoded).unwrap();
+        assert_eq!(encoded.freeze(), wrap_for_mllp("PID|1\r"));
+
+        let mut encoded = BytesMut::new();
+        m.encode_at(BytesMut::from("PID|1\r\n"), &mut encoded, 0)
+            .unwrap();
+        assert_eq!(encoded.freeze(), wrap_for_mllp("PID|1"));
+
+        // and by default the payload's left alone
+        let mut encoded = BytesMut::new();
+        MllpCodec::new()
+            .encode(BytesMut::from("PID|1\r"), &mut encoded)
+            .unwrap();
+        assert_eq!(encoded.freeze(), wrap_for_mllp("PID|1\r"));
+    }
+
+    #[test]
+    fn missing_control_id_generated_when_configured() {
+        let mut m = MllpCodec::builder().auto_control_id(true).build().unwrap();
+
+        let mut encoded = BytesMut::new();
+        m.encode(
+            BytesMut::from("MSH|^~\\&|ZIS||||||ADT^A01||P|2.3\r"),
+            &mut encoded,
+        )
+        .unwrap();
+        let payload = m.decode(&mut encoded).unwrap().unwrap();
+        let generated = hl7::control_id(&payload).unwrap().to_owned();
+        assert_eq!(hl7::msh_field(&payload, 11), Some("P")); // inserted, not overwriting the next field
+
+        // an ID is only generated where there isn't one already, and each frame gets its own
+        let mut encoded = BytesMut::new();
+        m.reframe(b"MSH|^~\\&|ZIS||||||ADT^A01|ID123|P|2.3\r", &mut encoded)
+            .unwrap();
+        let offset = encoded.len();
+        m.encode_at(BytesMut::from("MSH|^~\\&|ZIS"), &mut encoded, offset)
+            .unwrap();
+        let payloads = m.decode_all(&mut encoded).unwrap();
+        assert_eq!(hl7::control_id(&payloads[0]), Some("ID123"));
+        assert_ne!(hl7::control_id(&payloads[1]), Some(generated.as_str()));
+        assert!(hl7::control_id(&payloads[1]).is_some());
+
+        // and not at all by default
+        let mut encoded = BytesMut::new();
+        MllpCodec::new()
+            .encode(BytesMut::from("MSH|^~\\&|ZIS"), &mut encoded)
+            .unwrap();
+        assert_eq!(encoded.freeze(), wrap_for_mllp("MSH|^~\\&|ZIS"));
+    }
+
+    #[test]
+    fn checksummed_frames_round_trip() {
+        let mut m = MllpCodec::builder().frame_checksum(true).build().unwrap();
+
+        let mut encoded = BytesMut::new();
+        m.encode(BytesMut::from("MSH|^~\\&|ZIS\rEVN|A01"), &mut encoded)
+            .unwrap();
+        m.reframe(b"abcd", &mut encoded).unwrap();
+        let offset = encoded.len();
+        m.encode_at(BytesMut::from(""), &mut encoded, offset)
+            .unwrap();
+        assert_eq!(
+            encoded.len(),
+            m.framed_len(20) + m.framed_len(4) + m.framed_len(0)
+        );
+
+        let payloads = m.decode_all(&mut encoded).unwrap();
+        assert_eq!(payloads, vec!["MSH|^~\\&|ZIS\rEVN|A01", "abcd", ""]);
+    }
+
+    #[test]
+    fn corrupted_frame_fails_checksum() {
+        let mut m = MllpCodec::builder().frame_checksum(true).build().unwrap();
+        let mut encoded = BytesMut::new();
+        m.encode(BytesMut::from("abcd"), &mut encoded).unwrap();
+        encoded[2] = b'X';
+
+        match m.decode(&mut encoded) {
+            Err(MllpError::ChecksumMismatch {
+                expected: Some(expected),
+                actual,
+            }) => assert_ne!(expected, actual),
+            other => panic!("expected a checksum error, got {:?}", other),
+        }
+
+        // a frame from a peer that isn't checksumming is rejected too
+        let mut plain = BytesMut::from(&wrap_for_mllp("abcd")[..]);
+        assert!(matches!(
+            m.decode(&mut plain),
+            Err(MllpError::ChecksumMismatch { expected: None, .. })
+        ));
+    }
+
+    #[test]
+    fn encode_unchecked_matches_encode() {
+        let mut m = MllpCodec::new();
+        let mut encoded = BytesMut::new();
+        m.encode(BytesMut::from("abcd"), &mut encoded).unwrap();
+
+        let mut unchecked = BytesMut::with_capacity(m.framed_len(4));
+        m.encode_unchecked(BytesMut::from("abcd"), &mut unchecked)
+            .unwrap();
+        assert_eq!(unchecked, encoded);
+        assert_eq!(m.stats().frames_encoded, 2);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "spare capacity")]
+    fn encode_unchecked_asserts_capacity() {
+        let mut dst = BytesMut::with_capacity(4);
+        let _ = MllpCodec::new().encode_unchecked(BytesMut::from("abcd"), &mut dst);
+    }
+
+    #[test]
+    fn segment_crs_not_mistaken_for_footer() {
+        let message = "MSH|^~\\&|LAB|MYFAC|LAB||201411130917||ORU^R01|3216598|D|2.3|||AL|NE|\r\
+                       PID|1|ABC123DF|AND234DA_PID3|PID_4_ALTID|Patlast^Patfirst^Mid||19670202|F|\r\
+                       PV1|1|O|MYFACSOMPL||||^Xavarie^Sonna^^^^^XAVS|\r\
+                       OBR|1|PT1311:H00001R301.0100|PT1311:H00001R|301.0100^Complete Blood Count (CBC)|\r\
+                       OBX|1|NM|301.0500^White Blood Count (WBC)|1|10.1|10\\S\\9/L|3.1-9.7|H||A~S|F|\r\
+                       OBX|2|NM|301.0600^Red Blood Count (RBC)|1|3.2|10\\S\\12/L|3.7-5.0|L||A~S|F|\r";
+        assert_eq!(message.matches('\r').count(), 6);
+
+        let mut m = MllpCodec::new();
+        let frame = m.decode(&mut wrap_for_mllp_mut(message)).unwrap().unwrap();
+        assert_eq!(frame, message);
+
+        // nor when each read ends on a segment's CR
+        let framed = wrap_for_mllp(message);
+        let mut reads = framed.split_inclusive(|b| *b == b'\r').peekable();
+        while let Some(read) = reads.next() {
+            let decoded = m.decode(&mut BytesMut::from(read)).unwrap();
+            match reads.peek() {
+                Some(_) => assert_eq!(decoded, None),
+                None => assert_eq!(decoded.unwrap(), message),
+            }
+        }
+    }
+
+    #[test]
+    fn framed_payloads_rejected_when_configured() {
+        let mut m = MllpCodec::builder()
+            .reject_framed_payloads(true)
+            .build()
+            .unwrap();
+        let mut dst = BytesMut::new();
+        assert!(matches!(
+            m.encode(wrap_for_mllp_mut("MSH|^~\\&|LAB|1"), &mut dst),
+            Err(MllpError::AlreadyFramed)
+        ));
+        assert!(matches!(
+            m.encode_chunks(["\x0BMSH|^~\\&|", "LAB|1\x1C\x0D"], &mut dst),
+            Err(MllpError::AlreadyFramed)
+        ));
+        assert!(dst.is_empty());
+
+        m.encode(BytesMut::from("MSH|^~\\&|LAB|1"), &mut dst)
+            .unwrap();
+        assert_eq!(dst.freeze(), wrap_for_mllp("MSH|^~\\&|LAB|1"));
+
+        // by default what's given is what's framed
+        let mut dst = BytesMut::new();
+        MllpCodec::new()
+            .encode(wrap_for_mllp_mut("abcd"), &mut dst)
+            .unwrap();
+        assert_eq!(&dst[..], b"\x0B\x0Babcd\x1C\x0D\x1C\x0D");
+    }
+
+    #[test]
+    fn encodes_from_chunks() {
+        let segments: Vec<&[u8]> = vec![b"MSH|^~\\&|LAB|1\r", b"PID|1\r", b"OBX|1\r"];
+        let mut m = MllpCodec::new();
+        let mut dst = BytesMut::new();
+        m.encode_chunks(&segments, &mut dst).unwrap();
+        assert_eq!(
+            &dst[..],
+            &wrap_for_mllp("MSH|^~\\&|LAB|1\rPID|1\rOBX|1\r")[..]
+        );
+        assert_eq!(m.stats().frames_encoded, 1);
+
+        // the checksum and padding are worked out over the chunks as written
+        let mut m = MllpCodec::builder()
+            .frame_checksum(true)
+            .pad_to(16)
+            .build()
+            .unwrap();
+        let mut chunked = BytesMut::new();
+        m.encode_chunks(&segments, &mut chunked).unwrap();
+        let mut whole = BytesMut::new();
+        m.reframe(&segments.concat(), &mut whole).unwrap();
+        assert_eq!(chunked, whole);
+    }
+
+    #[test]
+    fn batch_round_trips_through_a_frame() {
+        let messages: [&[u8]; 3] = [
+            b"MSH|^~\\&|LAB||||||ORU^R01|1|P|2.3\rOBX|1\r",
+            b"MSH|^~\\&|LAB||||||ORU^R01|2|P|2.3\rOBX|1\r",
+            b"MSH|^~\\&|LAB||||||ORU^R01|3|P|2.3\rOBX|1\r",
+        ];
+        let mut m = MllpCodec::new();
+        let mut framed = BytesMut::new();
+        m.encode_batch(&messages, &mut framed).unwrap();
+        assert_eq!(m.stats().frames_encoded, 1);
+
+        let frame = m.decode(&mut framed).unwrap().unwrap();
+        assert!(frame.ends_with(b"BTS|3\rFTS|1\r"));
+        assert_eq!(batch::split_batch(&frame), messages);
+    }
+
+    #[test]
+    fn encode_raw_is_verbatim() {
+        let mut m = MllpCodec::new();
+        let framed = [wrap_for_mllp("abcd"), wrap_for_mllp("efgh")].concat();
+
+        let mut dst = BytesMut::from("prefix:");
+        m.encode_raw(&framed, &mut dst).unwrap();
+        assert_eq!(&dst[7..], &framed[..]);
+        assert_eq!(m.stats().frames_encoded, 0);
+
+        // and it round trips through a decoder untouched
+        let frames = MllpCodec::new().decode_all(&mut dst.split_off(7)).unwrap();
+        assert_eq!(frames, vec!["abcd", "efgh"]);
+    }
+
+    #[test]
+    fn framed_len_matches_encoded_frame() {
+        let mut m = MllpCodec::new();
+        let mut encoded = BytesMut::new();
+        m.encode(BytesMut::from("abcd"), &mut encoded).unwrap();
+        assert_eq!(m.framed_len(4), encoded.len());
+        assert_eq!(m.framed_len(0), 3);
+
+        let mut m = MllpCodec::builder()
+            .footer(&[0x1C, 0x0D, 0x0A])
+            .build()
+            .unwrap();
+        let mut encoded = BytesMut::new();
+        m.encode(BytesMut::from("abcd"), &mut encoded).unwrap();
+        assert_eq!(m.framed_len(4), encoded.len());
+        assert_eq!(m.framed_len(4), 8);
+    }
+
+    #[test]
+    fn padded_frames_fill_whole_blocks() {
+        let mut m = MllpCodec::builder().pad_to(16).build().unwrap();
+
+        let mut encoded = BytesMut::new();
+        m.encode(BytesMut::from("abcd"), &mut encoded).unwrap();
+        m.encode(BytesMut::from("0123456789abcdef"), &mut encoded)
+            .unwrap();
+        assert_eq!(encoded.len(), 16 + 32);
+        assert_eq!(encoded.len() % 16, 0);
+        assert_eq!(&encoded[..9], b"\x0Babcd\x1C\x0D\x00\x00");
+        assert_eq!(m.framed_len(4), 16);
+        assert_eq!(m.framed_len(13), 16); // exactly a block, no padding needed
+
+        // the padding is just inter-frame junk to the decoder
+        let frames = m.decode_all(&mut encoded).unwrap();
+        assert_eq!(frames, vec!["abcd", "0123456789abcdef"]);
+
+        let mut dst = BytesMut::from("prefix:");
+        m.encode_at(BytesMut::from("abcd"), &mut dst, 7).unwrap();
+        assert_eq!(dst.len(), 7 + 16);
+    }
+
+    #[test]
+    fn stx_etx_round_trips() {
+        let mut m = MllpCodec::stx_etx();
+
+        let mut encoded = BytesMut::new();
+        m.encode(BytesMut::from("MSH|^~\\&|A\r"), &mut encoded)
+            .unwrap();
+        m.encode(BytesMut::from("MSH|^~\\&|B\r"), &mut encoded)
+            .unwrap();
+        assert_eq!(&encoded[..13], b"\x02MSH|^~\\&|A\r\x03");
+        assert_eq!(m.framed_len(11), 13);
+
+        let frames = m.decode_all(&mut encoded).unwrap();
+        assert_eq!(frames, vec!["MSH|^~\\&|A\r", "MSH|^~\\&|B\r"]);
+
+        // MLLP framing bytes are just content, and frames still reassemble across reads
+        assert_eq!(
+            m.decode(&mut BytesMut::from("\x02a\x0Bb\x1C\x0D")).unwrap(),
+            None
+        );
+        let frame = m.decode(&mut BytesMut::from("c\x03")).unwrap();
+        assert_eq!(frame.as_deref(), Some(&b"a\x0Bb\x1C\x0Dc"[..]));
+    }
+
+    #[test]
+    fn custom_framing_round_trips() {
+        let mut m = MllpCodec::builder()
+            .header(0x02)
+            .footer(&[0x03, 0x0D, 0x0A])
+            .build()
+            .unwrap();
+
+        let mut encoded = BytesMut::new();
+        m.encode(BytesMut::from("abcd"), &mut encoded).unwrap();
+        assert_eq!(&encoded[..], b"\x02abcd\x03\x0D\x0A");
+
+        // standard framing bytes are just payload now
+        let mut src = BytesMut::from("\x02ab\x1C\x0Dcd\x03\x0D");
+        assert_eq!(m.decode(&mut src).unwrap(), None);
+        let frame = m.decode(&mut BytesMut::from("\x0A")).unwrap();
+        assert_eq!(frame.as_deref(), Some(&b"ab\x1C\x0Dcd"[..]));
+    }
+
+    #[test]
+    fn probes_for_complete_frame() {
+        let mut m = MllpCodec::new();
+        assert!(m.has_complete_frame(&wrap_for_mllp_mut("abcd")));
+        assert!(!m.has_complete_frame(&BytesMut::from("\x0Babcd")));
+        assert!(!m.has_complete_frame(&BytesMut::new()));
+        assert!(!m.has_complete_frame(&BytesMut::from("\x1C\x0D\x0Babcd"))); // the footer has to follow the header
+
+        // the start of the frame's been buffered by a previous decode, and the footer's split across reads
+        assert_eq!(m.decode(&mut BytesMut::from("\x0Babcd\x1C")).unwrap(), None);
+        assert!(!m.has_complete_frame(&BytesMut::new()));
+        let mut rest = BytesMut::from("\x0D");
+        assert!(m.has_complete_frame(&rest));
+        assert_eq!(m.decode(&mut rest).unwrap().unwrap(), "abcd");
+    }
+
+    #[test]
+    fn reports_partial_frame_progress() {
+        let mut m = MllpCodec::new();
+        assert_eq!(m.current_frame_progress(&BytesMut::new()), None);
+        assert_eq!(m.current_frame_progress(&BytesMut::from("junk")), None);
+        assert_eq!(
+            m.current_frame_progress(&BytesMut::from("junk\x0B")),
+            Some(0)
+        );
+
+        // progress carries on across reads, counting what's been buffered
+        assert_eq!(m.decode(&mut BytesMut::from("junk\x0Babcd")).unwrap(), None);
+        assert_eq!(m.current_frame_progress(&BytesMut::new()), Some(4));
+        assert_eq!(m.current_frame_progress(&BytesMut::from("efg")), Some(7));
+
+        // once the footer's arrived the frame's ready to decode, so there's nothing in progress
+        assert_eq!(m.current_frame_progress(&BytesMut::from("\x1C\x0D")), None);
+        assert_eq!(m.current_frame_progress(&wrap_for_mllp_mut("abcd")), None);
+    }
+
+    #[test]
+    fn junk_without_header_bounded() {
+        let mut m = MllpCodec::builder().max_prefix_skip(16).build().unwrap();
+        assert_eq!(m.decode(&mut BytesMut::from("0123456789")).unwrap(), None);
+        match m.decode(&mut BytesMut::from("0123456789")) {
+            Err(MllpError::PrefixTooLong { size: 20, max: 16 }) => {}
+            other => panic!("expected PrefixTooLong, got {:?}", other),
+        }
+        assert_eq!(m.buffered_len(), 0);
+        assert_eq!(m.stats().bytes_skipped, 20);
+
+        // a frame after the junk's discarded is decoded as usual, as is junk followed by a header
+        assert_eq!(
+            m.decode(&mut wrap_for_mllp_mut("abcd")).unwrap().unwrap(),
+            "abcd"
+        );
+        let mut src = BytesMut::from("0123456789012345678901234\x0Babcd\x1C\x0D");
+        assert_eq!(m.decode(&mut src).unwrap().unwrap(), "abcd");
+    }
+
+    #[test]
+    fn classifies_buffered_state() {
+        let mut m = MllpCodec::builder()
+            .max_frame_size(8)
+            .max_prefix_skip(8)
+            .build()
+            .unwrap();
+        assert_eq!(m.classify(&BytesMut::new()), FrameState::NoHeaderYet);
+        assert_eq!(m.classify(&BytesMut::from("junk")), FrameState::NoHeaderYet);
+        assert_eq!(
+            m.classify(&BytesMut::from("junk junk")),
+            FrameState::Invalid
+        );
+        assert_eq!(
+            m.classify(&BytesMut::from("junk\x0Bab")),
+            FrameState::PartialHeaderSeen
+        );
+        assert_eq!(m.classify(&wrap_for_mllp_mut("abcd")), FrameState::Complete);
+
+        // the state carries across reads, without classify consuming anything
+        assert_eq!(m.decode(&mut BytesMut::from("\x0Babcd")).unwrap(), None);
+        assert_eq!(m.classify(&BytesMut::new()), FrameState::PartialHeaderSeen);
+        assert_eq!(
+            m.classify(&BytesMut::from("\x1C\x0D")),
+            FrameState::Complete
+        );
+        assert_eq!(m.classify(&BytesMut::from("efghij")), FrameState::Invalid);
+        assert_eq!(m.buffered_len(), 5);
+    }
+
+    #[test]
+    fn reports_partial_frame_progress_pct() {
+        let mut m = MllpCodec::builder().max_frame_size(8).build().unwrap();
+        assert_eq!(m.current_frame_progress_pct(&BytesMut::new(), 8), None);
+
+        assert_eq!(m.decode(&mut BytesMut::from("\x0Babcd")).unwrap(), None);
+        assert_eq!(
+            m.current_frame_progress_pct(&BytesMut::new(), 8),
+            Some(50.0)
+        );
+        assert_eq!(
+            m.current_frame_progress_pct(&BytesMut::from("efghij"), 8),
+            Some(100.0)
+        );
+        assert_eq!(m.current_frame_progress_pct(&BytesMut::new(), 0), None);
+    }
+
+    #[test]
+    fn stray_footer_before_next_frame_discarded() {
+        let mut m = MllpCodec::new();
+        assert_eq!(
+            m.decode(&mut wrap_for_mllp_mut("data")).unwrap().unwrap(),
+            "data"
+        );
+
+        // the doubled footer turns up at the start of the next read, along with part of the next frame
+        let mut data = BytesMut::from("\x1C\x0D\x0Bne");
+        assert_eq!(m.decode(&mut data).unwrap(), None);
+        let frame = m.decode(&mut BytesMut::from("xt\x1C\x0D")).unwrap();
+        assert_eq!(frame.as_deref(), Some(&b"next"[..]));
+        assert_eq!(m.stats().bytes_skipped, 2);
+    }
+
+    #[test]
+    fn bare_footer_consumed_or_rejected() {
+        let mut m = MllpCodec::new();
+        let mut data = BytesMut::from("\x1C\x0D");
+        assert_eq!(m.decode(&mut data).unwrap(), None);
+        assert_eq!(m.buffered_len() + data.len(), 0);
+        assert_eq!(m.stats().bytes_skipped, 2);
+
+        let mut m = MllpCodec::builder()
+            .reject_bare_footer(true)
+            .build()
+            .unwrap();
+        let mut data = BytesMut::from("\x1C\x0D");
+        assert!(matches!(
+            m.decode(&mut data),
+            Err(MllpError::InvalidFrame(_))
+        ));
+        assert_eq!(m.buffered_len() + data.len(), 0);
+
+        // a stray footer ahead of a frame is still just dropped
+        let frame = m
+            .decode(&mut BytesMut::from("\x1C\x0D\x0Bnext\x1C\x0D"))
+            .unwrap();
+        assert_eq!(frame.as_deref(), Some(&b"next"[..]));
+    }
+
+    #[test]
+    fn resync_reported_for_last_decode() {
+        let mut m = MllpCodec::new();
+        assert!(!m.last_decode_resynced());
+
+        // the tail of a corrupted frame, then the next one
+        let frame = m
+            .decode(&mut BytesMut::from("rupted\x0Bnext\x1C\x0D"))
+            .unwrap();
+        assert_eq!(frame.as_deref(), Some(&b"next"[..]));
+        assert!(m.last_decode_resynced());
+
+        assert_eq!(
+            m.decode(&mut wrap_for_mllp_mut("clean")).unwrap().unwrap(),
+            "clean"
+        );
+        assert!(!m.last_decode_resynced());
+
+        let frame = m
+            .decode(&mut BytesMut::from("\x1C\x0D\x0Bnext\x1C\x0D"))
+            .unwrap();
+        assert_eq!(frame.as_deref(), Some(&b"next"[..]));
+        assert!(m.last_decode_resynced());
+    }
+
+    #[test]
+    fn reports_last_frame_offsets() {
+        let mut m = MllpCodec::new();
+        assert_eq!(
+            (m.last_header_offset(), m.last_footer_offset()),
+            (None, None)
+        );
+
+        let frame = m
+            .decode(&mut BytesMut::from("junk\x0Babcd\x1C\x0D"))
+            .unwrap();
+        assert_eq!(frame.unwrap(), "abcd");
+        assert_eq!(
+            (m.last_header_offset(), m.last_footer_offset()),
+            (Some(4), Some(9))
+        );
+
+        // a frame split across reads is measured within the combined buffer
+        assert_eq!(m.decode(&mut BytesMut::from("xy\x0Bab")).unwrap(), None);
+        let frame = m.decode(&mut BytesMut::from("cdef\x1C\x0D")).unwrap();
+        assert_eq!(frame.unwrap(), "abcdef");
+        assert_eq!(
+            (m.last_header_offset(), m.last_footer_offset()),
+            (Some(2), Some(9))
+        );
+
+        let mut m = MllpCodec::builder()
+            .allow_missing_header(true)
+            .build()
+            .unwrap();
+        m.decode(&mut BytesMut::from("abcd\x1C\x0D")).unwrap();
+        assert_eq!(
+            (m.last_header_offset(), m.last_footer_offset()),
+            (None, Some(4))
+        );
+    }
+
+    #[test]
+    fn leading_footer_ends_previous_frame() {
+        // each read as its own packet, as the compliant decoder would take them as one frame
+        let decode_each = |m: &mut MllpCodec, reads: &[&str]| -> Vec<Option<BytesMut>> {
+            reads
+                .iter()
+                .map(|read| m.decode(&mut BytesMut::from(*read)).unwrap())
+                .collect()
+        };
+        let reads = ["abcd\x1C\x0D", "\x1C\x0D", "\x0B\x1C\x0D", "efgh\x1C\x0D"];
+
+        let mut m = MllpCodec::builder()
+            .allow_missing_header(true)
+            .build()
+            .unwrap();
+        let frames = decode_each(&mut m, &reads);
+        assert_eq!(
+            frames,
+            ["abcd", "", "", "efgh"].map(|f| Some(BytesMut::from(f)))
+        );
+
+        // the stray footer's taken as the end of the first frame, but a headed empty frame is still a frame
+        let mut m = MllpCodec::builder()
+            .allow_missing_header(true)
+            .footer_ends_previous_frame(true)
+            .build()
+            .unwrap();
+        let frames = decode_each(&mut m, &reads);
+        let expected = [Some("abcd"), None, Some(""), Some("efgh")];
+        assert_eq!(frames, expected.map(|f| f.map(BytesMut::from)));
+        assert_eq!(m.stats().bytes_skipped, 2);
+
+        // skipping empty frames drops both
+        let mut m = MllpCodec::builder()
+            .allow_missing_header(true)
+            .skip_empty_frames(true)
+            .build()
+            .unwrap();
+        let frames = decode_each(&mut m, &reads);
+        let expected = [Some("abcd"), None, None, Some("efgh")];
+        assert_eq!(frames, expected.map(|f| f.map(BytesMut::from)));
+        assert_eq!(m.stats().bytes_skipped, 2 + 3);
+    }
+
+    #[test]
+    fn repeated_headers_collapsed_when_configured() {
+        let mut m = MllpCodec::new();
+        let frame = m
+            .decode(&mut BytesMut::from("\x0B\x0BMSH|^~\\&|LAB\x1C\x0D"))
+            .unwrap();
+        assert_eq!(frame.unwrap(), "\x0BMSH|^~\\&|LAB");
+
+        let mut m = MllpCodec::builder()
+            .collapse_repeated_headers(true)
+            .build()
+            .unwrap();
+        let frame = m
+            .decode(&mut BytesMut::from("\x0B\x0BMSH|^~\\&|LAB\x1C\x0D"))
+            .unwrap();
+        assert_eq!(frame.unwrap(), "MSH|^~\\&|LAB");
+
+        // including when the repeats are split across reads
+        assert_eq!(m.decode(&mut BytesMut::from("\x0B\x0B")).unwrap(), None);
+        let frame = m
+            .decode(&mut BytesMut::from("\x0BMSH|^~\\&|LAB\x1C\x0D"))
+            .unwrap();
+        assert_eq!(frame.unwrap(), "MSH|^~\\&|LAB");
+    }
+
+    #[test]
+    fn repeated_footers_collapsed() {
+        let mut m = MllpCodec::new();
+        let mut data = BytesMut::from("\x0Bdata\x1C\x0D\x1C\x0D");
+        assert_eq!(m.decode(&mut data).unwrap().unwrap(), "data");
+
+        // the stray footer's dropped rather than blocking the next frame
+        assert_eq!(m.decode(&mut data).unwrap(), None);
+        let frame = m.decode(&mut wrap_for_mllp_mut("next")).unwrap();
+        assert_eq!(frame.as_deref(), Some(&b"next"[..]));
+
+        // a frame that's nothing but footers is still an empty frame
+        let mut data = BytesMut::from("\x0B\x1C\x0D\x1C\x0D");
+        assert_eq!(m.decode(&mut data).unwrap().unwrap(), "");
+
+        // unless disabled, when the first footer's content (the non-compliant decoder ends frames at the first footer
+        // regardless)
+        #[cfg(not(feature = "noncompliance"))]
+        {
+            let mut m = MllpCodec::builder()
+                .collapse_repeated_footers(false)
+                .build()
+                .unwrap();
+            let mut data = BytesMut::from("\x0Bdata\x1C\x0D\x1C\x0D");
+            assert_eq!(m.decode(&mut data).unwrap().unwrap(), "data\x1C\x0D");
+        }
+    }
+
+    #[test]
+    fn find_footer_location() {
+        let data = wrap_for_mllp_mut("abcd"); //this gets the footer at position 5, as there's a leading byte added
+        let result = MllpCodec::get_footer_position(&data, &MLLP_FOOTER);
+
+        assert_eq!(result, Some(5));
+    }
+
+    #[test]
+    fn footer_matcher() {
+        let data = wrap_for_mllp_mut("abcd");
+
+        assert!(is_footer_at(&data, 5));
+        assert!(!is_footer_at(&data, 4));
+        assert!(!is_footer_at(&data, 6)); // only the CR left
+        assert!(!is_footer_at(&data, 100));
+        assert!(!is_footer_at(&data, usize::MAX));
+    }
+
+    #[test]
+    fn footer_search_skips_embedded_separators() {
+        let data = b"\x0Bab\x1Ccd\x1C\x1C\x0Def\x1C\x0D\x1C";
+        assert_eq!(find_slice(&MLLP_FOOTER, data), Some(7));
+        assert_eq!(find_slice(&MLLP_FOOTER, &data[..7]), None);
+        #[cfg(not(feature = "noncompliance"))]
+        assert_eq!(rfind_slice(&MLLP_FOOTER, data), Some(11));
+
+        let mut m = MllpCodec::new();
+        let frames = m.decode_all(&mut BytesMut::from(&data[..])).unwrap();
+        assert_eq!(frames, vec!["ab\x1Ccd\x1C"]);
+    }
+
+    #[test]
+    fn footer_before_header_not_taken_as_frame_end() {
+        let mut m = MllpCodec::new();
+        let frame = m
+            .decode(&mut BytesMut::from("\x1C\x0D\x0Bdata\x1C\x0D"))
+            .unwrap();
+        assert_eq!(frame.as_deref(), Some(&b"data"[..]));
+
+        // behind junk, the stray footer isn't at the start of the buffer to be dropped up front
+        let mut m = MllpCodec::new();
+        let frame = m
+            .decode(&mut BytesMut::from("xx\x1C\x0D\x0Bdata\x1C\x0D"))
+            .unwrap();
+        assert_eq!(frame.as_deref(), Some(&b"data"[..]));
+
+        // nor does it end a frame still waiting on its footer
+        let mut m = MllpCodec::new();
+        assert_eq!(
+            m.decode(&mut BytesMut::from("xx\x1C\x0D\x0Bda")).unwrap(),
+            None
+        );
+        let frame = m.decode(&mut BytesMut::from("ta\x1C\x0D")).unwrap();
+        assert_eq!(frame.as_deref(), Some(&b"data"[..]));
+    }
+
+    #[test]
+    fn missing_footer_detected() {
+        let data = BytesMut::from("no footer");
+        let result = MllpCodec::get_footer_position(&data, &MLLP_FOOTER);
+
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    fn ensure_decoder_finds_simple_message() {
+        let mut data = wrap_for_mllp_mut("abcd");
+        let mut m = MllpCodec::new();
+
+        let result = m.decode(&mut data);
+        println!("simple message result: {:?}", result);
+        match result {
+            Ok(None) => panic!("Failed to find a simple message!"),
+            Ok(Some(message)) => {
+                assert_eq!(&message[..], b"abcd");
+            }
+            Err(err) => panic!("Error looking for simple message: {:?}", err),
+        }
+    }
+
+    #[test]
+    fn ensure_data_after_end_is_ignored() {
+        // The MLLP spec states:
+        // "the Source system shall not send new HL7 content until an acknowledgement for the previous HL7 Content has been received."
+        // so we don't actually have to worry about this sort of data (ie more than 1 message per `receive`)
+
+        let mut data = BytesMut::from("\x0BTest Data\x1C\x0DMore Data");
+        let mut m = MllpCodec::new();
+
+        let result = m.decode(&mut data);
+
+        match result {
+            Ok(Some(message)) => {
+                assert_eq!(&message[..], b"Test Data");
+            }
+            _ => panic!("Failure for message with illegal trailing data"),
+        }
+    }
+
+    #[test]
+    fn ensure_no_data_is_left_on_the_stream() {
+        // we get errors from the tokio stuff if we close a connection with data still sitting unread on the stream.
+        // Ensure we remove it all as part of the decoder
+        let mut data = BytesMut::from("\x0BTest Data\x1C\x0D");
+        let mut m = MllpCodec::new();
+
+        let _result = m.decode(&mut data);
+
+        assert_eq!(
+            data.len(),
+            0,
+            "Decoder left data sitting in the buffer after read!"
+        );
+    }
+
+    #[test]
+    fn drain_returns_complete_frames_and_discards_partial() {
+        let mut m = MllpCodec::new();
+        let mut src = BytesMut::from(&[wrap_for_mllp("abc"), wrap_for_mllp("def")].concat()[..]);
+        src.extend_from_slice(b"\x0Bghi");
+
+        let frames = m.drain(&mut src);
+        assert_eq!(frames, vec![Bytes::from("abc"), Bytes::from("def")]);
+        assert!(src.is_empty());
+        assert_eq!(m.buffered_len(), 0);
+        assert_eq!(m.stats().bytes_skipped, 4);
+
+        // and the codec's good to go again afterwards
+        let mut next = wrap_for_mllp_mut("jkl");
+        assert_eq!(m.decode(&mut next).unwrap().unwrap(), "jkl");
+    }
+
+    #[test]
+    fn slow_decodes_logged_past_threshold() {
+        let m = MllpCodec::builder()
+            .slow_decode_threshold(Duration::from_millis(10))
+            .build()
+            .unwrap();
+        assert!(m.log_if_slow(Duration::from_millis(25), 1 << 20));
+        assert!(!m.log_if_slow(Duration::from_millis(5), 1 << 20));
+        assert!(!MllpCodec::new().log_if_slow(Duration::from_secs(60), 1 << 20));
+
+        // and a large frame through the real decode path, with a threshold it can't fail to hit
+        let mut m = MllpCodec::builder()
+            .slow_decode_threshold(Duration::ZERO)
+            .build()
+            .unwrap();
+        let payload = "A".repeat(1 << 20);
+        let mut src = wrap_for_mllp_mut(&payload);
+        assert_eq!(m.decode(&mut src).unwrap().unwrap().len(), payload.len());
+    }
+
+    #[test]
+    fn decodes_with_parsed_header() {
+        let sample =
+            "MSH|^~\\&|ZIS|1^AHospital|||200405141144||ADT^A01|20041104082400|P|2.3\rEVN|A01\r";
+        let mut m = MllpCodec::new();
+        let (frame, header) = m
+            .decode_with_header(&mut wrap_for_mllp_mut(sample))
+            .unwrap()
+            .unwrap();
+        assert_eq!(frame, sample.as_bytes());
+        assert_eq!(header.control_id.as_deref(), Some("20041104082400"));
+        assert_eq!(header.message_type.as_deref(), Some("ADT^A01"));
+        assert_eq!(header.version.as_deref(), Some("2.3"));
+
+        assert!(matches!(
+            m.decode_with_header(&mut wrap_for_mllp_mut("EVN|A01")),
+            Err(MllpError::InvalidFrame(_))
+        ));
+        assert_eq!(m.decode_with_header(&mut BytesMut::new()).unwrap(), None);
+    }
+
+    #[test]
+    fn nul_bytes_handled_as_configured() {
+        let build = |handling| MllpCodec::builder().nul_handling(handling).build().unwrap();
+        let framed = || wrap_for_mllp_mut("\0PID|1\0|\0\0x\0");
+
+        let mut m = build(NulHandling::PassThrough);
+        assert_eq!(
+            m.decode(&mut framed()).unwrap().unwrap(),
+            "\0PID|1\0|\0\0x\0"
+        );
+        assert_eq!(
+            MllpCodec::new().decode(&mut framed()).unwrap().unwrap(),
+            "\0PID|1\0|\0\0x\0"
+        );
+
+        let mut m = build(NulHandling::Strip);
+        assert_eq!(m.decode(&mut framed()).unwrap().unwrap(), "PID|1|x");
+
+        let mut m = build(NulHandling::Error);
+        assert!(matches!(
+            m.decode(&mut framed()),
+            Err(MllpError::InvalidFrame(_))
+        ));
+        assert_eq!(
+            m.decode(&mut wrap_for_mllp_mut("PID|1")).unwrap().unwrap(),
+            "PID|1"
+        );
+    }
+
+    #[test]
+    fn lf_after_footer_consumed_when_configured() {
+        let stream = "\x0BFirst\x1C\x0D\x0A\x0BSecond\x1C\x0D\x0A";
+
+        let mut m = MllpCodec::builder()
+            .consume_trailing_lf(true)
+            .build()
+            .unwrap();
+        let mut src = BytesMut::from(stream);
+        assert_eq!(m.decode_all(&mut src).unwrap(), vec!["First", "Second"]);
+        assert!(src.is_empty());
+        assert_eq!(m.buffered_len(), 0);
+        assert_eq!(m.stats().bytes_skipped, 0);
+
+        // including when the LF arrives in the next read
+        assert_eq!(
+            m.decode(&mut BytesMut::from("\x0BThird\x1C\x0D"))
+                .unwrap()
+                .unwrap(),
+            "Third"
+        );
+        let mut next = BytesMut::from("\x0A\x0BFourth\x1C\x0D");
+        assert_eq!(m.decode(&mut next).unwrap().unwrap(), "Fourth");
+        assert_eq!(m.stats().bytes_skipped, 0);
+
+        // by default it's skipped as junk
+        let mut m = MllpCodec::new();
+        assert_eq!(
+            m.decode_all(&mut BytesMut::from(stream)).unwrap(),
+            vec!["First", "Second"]
+        );
+        assert_eq!(m.stats().bytes_skipped, 1); // the last LF is still waiting for a frame
+    }
+
+    #[test]
+    fn max_frame_size_by_message_type() {
+        let mut m = MllpCodec::builder()
+            .max_frame_size(64)
+            .max_frame_size_for("ORU", 4096)
+            .build()
+            .unwrap();
+        let framed = |message_type: &str| {
+            let image = "A".repeat(1000);
+            wrap_for_mllp_mut(&format!(
+                "MSH|^~\\&|LAB||||||{}|1|P|2.3\rOBX|1|ED|IMG||{}\r",
+                message_type, image
+            ))
+        };
+
+        assert!(m.decode(&mut framed("ORU^R01")).unwrap().is_some());
+        assert!(matches!(
+            m.decode(&mut framed("ADT^A01")),
+            Err(MllpError::FrameTooLarge { max: 64, .. })
+        ));
+
+        // a partial frame is held to its type's limit once MSH-9 has arrived, and the largest limit until then
+        let mut partial = framed("ADT^A01");
+        partial.truncate(200);
+        assert!(matches!(
+            m.decode(&mut partial),
+            Err(MllpError::FrameTooLarge { max: 64, .. })
+        ));
+        let mut m = MllpCodec::builder()
+            .max_frame_size(4)
+            .max_frame_size_for("ORU", 4096)
+            .build()
+            .unwrap();
+        assert_eq!(
+            m.decode(&mut BytesMut::from("\x0BMSH|^~\\&|LAB||||||OR"))
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn bom_prepended_and_stripped_when_configured() {
+        let mut sender = MllpCodec::builder().prepend_bom(true).build().unwrap();
+        let mut framed = BytesMut::new();
+        sender
+            .encode(BytesMut::from("MSH|^~\\&|ZIS\r"), &mut framed)
+            .unwrap();
+        assert_eq!(&framed[..], b"\x0B\xEF\xBB\xBFMSH|^~\\&|ZIS\r\x1C\x0D");
+        assert_eq!(framed.len(), sender.framed_len(3 + 13));
+
+        // a payload with its own isn't given a second
+        let mut again = BytesMut::new();
+        sender
+            .reframe(&framed[1..framed.len() - 2], &mut again)
+            .unwrap();
+        assert_eq!(again, framed);
+        let mut chunked = BytesMut::new();
+        sender
+            .encode_chunks(["MSH|^~\\&", "|ZIS\r"], &mut chunked)
+            .unwrap();
+        assert_eq!(chunked, framed);
+
+        let mut receiver = MllpCodec::builder()
+            .strip_bom(true)
+            .validate_hl7(true)
+            .build()
+            .unwrap();
+        let frame = receiver.decode(&mut framed.clone()).unwrap().unwrap();
+        assert_eq!(&frame[..], b"MSH|^~\\&|ZIS\r");
+        let frame = receiver
+            .decode(&mut wrap_for_mllp_mut("MSH|^~\\&|ZIS\r"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(&frame[..], b"MSH|^~\\&|ZIS\r");
+
+        // and left be by default
+        let frame = MllpCodec::new().decode(&mut framed).unwrap().unwrap();
+        assert!(frame.starts_with(UTF8_BOM));
+    }
+
+    #[test]
+    fn content_transforms_borrow_unless_rewriting() {
+        fn borrowed(content: Result<Cow<'_, [u8]>, MllpError>) -> Option<&[u8]> {
+            match content.unwrap() {
+                Cow::Borrowed(content) => Some(content),
+                Cow::Owned(_) => None,
+            }
+        }
+        fn owned(content: Result<Cow<'_, [u8]>, MllpError>) -> Option<Vec<u8>> {
+            match content.unwrap() {
+                Cow::Borrowed(_) => None,
+                Cow::Owned(content) => Some(content),
+            }
+        }
+
+        let message = &b"MSH|^~\\&|ZIS\r"[..];
+        let with_bom = &b"\xEF\xBB\xBFMSH|^~\\&|ZIS\r"[..];
+
+        assert_eq!(
+            borrowed(MllpCodec::new().transform_content(with_bom)),
+            Some(with_bom)
+        );
+
+        let codec = MllpCodec::builder().strip_bom(true).build().unwrap();
+        assert_eq!(borrowed(codec.transform_content(with_bom)), Some(message));
+        assert_eq!(borrowed(codec.transform_content(message)), Some(message));
+
+        let codec = MllpCodec::builder().transcode_utf16(true).build().unwrap();
+        assert_eq!(borrowed(codec.transform_content(message)), Some(message));
+        let utf16: Vec<u8> = "MSH|^~\\&|ZIS\r"
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        assert_eq!(
+            owned(codec.transform_content(&utf16)).as_deref(),
+            Some(message)
+        );
+
+        let codec = MllpCodec::builder()
+            .nul_handling(NulHandling::Strip)
+            .build()
+            .unwrap();
+        assert_eq!(borrowed(codec.transform_content(message)), Some(message));
+        let content = codec.transform_content(b"MSH|^~\\&|Z\0IS\r");
+        assert_eq!(owned(content).as_deref(), Some(message));
+
+        let codec = MllpCodec::builder()
+            .decode_transform(|content| content.to_vec())
+            .build()
+            .unwrap();
+        assert_eq!(
+            owned(codec.transform_content(message)).as_deref(),
+            Some(message)
+        );
+
+        // decode strips the BOM without copying the frame out of the read buffer
+        let mut codec = MllpCodec::builder().strip_bom(true).build().unwrap();
+        let mut src = BytesMut::from(&b"\x0B\xEF\xBB\xBFMSH|^~\\&|ZIS\r\x1C\x0D"[..]);
+        let start = src.as_ptr() as usize;
+        let frame = codec.decode(&mut src).unwrap().unwrap();
+        assert_eq!(&frame[..], message);
+        assert_eq!(frame.as_ptr() as usize, start + 4);
+    }
+
+    #[test]
+    fn utf16_content_transcoded_when_configured() {
+        let message = "MSH|^~\\&|LAB|Zürich||||||ADT^A01|1|P|2.5|||||UNICODE UTF-16\rPID|1\r";
+        let mut framed = BytesMut::from(&b"\x0B\xFF\xFE"[..]);
+        framed.extend(message.encode_utf16().flat_map(u16::to_le_bytes));
+        framed.extend_from_slice(&MLLP_FOOTER);
+
+        let mut m = MllpCodec::builder()
+            .transcode_utf16(true)
+            .validate_hl7(true)
+            .build()
+            .unwrap();
+        let frame = m.decode(&mut framed.clone()).unwrap().unwrap();
+        assert_eq!(frame, message);
+        assert_eq!(hl7::msh_field(&frame, 18), Some("UNICODE UTF-16"));
+
+        // UTF-8 content's left as it is, and by default so is UTF-16
+        let frame = m.decode(&mut wrap_for_mllp_mut(message)).unwrap().unwrap();
+        assert_eq!(frame, message);
+        let frame = MllpCodec::new().decode(&mut framed).unwrap().unwrap();
+        assert_eq!(frame.len(), 2 + message.encode_utf16().count() * 2);
+    }
+
+    #[test]
+    fn tee_captures_everything_received() {
+        #[derive(Clone, Default)]
+        struct Capture(Arc<std::sync::Mutex<Vec<u8>>>);
+        impl Write for Capture {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let capture = Capture::default();
+        let mut m = MllpCodec::new().with_tee(capture.clone());
+        let reads: [&[u8]; 4] = [
+            b"junk\x0Bfir",
+            b"st\x1C\x0D\x1C\x0D",
+            b"\x0Bsecond\x1C",
+            b"\x0D",
+        ];
+        for read in reads {
+            m.decode(&mut BytesMut::from(read)).unwrap();
+        }
+        assert_eq!(*capture.0.lock().unwrap(), reads.concat());
+
+        // bytes left in src for the next call aren't copied twice
+        let capture = Capture::default();
+        let mut m = MllpCodec::new().with_tee(capture.clone());
+        let mut src = BytesMut::from("\x0Bfirst\x1C\x0D\x0Bsecond\x1C\x0D\x0Bthi");
+        let frames = m.decode_all(&mut src).unwrap();
+        assert_eq!(frames, vec!["first", "second"]);
+        src.extend_from_slice(b"rd\x1C\x0D");
+        assert_eq!(m.decode(&mut src).unwrap().unwrap(), "third");
+        assert_eq!(
+            *capture.0.lock().unwrap(),
+            b"\x0Bfirst\x1C\x0D\x0Bsecond\x1C\x0D\x0Bthird\x1C\x0D"
+        );
+    }
+
+    #[test]
+    fn min_frame_size_enforced() {
+        let mut m = MllpCodec::builder().min_frame_size(8).build().unwrap();
+        match m.decode(&mut wrap_for_mllp_mut("MSH|")) {
+            Err(MllpError::FrameTooSmall { size: 4, min: 8 }) => {}
+            other => panic!("expected FrameTooSmall, got {:?}", other),
+        }
+        assert_eq!(
+            m.decode(&mut wrap_for_mllp_mut("MSH|^~\\&|LAB|1"))
+                .unwrap()
+                .unwrap(),
+            "MSH|^~\\&|LAB|1"
+        );
+        assert_eq!(
+            m.decode(&mut wrap_for_mllp_mut("MSH|^~\\&"))
+                .unwrap()
+                .unwrap(),
+            "MSH|^~\\&"
+        );
+    }
+
+    #[test]
+    fn message_type_allowlist_enforced() {
+        let mut m = MllpCodec::builder()
+            .allowed_message_types(&["ADT", "ORU^R01"])
+            .build()
+            .unwrap();
+        let framed = |message_type: &str| {
+            wrap_for_mllp_mut(&format!("MSH|^~\\&|LAB||||||{}|1|P|2.3\r", message_type))
+        };
+
+        for allowed in ["ADT^A01", "ADT^A08", "ADT", "ORU^R01"] {
+            assert!(
+                m.decode(&mut framed(allowed)).unwrap().is_some(),
+                "{}",
+                allowed
+            );
+        }
+        for disallowed in ["ORU^R30", "ORM^O01", "ORU"] {
+            match m.decode(&mut framed(disallowed)) {
+                Err(MllpError::DisallowedMessageType { message_type }) => {
+                    assert_eq!(message_type.as_deref(), Some(disallowed))
+                }
+                other => panic!("{} was let through: {:?}", disallowed, other),
+            }
+        }
+        assert!(matches!(
+            m.decode(&mut framed("")),
+            Err(MllpError::DisallowedMessageType { message_type: None })
+        ));
+    }
+
+    #[test]
+    fn encoded_size_checked_when_configured() {
+        let mut m = MllpCodec::builder()
+            .max_frame_size(7)
+            .check_encoded_size(true)
+            .build()
+            .unwrap();
+        let mut dst = BytesMut::new();
+        m.encode(BytesMut::from("abcd"), &mut dst).unwrap(); // 4 + 3 bytes of framing
+        assert_eq!(&dst[..], b"\x0Babcd\x1C\x0D");
+
+        for result in [
+            m.encode(BytesMut::from("abcde"), &mut dst),
+            m.reframe(b"abcde", &mut dst),
+            m.encode_chunks(["abc", "de"], &mut dst),
+        ] {
+            match result {
+                Err(MllpError::FrameTooLarge { size: 8, max: 7 }) => {}
+                other => panic!("Oversized encode not rejected: {:?}", other),
+            }
+        }
+        assert_eq!(&dst[..], b"\x0Babcd\x1C\x0D"); // nothing written for the oversized payloads
+        assert_eq!(m.stats().frames_encoded, 1);
 
-        assert_eq!(result, None);
+        // not checked by default
+        let mut m = MllpCodec::builder().max_frame_size(7).build().unwrap();
+        m.encode(BytesMut::from("abcde"), &mut dst).unwrap();
     }
 
     #[test]
-    fn ensure_decoder_finds_simple_message() {
-        let mut data = wrap_for_mllp_mut("abcd");
+    fn drained_stats_reset() {
         let mut m = MllpCodec::new();
+        m.decode(&mut BytesMut::from("junk\x0Babcd\x1C\x0D"))
+            .unwrap();
+        m.encode(BytesMut::from("ab"), &mut BytesMut::new())
+            .unwrap();
+        assert!(m.decode(&mut BytesMut::from("\x0B")).unwrap().is_none());
 
-        let result = m.decode(&mut data);
-        println!("simple message result: {:?}", result);
-        match result {
-            Ok(None) => panic!("Failed to find a simple message!"),
-            Ok(Some(message)) => {
-                assert_eq!(&message[..], b"abcd");
+        let drained = m.drain_stats();
+        assert_eq!(
+            drained,
+            MllpStats {
+                frames_decoded: 1,
+                frames_encoded: 1,
+                bytes_skipped: 4,
+                decode_errors: 0,
+                max_frame_bytes: 4,
             }
-            Err(err) => panic!("Error looking for simple message: {:?}", err),
-        }
+        );
+        assert_eq!(m.stats(), MllpStats::default());
+        assert_eq!(m.drain_stats(), MllpStats::default());
+
+        // only counting from the drain, but the partial frame's still buffered
+        m.decode(&mut BytesMut::from("xy\x1C\x0D"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(m.stats().frames_decoded, 1);
+        assert_eq!(m.stats().max_frame_bytes, 2);
     }
 
     #[test]
-    fn ensure_data_after_end_is_ignored() {
-        // The MLLP spec states:
-        // "the Source system shall not send new HL7 content until an acknowledgement for the previous HL7 Content has been received."
-        // so we don't actually have to worry about this sort of data (ie more than 1 message per `receive`)
-
-        let mut data = BytesMut::from("\x0BTest Data\x1C\x0DMore Data");
+    fn max_frame_bytes_tracks_largest_frame() {
         let mut m = MllpCodec::new();
-
-        let result = m.decode(&mut data);
-
-        match result {
-            Ok(Some(message)) => {
-                assert_eq!(&message[..], b"Test Data");
-            }
-            _ => panic!("Failure for message with illegal trailing data"),
+        for payload in ["abc", "abcdefghij", "abcde"] {
+            m.decode(&mut wrap_for_mllp_mut(payload)).unwrap().unwrap();
         }
+        assert_eq!(m.stats().max_frame_bytes, 10);
     }
 
     #[test]
-    fn ensure_no_data_is_left_on_the_stream() {
-        // we get errors from the tokio stuff if we close a connection with data still sitting unread on the stream.
-        // Ensure we remove it all as part of the decoder
-        let mut data = BytesMut::from("\x0BTest Data\x1C\x0D");
-        let mut m = MllpCodec::new();
+    fn max_frame_bytes_includes_skipped_frames() {
+        let mut m = MllpCodec::builder()
+            .max_frame_size(8)
+            .oversize_action(OversizeAction::Skip)
+            .build()
+            .unwrap();
 
-        let _result = m.decode(&mut data);
+        // a complete oversized frame, skipped in one go
+        let mut src = wrap_for_mllp_mut(&"x".repeat(12));
+        assert_eq!(m.decode(&mut src).unwrap(), None);
+        assert_eq!(m.stats().max_frame_bytes, 12);
 
+        // and one skipped as it arrives, across several reads
         assert_eq!(
-            data.len(),
-            0,
-            "Decoder left data sitting in the buffer after read!"
+            m.decode(&mut BytesMut::from("\x0B0123456789")).unwrap(),
+            None
+        );
+        assert_eq!(m.decode(&mut BytesMut::from("0123456789")).unwrap(), None);
+        assert_eq!(m.stats().max_frame_bytes, 12); // the size isn't known until the footer turns up
+        assert_eq!(m.decode(&mut BytesMut::from("0123\x1C\x0D")).unwrap(), None);
+        assert_eq!(m.stats().max_frame_bytes, 24);
+
+        assert_eq!(
+            m.decode(&mut wrap_for_mllp_mut("ok")).unwrap().unwrap(),
+            "ok"
         );
+        assert_eq!(m.stats().max_frame_bytes, 24);
     }
 
     #[test]
@@ -415,6 +3501,606 @@ mod tests {
         }
     }
 
+    #[test]
+    fn missing_header_decodes_from_start_when_allowed() {
+        let mut mllp = MllpCodec::builder()
+            .allow_missing_header(true)
+            .build()
+            .unwrap();
+        let mut data = BytesMut::from("Test Data\x1C\x0D");
+
+        match mllp.decode(&mut data) {
+            Ok(Some(message)) => assert_eq!(&message[..], b"Test Data"),
+            _ => panic!("Footer-only frame not decoded with allow_missing_header on"),
+        }
+    }
+
+    #[test]
+    fn missing_header_not_decoded_by_default() {
+        let mut mllp = MllpCodec::new();
+        let mut data = BytesMut::from("Test Data\x1C\x0D");
+
+        match mllp.decode(&mut data) {
+            Ok(None) => {}
+            _ => panic!("Footer-only frame decoded with allow_missing_header off"),
+        }
+    }
+
+    #[test]
+    fn validator_receives_declared_version() {
+        let mut mllp = MllpCodec::builder()
+            .validator(|_: &[u8], version: Option<&str>| match version {
+                Some("2.3") => Ok(()),
+                other => Err(format!("Unsupported version {:?}", other)),
+            })
+            .build()
+            .unwrap();
+
+        let mut data = wrap_for_mllp_mut(
+            "MSH|^~\\&|ZIS|1^AHospital|||200405141144||ADT^A01|20041104082400|P|2.3\r",
+        );
+        match mllp.decode(&mut data) {
+            Ok(Some(_)) => {}
+            other => panic!("Valid v2.3 frame rejected: {:?}", other),
+        }
+
+        let mut data = wrap_for_mllp_mut(
+            "MSH|^~\\&|ZIS|1^AHospital|||200405141144||ADT^A01|20041104082400|P|2.5.1\r",
+        );
+        match mllp.decode(&mut data) {
+            Err(MllpError::InvalidFrame(reason)) => assert!(reason.contains("2.5.1")),
+            other => panic!("v2.5.1 frame not rejected: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn max_segment_len_enforced() {
+        let mut mllp = MllpCodec::builder().max_segment_len(19).build().unwrap();
+
+        let mut data = wrap_for_mllp_mut("MSH|^~\\&|ZIS\rOBX|1|ED|0123456789\r");
+        assert!(mllp.decode(&mut data).unwrap().is_some());
+
+        let mut data = wrap_for_mllp_mut("MSH|^~\\&|ZIS\rOBX|1|ED|0123456789A\r");
+        match mllp.decode(&mut data) {
+            Err(MllpError::InvalidFrame(reason)) => assert!(reason.contains("OBX")),
+            other => panic!("Over-long segment not rejected: {:?}", other),
+        }
+
+        // and it's HL7 validation, so a frame that isn't HL7 at all is rejected too
+        assert!(matches!(
+            mllp.decode(&mut wrap_for_mllp_mut("\x06")),
+            Err(MllpError::InvalidFrame(_))
+        ));
+    }
+
+    #[test]
+    fn strict_encoding_characters_enforced() {
+        let mut mllp = MllpCodec::builder()
+            .strict_encoding_characters(true)
+            .build()
+            .unwrap();
+
+        let mut data = wrap_for_mllp_mut("MSH|^~\\&|ZIS\r");
+        assert!(mllp.decode(&mut data).unwrap().is_some());
+
+        for truncated in ["MSH|^~\\|ZIS\r", "MSH|^~~&|ZIS\r"] {
+            match mllp.decode(&mut wrap_for_mllp_mut(truncated)) {
+                Err(MllpError::InvalidFrame(reason)) => assert!(reason.contains("MSH-2")),
+                other => panic!("Bad encoding characters not rejected: {:?}", other),
+            }
+        }
+
+        // only when asked for
+        let mut mllp = MllpCodec::builder().validate_hl7(true).build().unwrap();
+        assert!(mllp
+            .decode(&mut wrap_for_mllp_mut("MSH|^~\\|ZIS\r"))
+            .unwrap()
+            .is_some());
+    }
+
+    #[test]
+    fn footer_cr_not_counted_as_segment_separator() {
+        // the last segment has no CR of its own, so the only CR after it is the footer's
+        let mut mllp = MllpCodec::builder().max_segment_len(19).build().unwrap();
+        let mut data = wrap_for_mllp_mut("MSH|^~\\&|ZIS\rPID|1\rOBX|1|ED|0123456789");
+        let frame = mllp.decode(&mut data).unwrap().unwrap();
+        assert!(frame.ends_with(b"0123456789"));
+
+        let mut data = wrap_for_mllp_mut("MSH|^~\\&|ZIS\rPID|1\rOBX|1|ED|0123456789A");
+        match mllp.decode(&mut data) {
+            Err(MllpError::InvalidFrame(reason)) => {
+                assert!(reason.contains("segment 3 (OBX) is 20 bytes"), "{}", reason)
+            }
+            other => panic!("Over-long segment not rejected: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn lenient_segment_ids_validate() {
+        let sloppy = " msh|^~\\&|ZIS\rpid|1\r";
+        let mut strict = MllpCodec::builder().validate_hl7(true).build().unwrap();
+        assert!(strict.decode(&mut wrap_for_mllp_mut(sloppy)).is_err());
+
+        let mut lenient = MllpCodec::builder()
+            .validate_hl7(true)
+            .lenient_segment_ids(true)
+            .build()
+            .unwrap();
+        let frame = lenient.decode(&mut wrap_for_mllp_mut(sloppy)).unwrap();
+        assert_eq!(frame.unwrap(), sloppy); // the frame itself is handed back as sent
+    }
+
+    #[test]
+    fn expected_delimiters_enforced() {
+        let mut mllp = MllpCodec::builder()
+            .expect_delimiters("|^~\\&")
+            .build()
+            .unwrap();
+
+        let mut data = wrap_for_mllp_mut("MSH|^~\\&|ZIS|||||ADT^A01|1|P|2.3\r");
+        assert!(mllp.decode(&mut data).unwrap().is_some());
+
+        let mut data = wrap_for_mllp_mut("MSH#^~\\&#ZIS#####ADT^A01#1#P#2.3\r");
+        match mllp.decode(&mut data) {
+            Err(MllpError::InvalidFrame(reason)) => assert!(reason.contains("#^~\\&")),
+            other => panic!("Non-standard separator not rejected: {:?}", other),
+        }
+        assert!(data.is_empty()); // the bad frame's consumed, the stream can carry on
+
+        // no MSH at all can't match either
+        let mut data = wrap_for_mllp_mut("\x06");
+        assert!(matches!(
+            mllp.decode(&mut data),
+            Err(MllpError::InvalidFrame(_))
+        ));
+    }
+
+    #[test]
+    fn reversed_footer_decodes_when_allowed() {
+        let mut mllp = MllpCodec::builder()
+            .allow_reversed_footer(true)
+            .build()
+            .unwrap();
+        let mut data = BytesMut::from("\x0BTest Data\x0D\x1C");
+
+        match mllp.decode(&mut data) {
+            Ok(Some(message)) => assert_eq!(&message[..], b"Test Data"),
+            other => panic!("Reversed footer not accepted: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reversed_footer_not_decoded_by_default() {
+        let mut mllp = MllpCodec::new();
+        let mut data = BytesMut::from("\x0BTest Data\x0D\x1C");
+
+        match mllp.decode(&mut data) {
+            Ok(None) => {}
+            other => panic!("Reversed footer accepted by default: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn normal_footer_decodes_when_reversed_allowed() {
+        let mut mllp = MllpCodec::builder()
+            .allow_reversed_footer(true)
+            .build()
+            .unwrap();
+
+        let mut data = wrap_for_mllp_mut("Test Data");
+        match mllp.decode(&mut data) {
+            Ok(Some(message)) => assert_eq!(&message[..], b"Test Data"),
+            other => panic!("Normal footer not accepted: {:?}", other),
+        }
+
+        // content ending in a segment separator looks like a reversed footer followed by a CR
+        let mut data = wrap_for_mllp_mut("MSH|^~\\&|\rPID|1|\r");
+        match mllp.decode(&mut data) {
+            Ok(Some(message)) => assert_eq!(&message[..], b"MSH|^~\\&|\rPID|1|\r"),
+            other => panic!("Normal footer after trailing CR not accepted: {:?}", other),
+        }
+        assert!(data.is_empty());
+    }
+
+    #[test]
+    fn oversized_frame_errors_by_default() {
+        let mut mllp = MllpCodec::builder().max_frame_size(4).build().unwrap();
+        let mut data = wrap_for_mllp_mut("Test Data");
+
+        match mllp.decode(&mut data) {
+            Err(MllpError::FrameTooLarge { size: 9, max: 4 }) => {}
+            other => panic!("Oversized frame not rejected: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn frame_at_max_size_is_accepted() {
+        let mut mllp = MllpCodec::builder().max_frame_size(4).build().unwrap();
+        let mut data = wrap_for_mllp_mut("abcd");
+
+        match mllp.decode(&mut data) {
+            Ok(Some(message)) => assert_eq!(&message[..], b"abcd"),
+            other => panic!("Frame at max size not decoded: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn partial_oversized_frame_errors_before_footer() {
+        let mut mllp = MllpCodec::builder().max_frame_size(4).build().unwrap();
+        let mut data = BytesMut::from("\x0BTest Data with no footer yet");
+
+        match mllp.decode(&mut data) {
+            Err(MllpError::FrameTooLarge { max: 4, .. }) => {}
+            other => panic!("Oversized partial frame not rejected: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn oversized_frame_is_skipped() {
+        let mut mllp = MllpCodec::builder()
+            .max_frame_size(4)
+            .oversize_action(OversizeAction::Skip)
+            .build()
+            .unwrap();
+
+        let mut data = wrap_for_mllp_mut("Test Data");
+        match mllp.decode(&mut data) {
+            Ok(None) => {}
+            other => panic!("Oversized frame not skipped: {:?}", other),
+        }
+
+        let mut data = wrap_for_mllp_mut("abcd");
+        match mllp.decode(&mut data) {
+            Ok(Some(message)) => assert_eq!(&message[..], b"abcd"),
+            other => panic!("Frame after skipped frame not decoded: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn oversized_frame_split_over_calls_is_skipped() {
+        let mut mllp = MllpCodec::builder()
+            .max_frame_size(4)
+            .oversize_action(OversizeAction::Skip)
+            .build()
+            .unwrap();
+
+        let mut call1 = BytesMut::from("\x0BTest Data\x1C");
+        let mut call2 = BytesMut::from("\x0D");
+        let mut call3 = wrap_for_mllp_mut("abcd");
+
+        for call in [&mut call1, &mut call2] {
+            match mllp.decode(call) {
+                Ok(None) => {}
+                other => panic!("Oversized frame not skipped: {:?}", other),
+            }
+        }
+
+        match mllp.decode(&mut call3) {
+            Ok(Some(message)) => assert_eq!(&message[..], b"abcd"),
+            other => panic!("Frame after skipped frame not decoded: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn decode_all_returns_pipelined_frames() {
+        let mut mllp = MllpCodec::new();
+        let mut data = BytesMut::from("\x0BFirst\x1C\x0D\x0BSecond\x1C\x0D\x0BThi");
+
+        let frames = mllp.decode_all(&mut data).unwrap();
+        assert_eq!(frames, vec!["First", "Second"]);
+
+        let frames = mllp.decode_all(&mut BytesMut::from("rd\x1C\x0D")).unwrap();
+        assert_eq!(frames, vec!["Third"]);
+    }
+
+    #[test]
+    fn pipelined_frames_rejected_when_strict() {
+        let pipelined = "\x0BFirst\x1C\x0D\x0BSecond\x1C\x0D";
+
+        let mut m = MllpCodec::new();
+        let frames = m.decode_all(&mut BytesMut::from(pipelined)).unwrap();
+        assert_eq!(frames, vec!["First", "Second"]);
+
+        let mut m = MllpCodec::builder()
+            .one_frame_per_buffer(true)
+            .build()
+            .unwrap();
+        let mut data = BytesMut::from(pipelined);
+        assert!(matches!(
+            m.decode(&mut data),
+            Err(MllpError::PipelinedFrame)
+        ));
+        // just the start of a second frame is enough
+        let mut data = BytesMut::from("\x0BFirst\x1C\x0D\x0BSec");
+        assert!(matches!(
+            m.decode(&mut data),
+            Err(MllpError::PipelinedFrame)
+        ));
+        assert_eq!(&data[..], b"\x0BSec"); // the complete frame's discarded
+
+        // while a lone frame, even with trailing junk, is fine
+        let frame = m
+            .decode(&mut BytesMut::from("\x0BFirst\x1C\x0Djunk"))
+            .unwrap();
+        assert_eq!(frame.as_deref(), Some(&b"First"[..]));
+    }
+
+    #[test]
+    fn too_many_buffered_frames_errors() {
+        let mut mllp = MllpCodec::builder().max_buffered_frames(2).build().unwrap();
+        let mut data = BytesMut::from("\x0BFirst\x1C\x0D\x0BSecond\x1C\x0D\x0BThird\x1C\x0D");
+
+        match mllp.decode_all(&mut data) {
+            Err(MllpError::TooManyFrames { count: 3, max: 2 }) => {}
+            other => panic!("Buffered frame cap not enforced: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn buffered_frames_within_cap_decode() {
+        let mut mllp = MllpCodec::builder().max_buffered_frames(2).build().unwrap();
+        let mut data = BytesMut::from("\x0BFirst\x1C\x0D\x0BSecond\x1C\x0D");
+
+        assert_eq!(mllp.decode_all(&mut data).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn buffered_frames_counted_as_they_arrive() {
+        let mut m = MllpCodec::builder().max_buffered_frames(3).build().unwrap();
+        m.scan_forward = true; // one frame per decode, as a FrameReader does
+        let counted = |m: &MllpCodec| m.footer_count.map(|(_, counted, count)| (counted, count));
+
+        let mut src = BytesMut::from("\x0BFirst\x1C\x0D\x0BSecond\x1C\x0D\x0BThi");
+        assert_eq!(m.decode(&mut src).unwrap().unwrap(), "First");
+        assert_eq!(counted(&m), Some((src.len(), 1))); // the split frame's footer is dropped from the count
+
+        src.extend_from_slice(b"rd\x1C\x0D");
+        assert_eq!(m.decode(&mut src).unwrap().unwrap(), "Second");
+        assert_eq!(counted(&m), Some((src.len(), 1)));
+
+        src.extend_from_slice(b"\x0BFourth\x1C\x0D\x0BFifth\x1C\x0D\x0BSixth\x1C\x0D");
+        match m.decode(&mut src) {
+            Err(MllpError::TooManyFrames { count: 4, max: 3 }) => {}
+            other => panic!("Buffered frame cap not enforced: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn error_hook_can_skip_bad_frames() {
+        let mut mllp = MllpCodec::builder()
+            .validator(
+                |frame: &[u8], _: Option<&str>| match frame.starts_with(b"MSH") {
+                    true => Ok(()),
+                    false => Err("Not HL7".to_owned()),
+                },
+            )
+            .on_error(|_, _| RecoveryAction::Skip)
+            .build()
+            .unwrap();
+        let mut data = BytesMut::from("\x0BJunk\x1C\x0D\x0BMSH|^~\\&|\x1C\x0D");
+
+        let frames = mllp.decode_all(&mut data).unwrap();
+        assert_eq!(frames, vec!["MSH|^~\\&|"]);
+    }
+
+    #[test]
+    fn error_hook_skips_oversized_frame() {
+        let mut mllp = MllpCodec::builder()
+            .max_frame_size(4)
+            .on_error(recovery::lenient)
+            .build()
+            .unwrap();
+
+        match mllp.decode(&mut BytesMut::from("\x0BTest Data\x1C\x0D")) {
+            Ok(None) => {}
+            other => panic!("Oversized frame not skipped: {:?}", other),
+        }
+        match mllp.decode(&mut wrap_for_mllp_mut("abcd")) {
+            Ok(Some(message)) => assert_eq!(&message[..], b"abcd"),
+            other => panic!("Frame after skipped frame not decoded: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn error_hook_can_abort() {
+        let mut mllp = MllpCodec::builder()
+            .max_frame_size(4)
+            .on_error(recovery::strict)
+            .build()
+            .unwrap();
+
+        match mllp.decode(&mut BytesMut::from("\x0BTest Data\x1C\x0D")) {
+            Err(MllpError::FrameTooLarge { .. }) => {}
+            other => panic!("Oversized frame not rejected: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn error_hook_can_repair_and_retry() {
+        let mut mllp = MllpCodec::builder()
+            .max_buffered_frames(1)
+            .on_error(|_, buf: &mut BytesMut| {
+                buf.truncate(buf.len() / 2); // drop the second of two identical frames
+                RecoveryAction::Retry
+            })
+            .build()
+            .unwrap();
+
+        let mut data = BytesMut::from("\x0BTest\x1C\x0D\x0BTest\x1C\x0D");
+        assert_eq!(mllp.decode_all(&mut data).unwrap(), vec!["Test"]);
+    }
+
+    #[test]
+    fn unrepaired_retry_aborts() {
+        let mut mllp = MllpCodec::builder()
+            .max_buffered_frames(1)
+            .on_error(|_, _| RecoveryAction::Retry)
+            .build()
+            .unwrap();
+
+        let mut data = BytesMut::from("\x0BTest\x1C\x0D\x0BTest\x1C\x0D");
+        match mllp.decode(&mut data) {
+            Err(MllpError::TooManyFrames { .. }) => {}
+            other => panic!("Unrepaired retry didn't abort: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn endless_retries_abort() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counted = calls.clone();
+        let mut mllp = MllpCodec::builder()
+            .max_buffered_frames(1)
+            .on_error(move |_, buf: &mut BytesMut| {
+                counted.fetch_add(1, Ordering::Relaxed);
+                buf.extend_from_slice(b"x"); // a change, but not a fix
+                RecoveryAction::Retry
+            })
+            .build()
+            .unwrap();
+
+        let mut data = BytesMut::from("\x0BTest\x1C\x0D\x0BTest\x1C\x0D");
+        match mllp.decode(&mut data) {
+            Err(MllpError::TooManyFrames { .. }) => {}
+            other => panic!("Endless retries didn't abort: {:?}", other),
+        }
+        assert_eq!(calls.load(Ordering::Relaxed), 17); // 16 retries, then the abort
+    }
+
+    #[test]
+    fn decode_str_returns_valid_utf8() {
+        let mut mllp = MllpCodec::new();
+        let mut data = wrap_for_mllp_mut("PID||\"\"|10||Vries^Danny^D.^^de¶");
+
+        match mllp.decode_str(&mut data) {
+            Ok(Some(text)) => assert_eq!(text, "PID||\"\"|10||Vries^Danny^D.^^de¶"),
+            other => panic!("UTF-8 frame not decoded: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn decode_str_rejects_invalid_utf8() {
+        let mut mllp = MllpCodec::new();
+        let mut data = BytesMut::from(&b"\x0BVries^Danny\xE9\x1C\x0D"[..]); // Latin-1 e-acute
+
+        match mllp.decode_str(&mut data) {
+            Err(MllpError::InvalidUtf8(_)) => {}
+            other => panic!("Invalid UTF-8 not rejected: {:?}", other),
+        }
+        assert!(data.is_empty());
+    }
+
+    #[test]
+    fn high_water_signal_set_and_cleared() {
+        let mut m = MllpCodec::builder().high_water_mark(20).build().unwrap();
+
+        let mut src = BytesMut::new();
+        for payload in ["first", "second", "third"] {
+            src.extend_from_slice(&wrap_for_mllp(payload));
+        }
+        assert!(m.is_above_high_water(&src));
+
+        assert_eq!(m.decode_all(&mut src).unwrap().len(), 3);
+        assert!(!m.is_above_high_water(&src));
+
+        // a big partial frame held in the codec's own buffer counts too
+        assert_eq!(
+            m.decode(&mut BytesMut::from("\x0B0123456789abcdefghijk"))
+                .unwrap(),
+            None
+        );
+        assert!(m.is_above_high_water(&BytesMut::new()));
+        assert_eq!(
+            m.decode(&mut BytesMut::from("\x1C\x0D"))
+                .unwrap()
+                .unwrap()
+                .len(),
+            21
+        );
+        assert!(!m.is_above_high_water(&BytesMut::new()));
+
+        assert!(!MllpCodec::new().is_above_high_water(&src));
+    }
+
+    #[test]
+    fn decode_into_writes_payload_to_sink() {
+        let mut mllp = MllpCodec::new();
+        let mut sink = std::io::Cursor::new(Vec::new());
+
+        let mut data = BytesMut::from("junk\x0BMSH|^~\\&|A");
+        assert_eq!(mllp.decode_into(&mut data, &mut sink).unwrap(), None);
+        assert!(sink.get_ref().is_empty());
+
+        let mut data = BytesMut::from("\rPID|1\x1C\x0D");
+        assert_eq!(mllp.decode_into(&mut data, &mut sink).unwrap(), Some(16));
+        assert_eq!(sink.into_inner(), b"MSH|^~\\&|A\rPID|1");
+    }
+
+    #[test]
+    fn decode_into_buf_reuses_buffer() {
+        let mut m = MllpCodec::new();
+        let mut out = Vec::new();
+
+        let mut src = BytesMut::from(&wrap_for_mllp("abcdef")[..]);
+        assert!(m.decode_into_buf(&mut src, &mut out).unwrap());
+        assert_eq!(out, b"abcdef");
+        let capacity = out.capacity();
+
+        let mut src = BytesMut::from(&wrap_for_mllp("gh")[..]);
+        assert!(m.decode_into_buf(&mut src, &mut out).unwrap());
+        assert_eq!(out, b"gh"); // replaced, not appended
+        assert_eq!(out.capacity(), capacity);
+
+        assert!(!m.decode_into_buf(&mut src, &mut out).unwrap());
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn decode_into_surfaces_codec_errors() {
+        let mut mllp = MllpCodec::builder().max_frame_size(2).build().unwrap();
+        let error = mllp
+            .decode_into(&mut wrap_for_mllp_mut("abcd"), &mut Vec::new())
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert!(matches!(
+            error
+                .into_inner()
+                .unwrap()
+                .downcast::<MllpError>()
+                .as_deref(),
+            Ok(MllpError::FrameTooLarge { size: 4, max: 2 })
+        ));
+    }
+
+    #[test]
+    fn stats_reflect_operations() {
+        let mut mllp = MllpCodec::builder().max_frame_size(10).build().unwrap();
+
+        mllp.encode(BytesMut::from("abcd"), &mut BytesMut::new())
+            .unwrap();
+        mllp.encode_at(BytesMut::from("abcd"), &mut BytesMut::new(), 0)
+            .unwrap();
+
+        mllp.decode(&mut BytesMut::from("junk\x0BTest Data\x1C\x0D"))
+            .unwrap();
+        mllp.decode(&mut wrap_for_mllp_mut("abcd")).unwrap();
+        assert!(mllp
+            .decode(&mut wrap_for_mllp_mut("Far too much data"))
+            .is_err());
+
+        assert_eq!(
+            mllp.stats(),
+            MllpStats {
+                frames_decoded: 2,
+                frames_encoded: 2,
+                bytes_skipped: 4 + 20, // the junk, then the whole oversized frame
+                decode_errors: 1,
+                max_frame_bytes: 9,
+            }
+        );
+    }
+
     #[cfg(feature = "noncompliance")]
     mod noncompliance_tests {
         use super::*;
@@ -439,7 +4125,7 @@ mod tests {
                     // Check to make sure data is two messages and two encapsulations in size
                     assert_eq!(data.len(), (message.len() * 2) + 6);
                 }
-                _ => assert!(false),
+                _ => panic!("Error decoding pipelined message"),
             }
             // Read second message
             let result = mllp.decode(&mut data);
@@ -450,7 +4136,7 @@ mod tests {
                     // Check to make sure remaining data is the size of the message and encap
                     assert_eq!(data.len(), message.len() + 3);
                 }
-                _ => assert!(false),
+                _ => panic!("Error decoding pipelined message"),
             }
         }
     }
@@ -0,0 +1,119 @@
+use crate::{MllpCodec, MllpError};
+use bytes::BytesMut;
+use std::io::Read;
+use tokio_util::codec::Decoder;
+
+const READ_CHUNK: usize = 8 * 1024;
+
+/// Decodes MLLP frames from a blocking [Read], such as a capture file or a `std::net::TcpStream`, for tooling that
+/// doesn't want to spin up a tokio runtime.
+///
+/// Frames are searched for front-to-back, so a file containing many back-to-back frames decodes one frame at a time.
+/// Example:
+/// ```
+/// use hl7_mllp_codec::FrameReader;
+///
+/// let capture: &[u8] = b"\x0BFirst\x1C\x0D\x0BSecond\x1C\x0D";
+/// let frames: Vec<_> = FrameReader::new(capture).collect::<Result<_, _>>().unwrap();
+/// assert_eq!(frames, vec!["First", "Second"]);
+/// ```
+pub struct FrameReader<R> {
+    reader: R,
+    codec: MllpCodec,
+    pending: BytesMut,
+}
+
+impl<R: Read> FrameReader<R> {
+    /// Reads frames from `reader` using a default [MllpCodec].
+    pub fn new(reader: R) -> Self {
+        FrameReader::with_codec(reader, MllpCodec::new())
+    }
+
+    /// Reads frames from `reader` using a configured codec.
+    pub fn with_codec(reader: R, mut codec: MllpCodec) -> Self {
+        codec.scan_forward = true; // a stream can hold any number of frames, we want them in order
+        FrameReader {
+            reader,
+            codec,
+            pending: BytesMut::new(),
+        }
+    }
+
+    /// Reads the next frame, returning `Ok(None)` at the end of the input.  Input ending part way through a frame
+    /// fails with [MllpError::ConnectionClosed].
+    pub fn read_frame(&mut self) -> Result<Option<BytesMut>, MllpError> {
+        let mut chunk = [0u8; READ_CHUNK];
+        loop {
+            if let Some(frame) = self.codec.decode(&mut self.pending)? {
+                return Ok(Some(frame));
+            }
+
+            let read = self.reader.read(&mut chunk)?;
+            if read == 0 {
+                return if self.codec.buffered_len() == 0 && self.pending.is_empty() {
+                    Ok(None)
+                } else {
+                    Err(MllpError::ConnectionClosed)
+                };
+            }
+            self.pending.extend_from_slice(&chunk[..read]);
+        }
+    }
+
+    /// Returns the codec, eg to check its [stats](MllpCodec::stats).
+    pub fn codec(&self) -> &MllpCodec {
+        &self.codec
+    }
+
+    /// Consumes the reader, returning the underlying `Read`.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: Read> Iterator for FrameReader<R> {
+    type Item = Result<BytesMut, MllpError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_frame().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Hands out a single byte per read, to exercise frames split across reads
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            match self.0.split_first() {
+                Some((b, rest)) if !buf.is_empty() => {
+                    buf[0] = *b;
+                    self.0 = rest;
+                    Ok(1)
+                }
+                _ => Ok(0),
+            }
+        }
+    }
+
+    #[test]
+    fn reads_frames_split_across_reads() {
+        let mut reader = FrameReader::new(Trickle(b"junk\x0BFirst\x1C\x0D\x0BSecond\x1C\x0D"));
+        assert_eq!(reader.read_frame().unwrap().unwrap(), "First");
+        assert_eq!(reader.read_frame().unwrap().unwrap(), "Second");
+        assert!(reader.read_frame().unwrap().is_none());
+    }
+
+    #[test]
+    fn truncated_frame_is_an_error() {
+        let mut reader = FrameReader::new(&b"\x0BFirst\x1C\x0D\x0BSec"[..]);
+        assert_eq!(reader.read_frame().unwrap().unwrap(), "First");
+        assert!(matches!(
+            reader.read_frame(),
+            Err(MllpError::ConnectionClosed)
+        ));
+    }
+}
@@ -0,0 +1,31 @@
+//! Hooks for deciding how the decoder recovers from errors, see [MllpCodecBuilder::on_error](crate::MllpCodecBuilder::on_error).
+
+use crate::MllpError;
+use bytes::BytesMut;
+
+/// What the decoder should do after an error, as decided by an error hook.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecoveryAction {
+    /// Discard the offending frame and carry on decoding whatever follows it.
+    Skip,
+    /// Return the error from `decode`, which generally tears down the connection.
+    Abort,
+    /// Try decoding the buffer again, presumably because the hook has repaired it.  If the hook left the buffer's
+    /// length and start unchanged (so a repair made in place needs to drop or add bytes too) this is treated as
+    /// [Abort](RecoveryAction::Abort), rather than spinning forever on the same error, as are retries beyond 16 in a
+    /// single decode.
+    Retry,
+}
+
+/// The signature of an error hook: given the error and the decoder's working buffer, decide how to recover.
+pub type ErrorHook = dyn Fn(&MllpError, &mut BytesMut) -> RecoveryAction + Send + Sync;
+
+/// Fails the decode on any error.  This is the behaviour without a hook.
+pub fn strict(_error: &MllpError, _buf: &mut BytesMut) -> RecoveryAction {
+    RecoveryAction::Abort
+}
+
+/// Skips the offending frame on any error, keeping the connection alive at the cost of dropping data.
+pub fn lenient(_error: &MllpError, _buf: &mut BytesMut) -> RecoveryAction {
+    RecoveryAction::Skip
+}
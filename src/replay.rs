@@ -0,0 +1,67 @@
+use crate::{FrameReader, MllpClient, MllpCodec, MllpError};
+use bytes::BytesMut;
+use futures::SinkExt;
+use log::debug;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::time::Duration;
+use tokio::io;
+use tokio::net::{TcpStream, ToSocketAddrs};
+use tokio_util::codec::FramedWrite;
+
+/// Re-sends each frame in an MLLP capture file to the listener at `addr`, returning the number of frames sent, for load
+/// testing and reproducing issues seen in production.
+///
+/// With `respect_ack` each frame waits for the listener's ACK before the next is sent, as the spec requires, and the
+/// connection's shut down once the last ACK is in.  Without it the frames are fired off back to back and any ACKs read
+/// and discarded as they arrive (so a listener blocked on writing them doesn't stop reading), which is handy for
+/// finding out what a listener does when a sender _doesn't_ follow the spec.  Once everything's sent the connection's
+/// shut down, and the replay finishes when the listener hangs up.
+///
+/// The capture is read with a blocking [FrameReader], a frame at a time.  Those file reads run on the async executor's
+/// thread, blocking whatever else it's running while they do, so run big replays on a runtime (or thread) of their
+/// own.  Requires the `replay` crate feature.
+/// Example:
+/// ```no_run
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), hl7_mllp_codec::MllpError> {
+/// let sent = hl7_mllp_codec::replay_file("capture.mllp", "127.0.0.1:8080", true).await?;
+/// println!("Replayed {} frames", sent);
+/// # Ok(())
+/// # }
+/// ```
+pub async fn replay_file<P, A>(path: P, addr: A, respect_ack: bool) -> Result<usize, MllpError>
+where
+    P: AsRef<Path>,
+    A: ToSocketAddrs,
+{
+    let frames = FrameReader::new(BufReader::new(File::open(path)?));
+    let stream = TcpStream::connect(addr).await?;
+
+    let mut sent = 0;
+    if respect_ack {
+        let mut client = MllpClient::new(stream);
+        for frame in frames {
+            client.send(frame?).await?;
+            client.receive_ack().await?;
+            sent += 1;
+        }
+        client.shutdown(Duration::from_secs(0)).await?; // nothing's awaiting an ACK by now
+    } else {
+        let (mut read, write) = stream.into_split();
+        let mut transport = FramedWrite::new(write, MllpCodec::new());
+        let send = async {
+            for frame in frames {
+                transport.feed(frame?).await?;
+                sent += 1;
+            }
+            SinkExt::<BytesMut>::close(&mut transport).await // flushes, then shuts down our side
+        };
+        let drain = async { Ok(io::copy(&mut read, &mut io::sink()).await?) };
+        futures::future::try_join(send, drain).await?;
+    }
+
+    debug!("MLLP: Replayed {} frames", sent);
+    Ok(sent)
+}
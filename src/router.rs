@@ -0,0 +1,97 @@
+use crate::hl7::{receiving_facility, sending_facility};
+use std::collections::HashMap;
+
+/// Which MSH field a [Router] routes on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RouteBy {
+    /// Route on the sending facility (MSH-4), eg to split inbound feeds by tenant.
+    SendingFacility,
+    /// Route on the receiving facility (MSH-6), eg to fan outbound messages out to each site.
+    ReceivingFacility,
+}
+
+/// Looks up where a decoded frame should go, by its sending or receiving facility, for gateways serving several
+/// tenants over one listener.  Facilities are matched on their first component (the namespace ID), exactly.
+///
+/// Frames whose facility isn't in the table, or that don't have one at all, go to the default route if there is one.
+/// Example:
+/// ```
+/// use hl7_mllp_codec::{RouteBy, Router};
+/// use std::collections::HashMap;
+///
+/// let table = HashMap::from([("NORTH".to_string(), "north-queue"), ("SOUTH".to_string(), "south-queue")]);
+/// let router = Router::new(RouteBy::SendingFacility, table).with_default("triage-queue");
+///
+/// assert_eq!(router.route(b"MSH|^~\\&|LAB|NORTH||||||ORU^R01|1|P|2.3\r"), Some(&"north-queue"));
+/// assert_eq!(router.route(b"MSH|^~\\&|LAB|EAST||||||ORU^R01|2|P|2.3\r"), Some(&"triage-queue"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Router<D> {
+    by: RouteBy,
+    table: HashMap<String, D>,
+    default: Option<D>,
+}
+
+impl<D> Router<D> {
+    /// Creates a router looking destinations up in `table`, keyed by facility, with no default route.
+    pub fn new(by: RouteBy, table: HashMap<String, D>) -> Self {
+        Router {
+            by,
+            table,
+            default: None,
+        }
+    }
+
+    /// Sets the destination for frames that don't match any facility in the table.
+    pub fn with_default(mut self, destination: D) -> Self {
+        self.default = Some(destination);
+        self
+    }
+
+    /// Returns the destination for `frame`, or `None` if it doesn't match and there's no default route.
+    pub fn route(&self, frame: &[u8]) -> Option<&D> {
+        let facility = match self.by {
+            RouteBy::SendingFacility => sending_facility(frame),
+            RouteBy::ReceivingFacility => receiving_facility(frame),
+        };
+        facility
+            .and_then(|facility| self.table.get(facility))
+            .or(self.default.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn router(by: RouteBy) -> Router<u32> {
+        let table = HashMap::from([("NORTH".to_string(), 1), ("SOUTH".to_string(), 2)]);
+        Router::new(by, table)
+    }
+
+    #[test]
+    fn known_facility_routed() {
+        let frame = b"MSH|^~\\&|LAB|SOUTH^1.2.3^ISO|EMR|NORTH|||ORU^R01|1|P|2.3\r";
+        assert_eq!(router(RouteBy::SendingFacility).route(frame), Some(&2));
+        assert_eq!(router(RouteBy::ReceivingFacility).route(frame), Some(&1));
+    }
+
+    #[test]
+    fn unknown_facility_takes_default_route() {
+        let frame = b"MSH|^~\\&|LAB|EAST||||||ORU^R01|1|P|2.3\r";
+        assert_eq!(router(RouteBy::SendingFacility).route(frame), None);
+        let router = router(RouteBy::SendingFacility).with_default(0);
+        assert_eq!(router.route(frame), Some(&0));
+    }
+
+    #[test]
+    fn missing_facility_takes_default_route() {
+        let router = router(RouteBy::SendingFacility).with_default(0);
+        assert_eq!(
+            router.route(b"MSH|^~\\&|LAB|||||||ORU^R01|1|P|2.3\r"),
+            Some(&0)
+        );
+        assert_eq!(router.route(b"MSH|^~\\&|LAB\r"), Some(&0));
+        assert_eq!(router.route(b"not HL7 at all"), Some(&0));
+    }
+}
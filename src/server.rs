@@ -0,0 +1,624 @@
+use crate::ack::{classify, FrameKind};
+use crate::{FrameState, MllpCodec, MllpError};
+use bytes::BytesMut;
+use log::{debug, trace};
+use std::future::Future;
+#[cfg(feature = "listen")]
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::mpsc::UnboundedSender;
+#[cfg(feature = "listen")]
+use tokio::sync::Semaphore;
+use tokio::time::{timeout_at, Instant};
+use tokio_util::codec::{Decoder, Encoder, Framed, FramedParts};
+
+/// Options for [serve_mllp_with].
+///
+/// The timeouts run on tokio's clock, so tests can drive them deterministically from a paused runtime (see
+/// [tokio::time::pause] and [tokio::time::advance]) rather than waiting on the wall clock.
+#[derive(Debug, Clone, Default)]
+pub struct ServeOptions {
+    frame_timeout: Option<Duration>,
+    inactivity_timeout: Option<Duration>,
+    acks: Option<UnboundedSender<BytesMut>>,
+    max_connections: Option<(usize, ExcessConnections)>,
+}
+
+/// What a listener should do with connections beyond its [max_connections](ServeOptions::max_connections).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExcessConnections {
+    /// Close them as soon as they're accepted.
+    #[default]
+    Reject,
+    /// Stop accepting until a connection closes, leaving new connections waiting in the OS's listen backlog.
+    Queue,
+}
+
+impl ServeOptions {
+    /// Creates the default options, which never time out.
+    pub fn new() -> Self {
+        ServeOptions::default()
+    }
+
+    /// Once the first bytes of a frame have arrived, the whole frame must be received within `timeout` or the
+    /// connection is closed with [MllpError::FrameTimeout].  This drops stalled senders that open a frame and then
+    /// trickle bytes (or nothing at all).  Idle connections between frames are unaffected.
+    pub fn frame_timeout(mut self, timeout: Duration) -> Self {
+        self.frame_timeout = Some(timeout);
+        self
+    }
+
+    /// Once a frame has been started, if no further bytes arrive for `timeout` then the partial frame is abandoned and
+    /// the connection closed with [MllpError::FrameStalled].  Unlike [frame_timeout](ServeOptions::frame_timeout) this
+    /// only drops senders that have stalled mid-frame, a slow sender that keeps making progress can take as long as it
+    /// likes.  Idle connections between frames are unaffected.
+    pub fn inactivity_timeout(mut self, timeout: Duration) -> Self {
+        self.inactivity_timeout = Some(timeout);
+        self
+    }
+
+    /// For bidirectional gateways that send the ACKs for our messages down the same connection as their own messages.
+    /// Frames that [classify] as ACKs are sent to `acks` rather than the handler (and aren't themselves acknowledged).
+    /// As ACKs and messages can then arrive back-to-back the codec looks for the first footer in its buffer rather than
+    /// the last.  By default every frame goes to the handler.
+    pub fn route_acks_to(mut self, acks: UnboundedSender<BytesMut>) -> Self {
+        self.acks = Some(acks);
+        self
+    }
+
+    /// Limits a listener (see `listen_on_with`, with the `listen` feature) to serving `max` connections at once, protecting the host from
+    /// connection floods.  Connections beyond the limit are dealt with per `excess`.  Has no effect on a single
+    /// [serve_mllp_with] loop.  By default there's no limit.
+    pub fn max_connections(mut self, max: usize, excess: ExcessConnections) -> Self {
+        self.max_connections = Some((max, excess));
+        self
+    }
+}
+
+/// Runs a listener loop over `transport` with the default [ServeOptions], see [serve_mllp_with].
+pub async fn serve_mllp<T, F, Fut>(
+    transport: Framed<T, MllpCodec>,
+    handler: F,
+) -> Result<(), MllpError>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+    F: FnMut(BytesMut) -> Fut,
+    Fut: Future<Output = BytesMut>,
+{
+    serve_mllp_with(transport, &ServeOptions::default(), handler).await
+}
+
+/// Runs a listener loop over `transport`, handing each decoded message to `handler` and sending the ACK/NAK it returns
+/// back to the publisher.
+///
+/// Returns `Ok(())` once the remote end closes the connection cleanly between frames.
+///
+/// Requires the `server` crate feature.
+/// Example:
+/// ```no_run
+/// use bytes::BytesMut;
+/// use hl7_mllp_codec::{serve_mllp_with, MllpCodec, ServeOptions};
+/// use std::time::Duration;
+/// use tokio::net::TcpListener;
+/// use tokio_util::codec::Framed;
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+///     let listener = TcpListener::bind("127.0.0.1:8080").await?;
+///     let (stream, _) = listener.accept().await?;
+///     let options = ServeOptions::new().frame_timeout(Duration::from_secs(30));
+///
+///     serve_mllp_with(Framed::new(stream, MllpCodec::new()), &options, |_message| async {
+///         BytesMut::from("\x06") //<ACK> ascii char, simple ack
+///     })
+///     .await?;
+///     Ok(())
+/// }
+/// ```
+pub async fn serve_mllp_with<T, F, Fut>(
+    transport: Framed<T, MllpCodec>,
+    options: &ServeOptions,
+    mut handler: F,
+) -> Result<(), MllpError>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+    F: FnMut(BytesMut) -> Fut,
+    Fut: Future<Output = BytesMut>,
+{
+    // We drive the codec by hand rather than through the Stream impl so we can see when a frame is part way through
+    let FramedParts {
+        mut io,
+        mut codec,
+        read_buf: mut read_buffer,
+        write_buf,
+        ..
+    } = transport.into_parts();
+    codec.scan_forward |= options.acks.is_some(); // interleaved frames can arrive back-to-back
+
+    if !write_buf.is_empty() {
+        io.write_all(&write_buf).await?;
+    }
+
+    let mut deadline: Option<Instant> = None;
+    let mut write_buffer = BytesMut::new();
+
+    loop {
+        while let Some(message) = codec.decode(&mut read_buffer)? {
+            deadline = None;
+
+            if let Some(acks) = &options.acks {
+                if classify(&codec.config().segment_ids(&message)) == FrameKind::Ack {
+                    if acks.send(message).is_err() {
+                        debug!("MLLP: Ack receiver dropped, discarding interleaved ack");
+                    }
+                    continue;
+                }
+            }
+
+            let ack = handler(message).await;
+            codec.encode(ack, &mut write_buffer)?;
+            io.write_all(&write_buffer).await?;
+            io.flush().await?;
+            write_buffer.clear();
+        }
+
+        let mid_frame = !read_buffer.is_empty() || codec.buffered_len() > 0;
+        // junk (eg a LF after the last footer) isn't a frame, only a header starts the clock
+        let frame_started = codec.classify(&read_buffer) != FrameState::NoHeaderYet;
+        if !frame_started {
+            deadline = None;
+        } else if deadline.is_none() {
+            deadline = options
+                .frame_timeout
+                .map(|timeout| Instant::now() + timeout);
+        }
+
+        // the last progress was whatever's just been read, or decoded
+        let stalled_at = options
+            .inactivity_timeout
+            .filter(|_| frame_started)
+            .map(|timeout| Instant::now() + timeout);
+        let wait = match (deadline, stalled_at) {
+            (Some(deadline), Some(stalled_at)) => Some(deadline.min(stalled_at)),
+            (deadline, stalled_at) => deadline.or(stalled_at),
+        };
+
+        let read = match wait {
+            Some(wait) => match timeout_at(wait, io.read_buf(&mut read_buffer)).await {
+                Ok(read) => read?,
+                Err(_) if Some(wait) == stalled_at => {
+                    debug!("MLLP: No progress on a partial frame, abandoning it and closing connection");
+                    return Err(MllpError::FrameStalled);
+                }
+                Err(_) => {
+                    debug!("MLLP: Frame not completed before deadline, closing connection");
+                    return Err(MllpError::FrameTimeout);
+                }
+            },
+            None => io.read_buf(&mut read_buffer).await?,
+        };
+
+        if read == 0 {
+            trace!("MLLP: Connection closed by remote");
+            return if mid_frame {
+                Err(MllpError::ConnectionClosed)
+            } else {
+                Ok(())
+            };
+        }
+    }
+}
+
+/// Binds a listener to `addr` and serves every connection it accepts, see [listen_on].
+///
+/// Requires the `listen` crate feature.
+#[cfg(feature = "listen")]
+pub async fn listen<A, F, Fut>(addr: A, handler: F) -> Result<(), MllpError>
+where
+    A: tokio::net::ToSocketAddrs,
+    F: FnMut(BytesMut) -> Fut + Clone + Send + 'static,
+    Fut: Future<Output = BytesMut> + Send + 'static,
+{
+    listen_on(tokio::net::TcpListener::bind(addr).await?, handler).await
+}
+
+/// Accepts connections from `listener` with the default [ServeOptions], see [listen_on_with].
+///
+/// Requires the `listen` crate feature.
+/// Example:
+/// ```no_run
+/// use bytes::BytesMut;
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), hl7_mllp_codec::MllpError> {
+///     hl7_mllp_codec::listen("127.0.0.1:2575", |message: BytesMut| async move {
+///         println!("Received {:?}", message);
+///         BytesMut::from("\x06") //<ACK> ascii char, simple ack
+///     })
+///     .await
+/// }
+/// ```
+#[cfg(feature = "listen")]
+pub async fn listen_on<F, Fut>(
+    listener: tokio::net::TcpListener,
+    handler: F,
+) -> Result<(), MllpError>
+where
+    F: FnMut(BytesMut) -> Fut + Clone + Send + 'static,
+    Fut: Future<Output = BytesMut> + Send + 'static,
+{
+    listen_on_with(listener, ServeOptions::default(), handler).await
+}
+
+/// Accepts connections from `listener` until accepting fails, spawning a [serve_mllp_with] loop for each with its own
+/// copy of `handler` and `options`.  Connections that end in an error are logged and dropped without affecting the
+/// others.
+///
+/// Requires the `listen` crate feature.
+/// Example:
+/// ```no_run
+/// use bytes::BytesMut;
+/// use hl7_mllp_codec::{listen_on_with, ExcessConnections, ServeOptions};
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), hl7_mllp_codec::MllpError> {
+///     let listener = tokio::net::TcpListener::bind("127.0.0.1:2575").await?;
+///     let options = ServeOptions::new().max_connections(100, ExcessConnections::Reject);
+///     listen_on_with(listener, options, |_message: BytesMut| async move {
+///         BytesMut::from("\x06") //<ACK> ascii char, simple ack
+///     })
+///     .await
+/// }
+/// ```
+#[cfg(feature = "listen")]
+pub async fn listen_on_with<F, Fut>(
+    listener: tokio::net::TcpListener,
+    options: ServeOptions,
+    handler: F,
+) -> Result<(), MllpError>
+where
+    F: FnMut(BytesMut) -> Fut + Clone + Send + 'static,
+    Fut: Future<Output = BytesMut> + Send + 'static,
+{
+    let slots = options
+        .max_connections
+        .map(|(max, excess)| (Arc::new(Semaphore::new(max)), max, excess));
+
+    loop {
+        // a queueing listener doesn't accept until it has a slot for the connection
+        let queued = match &slots {
+            Some((slots, _, ExcessConnections::Queue)) => Some(
+                slots
+                    .clone()
+                    .acquire_owned()
+                    .await
+                    .expect("the semaphore is never closed"),
+            ),
+            _ => None,
+        };
+
+        let (stream, peer) = listener.accept().await?;
+        let slot = match &slots {
+            Some((slots, max, ExcessConnections::Reject)) => {
+                match slots.clone().try_acquire_owned() {
+                    Ok(slot) => Some(slot),
+                    Err(_) => {
+                        debug!(
+                            "MLLP: Rejecting connection from {}, already serving {} connections",
+                            peer, max
+                        );
+                        continue; // dropping the stream closes it
+                    }
+                }
+            }
+            _ => queued,
+        };
+        debug!("MLLP: Accepted connection from {}", peer);
+
+        let handler = handler.clone();
+        let options = options.clone();
+        tokio::spawn(async move {
+            let _slot = slot; // held until the connection's done with
+            match serve_mllp_with(Framed::new(stream, MllpCodec::new()), &options, handler).await {
+                Ok(()) => debug!("MLLP: Connection from {} closed", peer),
+                Err(e) => debug!("MLLP: Connection from {} failed: {}", peer, e),
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{FutureExt, SinkExt, StreamExt};
+
+    #[tokio::test]
+    async fn acks_each_message() {
+        let (local, remote) = tokio::io::duplex(1024);
+        let server = tokio::spawn(serve_mllp(
+            Framed::new(remote, MllpCodec::new()),
+            |_| async { BytesMut::from("\x06") },
+        ));
+
+        let mut publisher = Framed::new(local, MllpCodec::new());
+        for _ in 0..2 {
+            publisher.send(BytesMut::from("Test Data")).await.unwrap();
+            let ack = publisher.next().await.unwrap().unwrap();
+            assert_eq!(&ack[..], b"\x06");
+        }
+
+        drop(publisher);
+        server.await.unwrap().unwrap();
+    }
+
+    #[cfg(feature = "listen")]
+    #[tokio::test]
+    async fn listener_serves_connections() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(listen_on(listener, |message: BytesMut| async move {
+            crate::ack::build_ack(&message, crate::ack::AckCode::Accept).unwrap()
+        }));
+
+        for control_id in ["1", "2"] {
+            let stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+            let mut publisher = Framed::new(stream, MllpCodec::new());
+            let message = format!("MSH|^~\\&|LAB||||||ORU^R01|{}|P|2.3\r", control_id);
+            publisher
+                .send(BytesMut::from(message.as_str()))
+                .await
+                .unwrap();
+
+            let ack = publisher.next().await.unwrap().unwrap();
+            assert_eq!(crate::hl7::control_id(&ack), Some(control_id));
+        }
+
+        server.abort();
+    }
+
+    #[cfg(feature = "listen")]
+    async fn connect_and_send(
+        addr: std::net::SocketAddr,
+    ) -> Framed<tokio::net::TcpStream, MllpCodec> {
+        let stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let mut publisher = Framed::new(stream, MllpCodec::new());
+        publisher.send(BytesMut::from("Test Data")).await.unwrap();
+        publisher
+    }
+
+    #[cfg(feature = "listen")]
+    #[tokio::test]
+    async fn connections_beyond_limit_rejected() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let options = ServeOptions::new().max_connections(1, ExcessConnections::Reject);
+        let server = tokio::spawn(listen_on_with(listener, options, |_| async {
+            BytesMut::from("\x06")
+        }));
+
+        let mut first = connect_and_send(addr).await;
+        assert_eq!(&first.next().await.unwrap().unwrap()[..], b"\x06");
+
+        let stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let mut excess = Framed::new(stream, MllpCodec::new());
+        assert!(!matches!(excess.next().await, Some(Ok(_)))); // closed without being served
+
+        // once the first connection closes, there's room for another
+        drop(first);
+        let mut served = false;
+        for _ in 0..50 {
+            let mut next = connect_and_send(addr).await;
+            if let Some(Ok(ack)) = next.next().await {
+                assert_eq!(&ack[..], b"\x06");
+                served = true;
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(served, "No connection served after the first closed");
+
+        server.abort();
+    }
+
+    #[cfg(feature = "listen")]
+    #[tokio::test]
+    async fn connections_beyond_limit_queued() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let options = ServeOptions::new().max_connections(1, ExcessConnections::Queue);
+        let server = tokio::spawn(listen_on_with(listener, options, |_| async {
+            BytesMut::from("\x06")
+        }));
+
+        let mut first = connect_and_send(addr).await;
+        assert_eq!(&first.next().await.unwrap().unwrap()[..], b"\x06");
+
+        let mut queued = connect_and_send(addr).await;
+        let waiting = tokio::time::timeout(Duration::from_millis(100), queued.next()).await;
+        assert!(
+            waiting.is_err(),
+            "Queued connection served while at the limit"
+        );
+
+        drop(first);
+        assert_eq!(&queued.next().await.unwrap().unwrap()[..], b"\x06");
+
+        server.abort();
+    }
+
+    #[tokio::test]
+    async fn interleaved_acks_are_routed() {
+        let (local, remote) = tokio::io::duplex(1024);
+        let (acks, mut routed) = tokio::sync::mpsc::unbounded_channel();
+        let options = ServeOptions::new().route_acks_to(acks);
+        let server = tokio::spawn(async move {
+            serve_mllp_with(
+                Framed::new(remote, MllpCodec::new()),
+                &options,
+                |message| async move {
+                    assert_eq!(classify(&message), FrameKind::Message);
+                    BytesMut::from("\x06")
+                },
+            )
+            .await
+        });
+
+        let mut peer = Framed::new(local, MllpCodec::new());
+        let ack = "MSH|^~\\&|EMR||||||ACK^R01|1|P|2.3\rMSA|AA|1\r";
+        peer.send(BytesMut::from(ack)).await.unwrap();
+        peer.send(BytesMut::from("MSH|^~\\&|EMR||||||ADT^A01|X1|P|2.3\r"))
+            .await
+            .unwrap();
+
+        assert_eq!(&peer.next().await.unwrap().unwrap()[..], b"\x06");
+        assert_eq!(routed.recv().await.unwrap(), ack);
+
+        drop(peer);
+        server.await.unwrap().unwrap();
+    }
+
+    /// Lets spawned tasks run to their next wait, without the paused clock auto-advancing as it would if we slept
+    async fn settle() {
+        for _ in 0..10 {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn frame_timeout_fires_at_deadline() {
+        let (mut local, remote) = tokio::io::duplex(1024);
+        let options = ServeOptions::new().frame_timeout(Duration::from_secs(5));
+        let mut server = tokio::spawn(async move {
+            serve_mllp_with(Framed::new(remote, MllpCodec::new()), &options, |_| async {
+                BytesMut::from("\x06")
+            })
+            .await
+        });
+
+        local.write_all(b"\x0BPartial").await.unwrap();
+        settle().await;
+        tokio::time::advance(Duration::from_millis(4999)).await;
+        local.write_all(b" frame").await.unwrap(); // progress doesn't put the deadline back
+        settle().await;
+        assert!((&mut server).now_or_never().is_none());
+
+        tokio::time::advance(Duration::from_millis(1)).await;
+        match server.await.unwrap() {
+            Err(MllpError::FrameTimeout) => {}
+            other => panic!("Expected a frame timeout, got {:?}", other),
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn frame_timeout_ignores_trailing_bytes_between_frames() {
+        let (local, remote) = tokio::io::duplex(1024);
+        let options = ServeOptions::new().frame_timeout(Duration::from_secs(5));
+        let mut server = tokio::spawn(async move {
+            serve_mllp_with(Framed::new(remote, MllpCodec::new()), &options, |_| async {
+                BytesMut::from("\x06")
+            })
+            .await
+        });
+
+        let mut publisher = Framed::new(local, MllpCodec::new());
+        publisher
+            .get_mut()
+            .write_all(b"\x0BMSH|1\x1C\x0D\n")
+            .await
+            .unwrap();
+        assert_eq!(publisher.next().await.unwrap().unwrap(), "\x06");
+        publisher.get_mut().write_all(b"junk").await.unwrap();
+        settle().await;
+
+        tokio::time::advance(Duration::from_secs(60)).await;
+        settle().await;
+        assert!((&mut server).now_or_never().is_none()); // an idle connection, not a stalled frame
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn inactivity_timeout_fires_after_last_progress() {
+        let (mut local, remote) = tokio::io::duplex(1024);
+        let options = ServeOptions::new().inactivity_timeout(Duration::from_secs(5));
+        let mut server = tokio::spawn(async move {
+            serve_mllp_with(Framed::new(remote, MllpCodec::new()), &options, |_| async {
+                BytesMut::from("\x06")
+            })
+            .await
+        });
+
+        // bytes left after a complete frame (without a header) aren't a frame making no progress
+        let mut ack = [0; 3];
+        local.write_all(b"\x0BMSH|1\x1C\x0D\n").await.unwrap();
+        local.read_exact(&mut ack).await.unwrap();
+        tokio::time::advance(Duration::from_secs(60)).await;
+        settle().await;
+        assert!((&mut server).now_or_never().is_none());
+
+        local.write_all(b"\x0BPartial").await.unwrap();
+        settle().await;
+        tokio::time::advance(Duration::from_millis(4999)).await;
+        local.write_all(b" frame").await.unwrap();
+        settle().await;
+        tokio::time::advance(Duration::from_millis(4999)).await;
+        settle().await;
+        assert!((&mut server).now_or_never().is_none());
+
+        tokio::time::advance(Duration::from_millis(1)).await;
+        match server.await.unwrap() {
+            Err(MllpError::FrameStalled) => {}
+            other => panic!("Expected a stalled frame, got {:?}", other),
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn stalled_frame_hits_deadline() {
+        let (mut local, remote) = tokio::io::duplex(1024);
+        let options = ServeOptions::new().frame_timeout(Duration::from_secs(5));
+
+        local
+            .write_all(b"\x0BTest Data, but no footer")
+            .await
+            .unwrap();
+
+        let result = serve_mllp_with(Framed::new(remote, MllpCodec::new()), &options, |_| async {
+            panic!("Incomplete frame handed to handler")
+        })
+        .await;
+
+        match result {
+            Err(MllpError::FrameTimeout) => {}
+            other => panic!("Expected a frame timeout, got {:?}", other),
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn stalled_frame_hits_inactivity_timeout() {
+        let (mut local, remote) = tokio::io::duplex(1024);
+        let options = ServeOptions::new().inactivity_timeout(Duration::from_secs(5));
+        let server = tokio::spawn(async move {
+            serve_mllp_with(Framed::new(remote, MllpCodec::new()), &options, |_| async {
+                BytesMut::from("\x06")
+            })
+            .await
+        });
+
+        // a slow sender that keeps making progress is fine, however long the frame takes overall
+        local.write_all(b"\x0BSlow").await.unwrap();
+        for chunk in [&b" but"[..], b" steady", b"\x1C\x0D"] {
+            tokio::time::sleep(Duration::from_secs(4)).await;
+            local.write_all(chunk).await.unwrap();
+        }
+        let mut ack = [0u8; 4];
+        local.read_exact(&mut ack).await.unwrap();
+        assert_eq!(&ack, b"\x0B\x06\x1C\x0D");
+
+        // one that stops mid-frame isn't
+        local.write_all(b"\x0BStalled").await.unwrap();
+        tokio::time::sleep(Duration::from_secs(6)).await;
+        match server.await.unwrap() {
+            Err(MllpError::FrameStalled) => {}
+            other => panic!("Expected a stalled frame, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,30 @@
+/// A snapshot of the counters a [MllpCodec](crate::MllpCodec) accumulates over its lifetime, see [MllpCodec::stats](crate::MllpCodec::stats).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MllpStats {
+    /// The number of frames successfully decoded.
+    pub frames_decoded: u64,
+    /// The number of frames encoded.
+    pub frames_encoded: u64,
+    /// The number of bytes discarded while decoding, eg junk before a header or skipped oversized frames.
+    pub bytes_skipped: u64,
+    /// The number of calls to `decode` that returned an error.
+    pub decode_errors: u64,
+    /// The size of the largest frame content seen, in bytes, including frames skipped for being over the max frame
+    /// size (with [OversizeAction::Skip](crate::OversizeAction::Skip)).
+    pub max_frame_bytes: usize,
+}
+
+impl MllpStats {
+    pub(crate) fn record_decoded(&mut self, frame_len: usize) {
+        self.frames_decoded += 1;
+        self.record_frame_size(frame_len);
+    }
+
+    pub(crate) fn record_frame_size(&mut self, frame_len: usize) {
+        self.max_frame_bytes = self.max_frame_bytes.max(frame_len);
+    }
+
+    pub(crate) fn record_skipped(&mut self, bytes: usize) {
+        self.bytes_skipped += bytes as u64;
+    }
+}
@@ -0,0 +1,170 @@
+use crate::{MllpCodec, MllpError};
+use bytes::BytesMut;
+use std::time::{Duration, Instant};
+use tokio_util::codec::{Decoder, Encoder};
+
+/// A decoded frame, along with when it was decoded.
+#[derive(Debug, Clone)]
+pub struct TimestampedFrame {
+    /// The frame content, as returned by [MllpCodec].
+    pub data: BytesMut,
+    /// When the frame was decoded, which is as close to "when the footer arrived" as a codec can get.
+    pub received_at: Instant,
+    /// The time since the previous frame was decoded, or `None` for the first frame.  Steady senders show steady gaps,
+    /// bursty ones a run of near-zero gaps between long ones.
+    pub since_previous: Option<Duration>,
+}
+
+/// Wraps a [MllpCodec], stamping each decoded frame with the time it was decoded so latency analysis doesn't have
+/// to race to do so after the fact.  Encoding is unchanged.
+///
+/// Created with [MllpCodec::timestamped].
+/// Example:
+/// ```
+/// use bytes::BytesMut;
+/// use hl7_mllp_codec::MllpCodec;
+/// use tokio_util::codec::Decoder;
+///
+/// let mut codec = MllpCodec::new().timestamped();
+/// let frame = codec.decode(&mut BytesMut::from("\x0BHello\x1C\x0D")).unwrap().unwrap();
+/// println!("{:?} arrived at {:?}", frame.data, frame.received_at);
+/// ```
+pub struct TimestampedCodec {
+    inner: MllpCodec,
+    now: fn() -> Instant,
+    last_received: Option<Instant>,
+    last_gap: Option<Duration>,
+}
+
+impl MllpCodec {
+    /// Wraps this codec so decoded frames carry the time they were decoded, see [TimestampedCodec].
+    pub fn timestamped(self) -> TimestampedCodec {
+        self.timestamped_with_clock(Instant::now)
+    }
+
+    /// [timestamped](MllpCodec::timestamped), but reading the time from `now`, eg
+    /// `|| tokio::time::Instant::now().into_std()` so the stamps follow a paused tokio clock in tests.
+    pub fn timestamped_with_clock(self, now: fn() -> Instant) -> TimestampedCodec {
+        TimestampedCodec {
+            inner: self,
+            now,
+            last_received: None,
+            last_gap: None,
+        }
+    }
+}
+
+impl TimestampedCodec {
+    /// The gap between the last two decoded frames (see [TimestampedFrame::since_previous]), for dashboards that
+    /// sample the codec rather than watching every frame.  `None` until a second frame has been decoded.
+    pub fn last_gap(&self) -> Option<Duration> {
+        self.last_gap
+    }
+
+    /// Returns the wrapped codec.
+    pub fn into_inner(self) -> MllpCodec {
+        self.inner
+    }
+}
+
+impl Encoder<BytesMut> for TimestampedCodec {
+    type Error = MllpError;
+
+    fn encode(&mut self, event: BytesMut, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        self.inner.encode(event, dst)
+    }
+}
+
+impl Decoder for TimestampedCodec {
+    type Item = TimestampedFrame;
+    type Error = MllpError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        let data = match self.inner.decode(src)? {
+            Some(data) => data,
+            None => return Ok(None),
+        };
+
+        let received_at = (self.now)();
+        let since_previous = self
+            .last_received
+            .map(|last| received_at.saturating_duration_since(last));
+        self.last_received = Some(received_at);
+        self.last_gap = since_previous;
+        Ok(Some(TimestampedFrame {
+            data,
+            received_at,
+            since_previous,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sequential_frames_have_non_decreasing_timestamps() {
+        let mut codec = MllpCodec::new().timestamped();
+
+        let first = codec
+            .decode(&mut BytesMut::from("\x0BFirst\x1C\x0D"))
+            .unwrap()
+            .unwrap();
+        let second = codec
+            .decode(&mut BytesMut::from("\x0BSecond\x1C\x0D"))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(&first.data[..], b"First");
+        assert_eq!(&second.data[..], b"Second");
+        assert!(second.received_at >= first.received_at);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn reports_gap_since_previous_frame() {
+        let mut codec =
+            MllpCodec::new().timestamped_with_clock(|| tokio::time::Instant::now().into_std());
+
+        let first = codec
+            .decode(&mut BytesMut::from("\x0BFirst\x1C\x0D"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(first.since_previous, None);
+        assert_eq!(codec.last_gap(), None);
+
+        // a frame arriving in pieces is stamped when it completes
+        tokio::time::advance(Duration::from_millis(1500)).await;
+        assert!(codec
+            .decode(&mut BytesMut::from("\x0BSec"))
+            .unwrap()
+            .is_none());
+        tokio::time::advance(Duration::from_millis(500)).await;
+        let second = codec
+            .decode(&mut BytesMut::from("ond\x1C\x0D"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(second.since_previous, Some(Duration::from_secs(2)));
+        assert_eq!(
+            second.received_at - first.received_at,
+            Duration::from_secs(2)
+        );
+        assert_eq!(codec.last_gap(), Some(Duration::from_secs(2)));
+
+        let third = codec
+            .decode(&mut BytesMut::from("\x0BThird\x1C\x0D"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(third.since_previous, Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn encodes_as_plain_codec() {
+        let mut codec = MllpCodec::new().timestamped();
+        let mut dst = BytesMut::new();
+
+        codec.encode(BytesMut::from("abcd"), &mut dst).unwrap();
+
+        assert_eq!(&dst[..], b"\x0Babcd\x1C\x0D");
+    }
+}
@@ -0,0 +1,102 @@
+//! **Non-standard** UTF-16 content, as emitted by some Windows based systems, transcoded to UTF-8 once deframed.
+//!
+//! The framing bytes are still single bytes on the wire, so frames are found as usual and only their content is
+//! transcoded.  Content is taken to be UTF-16 if it starts with a byte order mark, or failing that with `MSH` encoded
+//! as UTF-16 (which can only be the case for UTF-16 content, as `M` isn't `0x00`).
+//!
+//! The character set declared in MSH-18 (eg `UNICODE UTF-16`) isn't consulted: in a UTF-16 frame MSH-18 is itself
+//! UTF-16, so can't be read until the frame's already been found to be UTF-16, and a frame declaring it without a BOM
+//! starts with a UTF-16 `MSH` anyway.  Content that starts with anything else (eg a UTF-16 batch header) isn't
+//! detected.
+
+/// The byte orders UTF-16 content can be in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ByteOrder {
+    Little,
+    Big,
+}
+
+/// Works out whether `content` is UTF-16, returning its byte order and the length of any BOM
+fn detect(content: &[u8]) -> Option<(ByteOrder, usize)> {
+    match content {
+        [0xFF, 0xFE, ..] => Some((ByteOrder::Little, 2)),
+        [0xFE, 0xFF, ..] => Some((ByteOrder::Big, 2)),
+        [b'M', 0, b'S', 0, b'H', 0, ..] => Some((ByteOrder::Little, 0)),
+        [0, b'M', 0, b'S', 0, b'H', ..] => Some((ByteOrder::Big, 0)),
+        _ => None,
+    }
+}
+
+/// Transcodes `content` to UTF-8 if it's UTF-16, returning `Ok(None)` if it isn't (so should be left be)
+pub(crate) fn to_utf8(content: &[u8]) -> Result<Option<String>, String> {
+    let (order, bom_len) = match detect(content) {
+        Some(detected) => detected,
+        None => return Ok(None),
+    };
+    let content = &content[bom_len..];
+    if content.len() % 2 == 1 {
+        return Err(format!(
+            "UTF-16 content has an odd length ({} bytes)",
+            content.len()
+        ));
+    }
+
+    let units = content.chunks_exact(2).map(|pair| match order {
+        ByteOrder::Little => u16::from_le_bytes([pair[0], pair[1]]),
+        ByteOrder::Big => u16::from_be_bytes([pair[0], pair[1]]),
+    });
+    std::char::decode_utf16(units)
+        .collect::<Result<String, _>>()
+        .map(Some)
+        .map_err(|e| format!("invalid UTF-16 content: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utf16le(s: &str) -> Vec<u8> {
+        s.encode_utf16().flat_map(u16::to_le_bytes).collect()
+    }
+
+    #[test]
+    fn transcodes_either_byte_order() {
+        let message = "MSH|^~\\&|LAB|Zürich||||||ADT^A01|1|P|2.5|||||UNICODE UTF-16\r";
+        assert_eq!(
+            to_utf8(&utf16le(message)).unwrap().as_deref(),
+            Some(message)
+        );
+
+        let mut with_bom = vec![0xFF, 0xFE];
+        with_bom.extend(utf16le(message));
+        assert_eq!(to_utf8(&with_bom).unwrap().as_deref(), Some(message));
+
+        let mut big_endian = vec![0xFE, 0xFF];
+        big_endian.extend(message.encode_utf16().flat_map(u16::to_be_bytes));
+        assert_eq!(to_utf8(&big_endian).unwrap().as_deref(), Some(message));
+    }
+
+    #[test]
+    fn msh_18_declared_frame_without_bom_transcoded() {
+        let message = "MSH|^~\\&|LAB||||||ADT^A01|1|P|2.5|||||UNICODE UTF-16\r";
+        let big_endian: Vec<u8> = message.encode_utf16().flat_map(u16::to_be_bytes).collect();
+        assert_eq!(to_utf8(&big_endian).unwrap().as_deref(), Some(message));
+    }
+
+    #[test]
+    fn leaves_other_content_be() {
+        assert_eq!(to_utf8(b"MSH|^~\\&|LAB\r").unwrap(), None);
+        assert_eq!(to_utf8(b"").unwrap(), None);
+    }
+
+    #[test]
+    fn rejects_broken_utf16() {
+        let mut odd = utf16le("MSH|");
+        odd.push(b'x');
+        assert!(to_utf8(&odd).is_err());
+
+        let mut unpaired = utf16le("MSH|");
+        unpaired.extend([0x00, 0xD8]); // a lone high surrogate
+        assert!(to_utf8(&unpaired).is_err());
+    }
+}
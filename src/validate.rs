@@ -0,0 +1,129 @@
+use crate::hl7;
+
+/// A pluggable check run against every decoded frame before it's handed back from `decode`.
+///
+/// The validator is given the frame content (framing removed) and the HL7 version the frame declares in MSH-12 (if
+/// any), so version-specific rules can branch.  Returning `Err` fails the decode with [MllpError::InvalidFrame](crate::MllpError::InvalidFrame).
+///
+/// Any `Fn(&[u8], Option<&str>) -> Result<(), String>` closure is a validator.
+/// Example:
+/// ```
+/// use hl7_mllp_codec::MllpCodec;
+///
+/// let mllp = MllpCodec::builder()
+///     .validator(|frame: &[u8], version: Option<&str>| match version {
+///         Some("2.3") if !frame.starts_with(b"MSH") => Err("v2.3 frames must start with MSH".to_owned()),
+///         _ => Ok(()),
+///     })
+///     .build().unwrap();
+/// ```
+pub trait FrameValidator: Send + Sync {
+    /// Checks a decoded frame, returning a description of the problem if it's unacceptable.
+    fn validate(&self, frame: &[u8], version: Option<&str>) -> Result<(), String>;
+}
+
+impl<F> FrameValidator for F
+where
+    F: Fn(&[u8], Option<&str>) -> Result<(), String> + Send + Sync,
+{
+    fn validate(&self, frame: &[u8], version: Option<&str>) -> Result<(), String> {
+        self(frame, version)
+    }
+}
+
+/// The built-in HL7 structure checks enabled by [validate_hl7](crate::MllpCodecBuilder::validate_hl7): the frame
+/// must start with an MSH segment, and no segment may be longer than `max_segment_len` (if set).
+pub(crate) fn validate_hl7(frame: &[u8], max_segment_len: Option<usize>) -> Result<(), String> {
+    if !frame.starts_with(b"MSH") || frame.len() < 8 {
+        return Err("frame doesn't start with an MSH segment".to_owned());
+    }
+
+    if let Some(max) = max_segment_len {
+        // CR is the HL7 segment separator, but LF turns up often enough to split on too.  The empty "segments" either
+        // side of a CRLF, or after the last segment's terminator, aren't segments.  `frame` is the content alone, so
+        // the footer's CR never gets this far.
+        let segments = frame
+            .split(|b| *b == b'\r' || *b == b'\n')
+            .filter(|segment| !segment.is_empty());
+        if let Some((i, segment)) = segments.enumerate().find(|(_, s)| s.len() > max) {
+            return Err(format!(
+                "segment {} ({}) is {} bytes, exceeding the max segment length of {}",
+                i + 1,
+                String::from_utf8_lossy(&segment[..segment.len().min(3)]),
+                segment.len(),
+                max
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// The MSH-2 check enabled by [strict_encoding_characters](crate::MllpCodecBuilder::strict_encoding_characters): the
+/// encoding characters must be exactly four distinct characters, none of them the field separator.
+pub(crate) fn validate_encoding_characters(frame: &[u8]) -> Result<(), String> {
+    let separator = hl7::msh_field(frame, 1).ok_or("frame doesn't start with an MSH segment")?;
+    let encoding = hl7::msh_field(frame, 2).ok_or("MSH has no encoding characters")?;
+
+    let characters: Vec<char> = encoding.chars().collect();
+    if characters.len() != 4 {
+        return Err(format!(
+            "MSH-2 {:?} isn't four encoding characters",
+            encoding
+        ));
+    }
+    let distinct = characters
+        .iter()
+        .enumerate()
+        .all(|(i, c)| !characters[i + 1..].contains(c) && !separator.contains(*c));
+    if !distinct {
+        return Err(format!(
+            "MSH-2 {:?} repeats a delimiter (the field separator is {:?})",
+            encoding, separator
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encoding_characters_must_be_four_distinct() {
+        assert!(validate_encoding_characters(b"MSH|^~\\&|A\r").is_ok());
+        assert!(validate_encoding_characters(b"MSH#*~\\@#A\r").is_ok());
+        assert!(validate_encoding_characters(b"MSH|^~\\|A\r").is_err()); // three
+        assert!(validate_encoding_characters(b"MSH|^~\\&#|A\r").is_err()); // five
+        assert!(validate_encoding_characters(b"MSH|^^\\&|A\r").is_err());
+        assert!(validate_encoding_characters(b"MSH#^~\\##A\r").is_err()); // the field separator again
+        assert!(validate_encoding_characters(b"MSH||A\r").is_err());
+    }
+
+    #[test]
+    fn requires_msh_first() {
+        assert!(validate_hl7(b"MSH|^~\\&|A\rPID|1\r", None).is_ok());
+        assert!(validate_hl7(b"PID|1\rMSH|^~\\&|A\r", None).is_err());
+        assert!(validate_hl7(b"\x06", None).is_err());
+    }
+
+    #[test]
+    fn segment_at_limit_allowed() {
+        let frame = b"MSH|^~\\&|A\rOBX|1|ED|0123456789\r";
+        assert!(validate_hl7(frame, Some(19)).is_ok());
+    }
+
+    #[test]
+    fn over_long_segment_rejected() {
+        let frame = b"MSH|^~\\&|A\rOBX|1|ED|0123456789\r";
+        let reason = validate_hl7(frame, Some(18)).unwrap_err();
+        assert!(reason.contains("segment 2 (OBX) is 19 bytes"), "{}", reason);
+    }
+
+    #[test]
+    fn crlf_terminators_not_counted_as_segments() {
+        let frame = b"MSH|^~\\&|A\r\nPID|1\r\nOBX|1|ED|0123456789\r\n";
+        let reason = validate_hl7(frame, Some(18)).unwrap_err();
+        assert!(reason.contains("segment 3 (OBX)"), "{}", reason);
+    }
+}
@@ -0,0 +1,80 @@
+use crate::MllpCodec;
+use bytes::BytesMut;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::AsyncWrite;
+
+/// Wraps `payload` in standard MLLP framing and writes it to `writer`, flushing once the whole frame is written.  For
+/// sending the odd frame over a raw [AsyncWrite] without building a `Framed` (see [MllpCodec::frame]) around it.
+///
+/// If the returned future is dropped part way through, some of the frame may already have been written.
+/// Example:
+/// ```
+/// use hl7_mllp_codec::write_mllp_frame;
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() -> std::io::Result<()> {
+/// let mut sent = Vec::new();
+/// write_mllp_frame(&mut sent, b"Hello").await?;
+/// assert_eq!(sent, b"\x0BHello\x1C\x0D");
+/// # Ok(())
+/// # }
+/// ```
+pub async fn write_mllp_frame<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    payload: &[u8],
+) -> io::Result<()> {
+    let mut codec = MllpCodec::new();
+    let mut frame = BytesMut::with_capacity(codec.framed_len(payload.len()));
+    codec.reframe(payload, &mut frame)?;
+
+    let mut unwritten = &frame[..];
+    while !unwritten.is_empty() {
+        let written = poll_fn(|cx| Pin::new(&mut *writer).poll_write(cx, unwritten)).await?;
+        if written == 0 {
+            return Err(io::ErrorKind::WriteZero.into());
+        }
+        unwritten = &unwritten[written..];
+    }
+    poll_fn(|cx| Pin::new(&mut *writer).poll_flush(cx)).await
+}
+
+/// A future polling `f`, as `std::future::poll_fn` (which needs Rust 1.64) does
+fn poll_fn<T, F: FnMut(&mut Context<'_>) -> Poll<T> + Unpin>(f: F) -> impl Future<Output = T> {
+    struct PollFn<F>(F);
+
+    impl<T, F: FnMut(&mut Context<'_>) -> Poll<T> + Unpin> Future for PollFn<F> {
+        type Output = T;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+            (self.0)(cx)
+        }
+    }
+
+    PollFn(f)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+    use tokio_util::codec::Decoder;
+
+    #[tokio::test]
+    async fn written_frame_decodes() {
+        let (mut client, mut server) = tokio::io::duplex(4); // small enough to force partial writes
+        let send =
+            tokio::spawn(
+                async move { write_mllp_frame(&mut client, b"MSH|^~\\&|ZIS\rEVN|A01").await },
+            );
+
+        let mut received = BytesMut::new();
+        while server.read_buf(&mut received).await.unwrap() > 0 {}
+        send.await.unwrap().unwrap();
+
+        let frame = MllpCodec::new().decode(&mut received).unwrap().unwrap();
+        assert_eq!(frame, "MSH|^~\\&|ZIS\rEVN|A01");
+    }
+}
@@ -0,0 +1,86 @@
+#![cfg(feature = "replay")]
+
+use bytes::BytesMut;
+use hl7_mllp_codec::ack::{build_ack, AckCode};
+use hl7_mllp_codec::{replay_file, MllpCodec};
+use std::path::PathBuf;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio_util::codec::Encoder;
+
+const CAPTURE: &[u8] = b"\x0BMSH|^~\\&|ONE||||||ADT^A01|1|P|2.3\x1C\x0D\x0BMSH|^~\\&|TWO||||||ADT^A01|2|P|2.3\x1C\x0D\x0BMSH|^~\\&|THREE||||||ADT^A01|3|P|2.3\x1C\x0D";
+
+fn capture_file(name: &str) -> PathBuf {
+    let path =
+        std::env::temp_dir().join(format!("mllp-replay-{}-{}.mllp", name, std::process::id()));
+    std::fs::write(&path, CAPTURE).unwrap();
+    path
+}
+
+/// Accepts a single connection, ACKing each frame, and returns the control IDs received once the sender hangs up.
+/// Frames are decoded forwards (as a FrameReader does), so pipelined frames arriving in one read are each seen.
+async fn listen() -> (std::net::SocketAddr, tokio::task::JoinHandle<Vec<String>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let received = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut codec = MllpCodec::new();
+        let mut buf = BytesMut::new();
+        let mut received = Vec::new();
+        while stream.read_buf(&mut buf).await.unwrap() > 0 {
+            let mut acks = BytesMut::new();
+            for frame in codec.decode_all(&mut buf).unwrap() {
+                received.push(hl7_mllp_codec::hl7::control_id(&frame).unwrap().to_owned());
+                let ack = build_ack(&frame, AckCode::Accept).unwrap();
+                codec.encode(ack, &mut acks).unwrap();
+            }
+            stream.write_all(&acks).await.unwrap();
+        }
+        received
+    });
+    (addr, received)
+}
+
+#[tokio::test]
+async fn replays_waiting_for_acks() {
+    let path = capture_file("acked");
+    let (addr, received) = listen().await;
+
+    assert_eq!(replay_file(&path, addr, true).await.unwrap(), 3);
+    assert_eq!(received.await.unwrap(), vec!["1", "2", "3"]);
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn replays_without_waiting_for_acks() {
+    let path = capture_file("firehose");
+    let (addr, received) = listen().await;
+
+    assert_eq!(replay_file(&path, addr, false).await.unwrap(), 3);
+    assert_eq!(received.await.unwrap(), vec!["1", "2", "3"]);
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn firehose_replay_drains_acks() {
+    // enough frames that their unread ACKs would fill the socket buffers, blocking the listener
+    const FRAMES: usize = 50_000;
+    let mut capture = Vec::new();
+    for id in 0..FRAMES {
+        capture.extend_from_slice(
+            format!("\x0BMSH|^~\\&|BULK||||||ADT^A01|{}|P|2.3\x1C\x0D", id).as_bytes(),
+        );
+    }
+    let path = std::env::temp_dir().join(format!("mllp-replay-bulk-{}.mllp", std::process::id()));
+    std::fs::write(&path, capture).unwrap();
+    let (addr, received) = listen().await;
+
+    let replay = replay_file(&path, addr, false);
+    let sent = tokio::time::timeout(std::time::Duration::from_secs(30), replay).await;
+    assert_eq!(sent.expect("replay deadlocked").unwrap(), FRAMES);
+    assert_eq!(received.await.unwrap().len(), FRAMES);
+
+    std::fs::remove_file(path).unwrap();
+}
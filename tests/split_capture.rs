@@ -0,0 +1,48 @@
+#![cfg(feature = "capture")]
+
+use hl7_mllp_codec::split_capture;
+use std::path::PathBuf;
+
+fn out_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mllp-split-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+const CAPTURE: &[u8] =
+    b"\x0BMSH|^~\\&|ONE\x1C\x0D\x0BMSH|^~\\&|TWO\x1C\x0D\x0BMSH|^~\\&|THREE\x1C\x0D";
+
+#[test]
+fn split_strips_framing() {
+    let dir = out_dir("stripped");
+    assert_eq!(split_capture(CAPTURE, &dir, true).unwrap(), 3);
+
+    assert_eq!(
+        std::fs::read(dir.join("00001.hl7")).unwrap(),
+        b"MSH|^~\\&|ONE"
+    );
+    assert_eq!(
+        std::fs::read(dir.join("00002.hl7")).unwrap(),
+        b"MSH|^~\\&|TWO"
+    );
+    assert_eq!(
+        std::fs::read(dir.join("00003.hl7")).unwrap(),
+        b"MSH|^~\\&|THREE"
+    );
+    assert!(!dir.join("00004.hl7").exists());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn split_keeps_framing() {
+    let dir = out_dir("framed");
+    assert_eq!(split_capture(CAPTURE, &dir, false).unwrap(), 3);
+
+    assert_eq!(
+        std::fs::read(dir.join("00002.hl7")).unwrap(),
+        b"\x0BMSH|^~\\&|TWO\x1C\x0D"
+    );
+
+    std::fs::remove_dir_all(dir).unwrap();
+}